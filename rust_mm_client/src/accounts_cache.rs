@@ -1,4 +1,4 @@
-use log::info;
+use log::debug;
 use solana_account_decoder::UiAccount;
 
 use crate::MarketMakerError;
@@ -41,11 +41,12 @@ impl AccountsCache {
     }
 
     pub fn insert(&self, key: Pubkey, data: AccountState) -> Result<(), MarketMakerError> {
+        let data_slot = data.slot;
         self.map.insert(key, data);
 
         match self.sender.send(key) {
             Ok(_) => {
-                info!("[CACHE] Updated account with key: {}", key);
+                debug!(slot = data_slot; "[CACHE] Updated account with key: {}", key);
                 Ok(())
             }
             Err(_) => {
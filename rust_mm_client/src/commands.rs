@@ -0,0 +1,580 @@
+use {
+    crate::{
+        _fetch_cypher_user, _fetch_open_orders, _get_or_init_open_orders, _init_cypher_user,
+        config::OwnerConfig,
+        deposit_quote_token,
+        error::{ConfigLoadError, KeyError, OrderError, TxError},
+        fast_tx_builder::FastTxnBuilder,
+        market_maker::{
+            export_audit_log, get_cancel_order_ix, get_open_orders, get_serum_market,
+            get_settle_funds_ix, AuditLogConfig, CapitalManager, CapitalManagerConfig,
+        },
+        request_airdrop,
+        signer::{load_signer, TxSigner},
+        utils::{
+            derive_quote_token_address, get_close_open_orders_ix, get_set_delegate_ix,
+            get_token_account, unix_timestamp,
+        },
+        watchdog::{read_heartbeat, WatchdogConfig},
+        withdraw_quote_token, MarketMakerError,
+    },
+    chrono::NaiveDate,
+    cypher::{constants::QUOTE_TOKEN_IDX, CypherGroup},
+    jet_proto_math::Number,
+    log::{info, warn},
+    serum_dex::instruction::CancelOrderInstructionV2,
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature},
+    std::{
+        convert::identity,
+        fs::File,
+        io::{self, Write},
+        path::Path,
+        sync::Arc,
+        time::Duration,
+    },
+};
+
+// cancel instructions carry a lot of accounts, this keeps the transactions below the size limit
+const MAX_IXS_PER_TX: usize = 4;
+
+/// Everything the account administration commands need, resolved from the configs once.
+pub struct CommandContext {
+    pub rpc_client: Arc<RpcClient>,
+    /// the trading key, which is also the owner unless `owner_config` is set
+    pub signer: Arc<dyn TxSigner>,
+    pub owner_pubkey: Pubkey,
+    pub owner_config: Option<OwnerConfig>,
+    /// whether the cluster has a quote token faucet
+    pub airdrop: bool,
+    pub cypher_group_pubkey: Pubkey,
+    pub cypher_group: Box<CypherGroup>,
+    pub cypher_user_pubkey: Pubkey,
+    pub market_pubkey: Pubkey,
+    pub market_index: usize,
+    pub base_decimals: u8,
+    pub symbol: String,
+    pub open_orders_pubkey: Pubkey,
+    /// only needed to run the market maker
+    pub pubsub_url: String,
+}
+
+impl CommandContext {
+    fn quote_multiplier(&self) -> f64 {
+        let quote_token = self.cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        10_u64.pow(quote_token.decimals().into()) as f64
+    }
+
+    /// converts an amount of quote in ui units to native units
+    fn to_native_quote(&self, amount: f64) -> Result<u64, MarketMakerError> {
+        if !amount.is_finite() || amount <= 0.0 {
+            warn!("The amount must be a positive number: {}", amount);
+            return Err(MarketMakerError::InvalidAmount);
+        }
+        Ok((amount * self.quote_multiplier()) as u64)
+    }
+
+    /// the key which owns the cypher account, only loaded by the commands which need it
+    /// since it is kept offline when trading with a delegate
+    async fn owner(&self) -> Result<Arc<dyn TxSigner>, MarketMakerError> {
+        let owner_config = match &self.owner_config {
+            Some(c) => c,
+            None => return Ok(Arc::clone(&self.signer)),
+        };
+        let signer_config = match &owner_config.signer_config {
+            Some(c) => c,
+            None => {
+                warn!(
+                    "This command needs the owner key {}, set `ownerConfig.signerConfig` to load it.",
+                    self.owner_pubkey
+                );
+                return Err(KeyError::OwnerUnavailable.into());
+            }
+        };
+
+        let owner = load_signer(signer_config, &owner_config.wallet).await?;
+        if owner.pubkey() != self.owner_pubkey {
+            warn!(
+                "Loaded owner key {} but `ownerConfig.pubkey` is {}.",
+                owner.pubkey(),
+                self.owner_pubkey
+            );
+            return Err(KeyError::OwnerMismatch.into());
+        }
+        Ok(owner)
+    }
+
+    async fn send_ixs(
+        &self,
+        ixs: Vec<Instruction>,
+        signer: &dyn TxSigner,
+    ) -> Result<Vec<Signature>, TxError> {
+        let mut signatures = Vec::new();
+        for chunk in ixs.chunks(MAX_IXS_PER_TX) {
+            let mut builder = FastTxnBuilder::new();
+            for ix in chunk {
+                builder.add(ix.clone());
+            }
+            let hash = self.rpc_client.get_latest_blockhash().await?;
+            let tx = builder.build(hash, signer, None).await?;
+            let signature = self
+                .rpc_client
+                .send_and_confirm_transaction_with_spinner(&tx)
+                .await?;
+            signatures.push(signature);
+        }
+        Ok(signatures)
+    }
+}
+
+/// creates the cypher user account and the open orders account for the configured market
+/// if they do not exist yet, without depositing any funds, and sets the trading key as the
+/// delegate if it is not the owner
+pub async fn init_account(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    let owner = ctx.owner().await?;
+    match _fetch_cypher_user(&ctx.cypher_user_pubkey, Arc::clone(&ctx.rpc_client)).await {
+        Ok(_) => info!(
+            "Cypher user account {} already exists.",
+            ctx.cypher_user_pubkey
+        ),
+        Err(_) => {
+            info!(
+                "Creating cypher user account with key: {}",
+                ctx.cypher_user_pubkey
+            );
+            _init_cypher_user(
+                &ctx.cypher_group_pubkey,
+                owner.as_ref(),
+                Arc::clone(&ctx.rpc_client),
+            )
+            .await?;
+        }
+    }
+
+    _get_or_init_open_orders(
+        owner.as_ref(),
+        &ctx.cypher_group_pubkey,
+        &ctx.cypher_user_pubkey,
+        &ctx.market_pubkey,
+        &ctx.open_orders_pubkey,
+        Arc::clone(&ctx.rpc_client),
+    )
+    .await?;
+
+    if ctx.owner_config.is_some() {
+        send_set_delegate(ctx, owner.as_ref(), &ctx.signer.pubkey()).await?;
+    }
+
+    Ok(())
+}
+
+/// lets the trading key trade for the owner's cypher account, or removes any delegate with `revoke`,
+/// e.g. after the trading host has been compromised
+pub async fn set_delegate(ctx: &CommandContext, revoke: bool) -> Result<(), MarketMakerError> {
+    if ctx.owner_config.is_none() {
+        warn!("There is no `ownerConfig` in the config, the trading key already owns the cypher account.");
+        return Err(ConfigLoadError::MissingSection("ownerConfig").into());
+    }
+
+    let owner = ctx.owner().await?;
+    let delegate = if revoke {
+        Pubkey::default()
+    } else {
+        ctx.signer.pubkey()
+    };
+    send_set_delegate(ctx, owner.as_ref(), &delegate).await
+}
+
+async fn send_set_delegate(
+    ctx: &CommandContext,
+    owner: &dyn TxSigner,
+    delegate: &Pubkey,
+) -> Result<(), MarketMakerError> {
+    let ix = get_set_delegate_ix(
+        &ctx.cypher_group_pubkey,
+        &ctx.cypher_user_pubkey,
+        &owner.pubkey(),
+        delegate,
+    );
+
+    match ctx.send_ixs(vec![ix], owner).await {
+        Ok(signatures) => {
+            for s in signatures {
+                info!(
+                    "Successfully set the delegate of the cypher account to {}. Transaction signature: {}",
+                    delegate, s
+                );
+            }
+            Ok(())
+        }
+        Err(e) => {
+            warn!("There was an error setting the delegate: {}", e);
+            Err(MarketMakerError::ErrorSettingDelegate(e))
+        }
+    }
+}
+
+/// deposits the given amount of quote, in ui units, from the wallet into the cypher account
+pub async fn deposit(ctx: &CommandContext, amount: f64) -> Result<(), MarketMakerError> {
+    let native_amount = ctx.to_native_quote(amount)?;
+    info!("Depositing quote token (native): {}.", native_amount);
+
+    let owner = ctx.owner().await?;
+    deposit_quote_token(
+        owner.as_ref(),
+        &ctx.cypher_user_pubkey,
+        &ctx.cypher_group,
+        Arc::clone(&ctx.rpc_client),
+        Number::from(native_amount),
+    )
+    .await
+}
+
+/// withdraws the given amount of quote, in ui units, from the cypher account into the wallet
+pub async fn withdraw(ctx: &CommandContext, amount: f64) -> Result<(), MarketMakerError> {
+    let native_amount = ctx.to_native_quote(amount)?;
+    info!("Withdrawing quote token (native): {}.", native_amount);
+
+    let owner = ctx.owner().await?;
+    withdraw_quote_token(
+        owner.as_ref(),
+        &ctx.cypher_user_pubkey,
+        &ctx.cypher_group,
+        Arc::clone(&ctx.rpc_client),
+        native_amount,
+    )
+    .await
+}
+
+pub async fn airdrop(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    if !ctx.airdrop {
+        warn!("The cluster does not have a faucet, set `airdrop` on the cluster to enable it.");
+        return Err(MarketMakerError::AirdropUnavailable);
+    }
+    let owner = ctx.owner().await?;
+    request_airdrop(owner.as_ref(), Arc::clone(&ctx.rpc_client)).await
+}
+
+/// moves quote between the wallet and the cypher account once, according to the capital manager config
+pub async fn rebalance(
+    ctx: &CommandContext,
+    config: Option<&CapitalManagerConfig>,
+    dry_run: bool,
+) -> Result<(), MarketMakerError> {
+    let config = match config {
+        Some(c) => c,
+        None => {
+            warn!("There is no `capitalManagerConfig` in the config, nothing to rebalance.");
+            return Err(ConfigLoadError::MissingSection("capitalManagerConfig").into());
+        }
+    };
+
+    let capital_manager = Arc::new(CapitalManager::new_standalone(
+        config.clone(),
+        Arc::clone(&ctx.rpc_client),
+        ctx.owner().await?,
+        ctx.cypher_group_pubkey,
+        ctx.cypher_user_pubkey,
+    ));
+    capital_manager.rebalance(dry_run).await?;
+    Ok(())
+}
+
+/// writes the audit log rows from `from` to `to`, both included, to `output` or to stdout,
+/// runs without the logger so the messages go to stderr
+pub fn export_audit(
+    config: Option<&AuditLogConfig>,
+    from: NaiveDate,
+    to: NaiveDate,
+    output: Option<&str>,
+) -> Result<(), MarketMakerError> {
+    let config = match config {
+        Some(c) => c,
+        None => {
+            eprintln!(
+                "There is no `auditLogConfig` in the config, there is no audit log to export."
+            );
+            return Err(ConfigLoadError::MissingSection("auditLogConfig").into());
+        }
+    };
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path).map_err(MarketMakerError::AuditLogError)?),
+        None => Box::new(io::stdout()),
+    };
+    let rows = export_audit_log(Path::new(&config.directory), from, to, writer.as_mut())
+        .and_then(|rows| writer.flush().map(|_| rows))
+        .map_err(MarketMakerError::AuditLogError)?;
+
+    eprintln!("Exported {} audit log rows from {} to {}.", rows, from, to);
+    Ok(())
+}
+
+/// cancels every order resting in the open orders account of the configured market
+pub async fn cancel_all(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    let open_orders =
+        _fetch_open_orders(&ctx.open_orders_pubkey, Arc::clone(&ctx.rpc_client)).await?;
+    let orders = get_open_orders(&open_orders).await;
+    if orders.is_empty() {
+        info!("There are no open orders to cancel.");
+        return Ok(());
+    }
+
+    let market_state = get_serum_market(Arc::clone(&ctx.rpc_client), ctx.market_pubkey)
+        .await
+        .map_err(|_| MarketMakerError::ErrorFetchingDexMarket)?;
+    let cypher_market = ctx.cypher_group.get_cypher_market(ctx.market_index).unwrap();
+    let cypher_token = ctx.cypher_group.get_cypher_token(ctx.market_index).unwrap();
+
+    let ixs = orders
+        .iter()
+        .map(|order| {
+            info!(
+                client_order_id = order.client_order_id;
+                "Cancelling order with id {}",
+                order.order_id
+            );
+            get_cancel_order_ix(
+                &ctx.cypher_group,
+                cypher_market,
+                cypher_token,
+                &market_state,
+                &ctx.open_orders_pubkey,
+                &ctx.cypher_user_pubkey,
+                ctx.signer.as_ref(),
+                CancelOrderInstructionV2 {
+                    order_id: order.order_id,
+                    side: order.side,
+                },
+            )
+        })
+        .collect();
+
+    match ctx.send_ixs(ixs, ctx.signer.as_ref()).await {
+        Ok(signatures) => {
+            for s in signatures {
+                info!("Successfully cancelled orders. Transaction signature: {}", s);
+            }
+            info!("Cancelled {} orders.", orders.len());
+            Ok(())
+        }
+        Err(e) => {
+            warn!("There was an error cancelling orders: {}", e);
+            Err(OrderError::Submit(e).into())
+        }
+    }
+}
+
+/// watches the heartbeat of a market maker running in another process and cancels every order
+/// once it goes stale, until the heartbeat is fresh again, runs until the process is killed
+pub async fn watchdog(
+    ctx: &CommandContext,
+    config: Option<&WatchdogConfig>,
+) -> Result<(), MarketMakerError> {
+    let config = match config {
+        Some(c) => c,
+        None => {
+            warn!("There is no `watchdogConfig` in the config, there is no heartbeat to watch.");
+            return Err(ConfigLoadError::MissingSection("watchdogConfig").into());
+        }
+    };
+    let heartbeat_file = Path::new(&config.heartbeat_file);
+    let poll_interval = Duration::from_millis(config.poll_interval_ms);
+
+    info!(
+        "Watching the heartbeat at {}, the orders are cancelled once it is older than {} seconds.",
+        config.heartbeat_file, config.timeout_secs
+    );
+
+    // only armed once a fresh heartbeat is seen, so starting the watchdog before the market maker
+    // or after it has already cancelled its orders does nothing
+    let mut armed = false;
+    loop {
+        let age = read_heartbeat(heartbeat_file).map(|ts| unix_timestamp() - ts);
+        match age {
+            Some(age) if age <= config.timeout_secs as i64 => {
+                if !armed {
+                    info!("Received a heartbeat, the watchdog is armed.");
+                    armed = true;
+                }
+            }
+            _ if armed => {
+                match age {
+                    Some(age) => warn!("No heartbeat for {} seconds, cancelling every order.", age),
+                    None => warn!("The heartbeat cannot be read, cancelling every order."),
+                }
+                let open_orders =
+                    _fetch_open_orders(&ctx.open_orders_pubkey, Arc::clone(&ctx.rpc_client)).await;
+                match open_orders {
+                    Ok(oo) if get_open_orders(&oo).await.is_empty() => {
+                        info!("There are no orders left, waiting for the next heartbeat.");
+                        armed = false;
+                    }
+                    Ok(_) => {
+                        // retried on the next poll if it failed
+                        let _ = cancel_all(ctx).await;
+                    }
+                    Err(e) => warn!("Failed to fetch the open orders: {}", e),
+                }
+            }
+            _ => (),
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// settles the free funds of the open orders account of the configured market
+pub async fn settle(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    let market_state = get_serum_market(Arc::clone(&ctx.rpc_client), ctx.market_pubkey)
+        .await
+        .map_err(|_| MarketMakerError::ErrorFetchingDexMarket)?;
+    let cypher_market = ctx.cypher_group.get_cypher_market(ctx.market_index).unwrap();
+    let cypher_token = ctx.cypher_group.get_cypher_token(ctx.market_index).unwrap();
+
+    let ix = get_settle_funds_ix(
+        &ctx.cypher_group,
+        cypher_market,
+        cypher_token,
+        &market_state,
+        &ctx.open_orders_pubkey,
+        &ctx.cypher_user_pubkey,
+        ctx.signer.as_ref(),
+    );
+
+    match ctx.send_ixs(vec![ix], ctx.signer.as_ref()).await {
+        Ok(signatures) => {
+            for s in signatures {
+                info!("Successfully settled funds. Transaction signature: {}", s);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            warn!("There was an error settling funds: {}", e);
+            Err(OrderError::Settle(e).into())
+        }
+    }
+}
+
+/// closes the open orders account of the configured market and reclaims its rent,
+/// the account must not have resting orders or unsettled funds
+pub async fn close_open_orders(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    let open_orders =
+        _fetch_open_orders(&ctx.open_orders_pubkey, Arc::clone(&ctx.rpc_client)).await?;
+    let orders = get_open_orders(&open_orders).await;
+    if !orders.is_empty() {
+        warn!(
+            "The open orders account still has {} resting orders, run `cancel-all` first.",
+            orders.len()
+        );
+        return Err(MarketMakerError::OpenOrdersNotEmpty);
+    }
+    if identity(open_orders.native_coin_total) != 0 || identity(open_orders.native_pc_total) != 0 {
+        warn!("The open orders account still has unsettled funds, run `settle` first.");
+        return Err(MarketMakerError::OpenOrdersNotEmpty);
+    }
+
+    let owner = ctx.owner().await?;
+    let ix = get_close_open_orders_ix(
+        &ctx.cypher_group_pubkey,
+        &ctx.cypher_user_pubkey,
+        &ctx.market_pubkey,
+        &ctx.open_orders_pubkey,
+        &owner.pubkey(),
+    );
+
+    match ctx.send_ixs(vec![ix], owner.as_ref()).await {
+        Ok(signatures) => {
+            for s in signatures {
+                info!(
+                    "Successfully closed open orders account. Transaction signature: {}",
+                    s
+                );
+            }
+            Ok(())
+        }
+        Err(e) => {
+            warn!("There was an error closing the open orders account: {}", e);
+            Err(MarketMakerError::ErrorClosingOpenOrders(e))
+        }
+    }
+}
+
+/// prints the balances, the position in the configured market, the open orders and the margin
+pub async fn status(ctx: &CommandContext) -> Result<(), MarketMakerError> {
+    let cypher_user =
+        _fetch_cypher_user(&ctx.cypher_user_pubkey, Arc::clone(&ctx.rpc_client)).await?;
+    let quote_token = ctx.cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+    let quote_multiplier = ctx.quote_multiplier();
+    let base_multiplier = 10_u64.pow(ctx.base_decimals.into()) as f64;
+
+    println!("Wallet: {}", ctx.owner_pubkey);
+    if ctx.owner_config.is_some() {
+        println!("  Trading as delegate: {}", ctx.signer.pubkey());
+    }
+    let wallet_ata = derive_quote_token_address(ctx.owner_pubkey);
+    match get_token_account(Arc::clone(&ctx.rpc_client), &wallet_ata).await {
+        Ok(ta) => println!("  Quote balance: {}", ta.ui_amount_string),
+        Err(_) => println!("  Quote balance: no quote token account"),
+    }
+
+    println!("Cypher account: {}", ctx.cypher_user_pubkey);
+    match cypher_user.get_position(QUOTE_TOKEN_IDX) {
+        Some(position) => println!(
+            "  Quote deposits: {} | Quote borrows: {}",
+            position.total_deposits(quote_token).as_u64(0) as f64 / quote_multiplier,
+            position.total_borrows(quote_token).as_u64(0) as f64 / quote_multiplier,
+        ),
+        None => println!("  Quote deposits: 0 | Quote borrows: 0"),
+    }
+    match cypher_user.get_position(ctx.market_index) {
+        Some(position) => println!(
+            "  {} deposits: {} | {} borrows: {}",
+            ctx.symbol,
+            position.base_deposits().as_u64(0) as f64 / base_multiplier,
+            ctx.symbol,
+            position.base_borrows().as_u64(0) as f64 / base_multiplier,
+        ),
+        None => println!("  No {} position", ctx.symbol),
+    }
+
+    let assets_value = cypher_user.get_assets_value(&ctx.cypher_group).as_u64(0) as f64;
+    let liabilities_value = cypher_user
+        .get_liabilities_value(&ctx.cypher_group)
+        .as_u64(0) as f64;
+    println!(
+        "  Assets value: {} | Liabilities value: {}",
+        assets_value / quote_multiplier,
+        liabilities_value / quote_multiplier
+    );
+    if liabilities_value > 0.0 {
+        println!("  Margin ratio: {:.4}", assets_value / liabilities_value);
+    } else {
+        println!("  Margin ratio: no liabilities");
+    }
+
+    println!("Open orders account: {}", ctx.open_orders_pubkey);
+    match _fetch_open_orders(&ctx.open_orders_pubkey, Arc::clone(&ctx.rpc_client)).await {
+        Ok(open_orders) => {
+            println!(
+                "  Coin free: {} | Coin total: {} | Price coin free: {} | Price coin total: {}",
+                identity(open_orders.native_coin_free),
+                identity(open_orders.native_coin_total),
+                identity(open_orders.native_pc_free),
+                identity(open_orders.native_pc_total)
+            );
+            let orders = get_open_orders(&open_orders).await;
+            if orders.is_empty() {
+                println!("  No resting orders");
+            }
+            for order in orders {
+                println!(
+                    "  {:?} | price (lots): {} | order id: {} | client order id: {}",
+                    order.side, order.price, order.order_id, order.client_order_id
+                );
+            }
+        }
+        Err(_) => println!("  Does not exist, run `init-account` to create it"),
+    }
+
+    Ok(())
+}
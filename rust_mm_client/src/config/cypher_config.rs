@@ -1,37 +1,45 @@
 use {
     serde::{Deserialize, Serialize},
     serde_json,
-    std::{error::Error, fs::File, io::BufReader},
+    super::ConfigError,
+    std::{collections::HashMap, fs::File, io::BufReader},
 };
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CypherConfig {
-    pub clusters: Clusters,
+    /// the clusters keyed by name, e.g. `devnet`, `mainnet` or `localnet`
+    pub clusters: HashMap<String, ClusterConfig>,
     pub groups: Vec<CypherGroupConfig>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct Clusters {
-    pub devnet: ClusterConfig,
-    pub mainnet: ClusterConfig,
-}
-
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClusterConfig {
     pub rpc_url: String,
     pub pubsub_url: String,
+    /// whether the quote token faucet can be used to fund the account, only true on test clusters
+    #[serde(default)]
+    pub airdrop: bool,
+    /// if set, the rpc node has to report this genesis hash, guards against pointing at the wrong cluster
+    #[serde(default)]
+    pub genesis_hash: Option<String>,
 }
 
 impl CypherConfig {
-    pub fn get_config_for_cluster(&self, cluster: &str) -> &ClusterConfig {
-        match cluster {
-            "devnet" => &self.clusters.devnet,
-            "mainnet" => &self.clusters.mainnet,
-            "" => &self.clusters.devnet,
-            &_ => &self.clusters.devnet,
-        }
+    pub fn get_config_for_cluster(&self, cluster: &str) -> Option<&ClusterConfig> {
+        self.clusters.get(cluster)
+    }
+
+    pub fn get_config_for_cluster_mut(&mut self, cluster: &str) -> Option<&mut ClusterConfig> {
+        self.clusters.get_mut(cluster)
+    }
+
+    /// sorted so error messages listing them are stable
+    pub fn cluster_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.clusters.keys().map(|k| k.as_str()).collect();
+        names.sort_unstable();
+        names
     }
 
     pub fn get_group(&self, cluster: &str) -> Option<&CypherGroupConfig> {
@@ -89,9 +97,9 @@ pub struct CypherMarketConfig {
     pub event_queue: String,
 }
 
-pub fn load_cypher_config(path: &str) -> Result<CypherConfig, Box<dyn Error>> {
-    let file = File::open(path)?;
+pub fn load_cypher_config(path: &str) -> Result<CypherConfig, ConfigError> {
+    let file = File::open(path)
+        .map_err(|e| ConfigError::new(path, "", &format!("cannot be opened: {}", e)))?;
     let reader = BufReader::new(file);
-    let cypher_config: CypherConfig = serde_json::from_reader(reader).unwrap();
-    Ok(cypher_config)
+    serde_json::from_reader(reader).map_err(|e| ConfigError::from_json(path, e))
 }
@@ -1,31 +1,205 @@
 use {
     crate::{
-        market_maker::{InventoryManagerConfig, OrderManagerConfig}
+        logging::LogConfig,
+        market_maker::{
+            AuditLogConfig, CapitalManagerConfig, DryRunConfig, InventoryManagerConfig,
+            OrderManagerConfig, PnlConfig, RequoteConfig, ShutdownConfig, StateStoreConfig,
+        },
+        services::{ControlConfig, MetricsConfig},
+        signer::SignerConfig,
+        watchdog::WatchdogConfig,
     },
     serde::{Deserialize, Serialize},
-    serde_json,
-    std::{error::Error, fs::File, io::BufReader},
+    serde_json::{self, Value},
+    super::ConfigError,
+    std::{fs, path::Path},
 };
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MarketMakerConfig {
+    /// the keypair file used by the file signer, not needed with the other signers
+    #[serde(default)]
     pub wallet: String,
+    /// where the trading key is kept, the plaintext keypair at `wallet` if omitted
+    #[serde(default)]
+    pub signer_config: SignerConfig,
+    /// set when the cypher account is owned by a separate key, the key above then only trades
+    /// as its delegate and cannot withdraw, omit to trade with the owner key
+    #[serde(default)]
+    pub owner_config: Option<OwnerConfig>,
     pub group: String,
     pub inventory_manager_config: InventoryManagerConfig,
     pub order_manager_config: OrderManagerConfig,
+    #[serde(default)]
+    pub requote_config: RequoteConfig,
+    #[serde(default)]
+    pub shutdown_config: ShutdownConfig,
+    /// omit to not write a heartbeat for the `watchdog` command
+    #[serde(default)]
+    pub watchdog_config: Option<WatchdogConfig>,
+    #[serde(default)]
+    pub pnl_config: PnlConfig,
+    /// omit to keep the order history and the pnl in memory only, they are then lost on restart
+    #[serde(default)]
+    pub state_store_config: Option<StateStoreConfig>,
+    /// omit to not write an audit log of the orders placed, cancelled and filled
+    #[serde(default)]
+    pub audit_log_config: Option<AuditLogConfig>,
+    /// omit to disable the prometheus metrics endpoint
+    #[serde(default)]
+    pub metrics_config: Option<MetricsConfig>,
+    #[serde(default)]
+    pub log_config: LogConfig,
+    /// omit to disable the operator control api
+    #[serde(default)]
+    pub control_config: Option<ControlConfig>,
+    #[serde(default)]
+    pub hot_reload_config: HotReloadConfig,
+    /// omit to disable moving quote between the wallet and the cypher account
+    #[serde(default)]
+    pub capital_manager_config: Option<CapitalManagerConfig>,
+    /// set to log and simulate transactions instead of sending them, omit to trade for real
+    #[serde(default)]
+    pub dry_run_config: Option<DryRunConfig>,
     pub market: MarketConfig,
 }
 
+/// The funding key which owns the cypher account, kept offline while the market maker runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnerConfig {
+    /// the cypher account is derived from this key
+    pub pubkey: String,
+    /// how to load the owner key for the administration commands, omit it on the trading host
+    #[serde(default)]
+    pub signer_config: Option<SignerConfig>,
+    /// the keypair file used by the file signer
+    #[serde(default)]
+    pub wallet: String,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MarketConfig {
     pub name: String,
+    /// the number of orders to keep from each side of the book, omit to keep the full book
+    #[serde(default)]
+    pub book_depth: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HotReloadConfig {
+    /// whether to poll the config file for changes, the config is always reloaded on SIGHUP
+    pub watch: bool,
+    pub poll_interval_ms: u64,
 }
 
-pub fn load_mm_config(path: &str) -> Result<MarketMakerConfig, Box<dyn Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mm_config: MarketMakerConfig = serde_json::from_reader(reader).unwrap();
-    Ok(mm_config)
+impl Default for HotReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            poll_interval_ms: 2000,
+        }
+    }
+}
+
+/// The sections of the config which can be applied to a running market maker,
+/// changes to any other section require a restart.
+pub const RELOADABLE_SECTIONS: &[&str] = &[
+    "inventoryManagerConfig",
+    "orderManagerConfig",
+    "requoteConfig",
+    "shutdownConfig",
+    "hotReloadConfig",
+];
+
+/// The fields of the reloadable sections which are only read at startup, the initial capital is
+/// deposited and taken as the pnl baseline then.
+pub const STARTUP_ONLY_FIELDS: &[&str] = &["inventoryManagerConfig.initialCapital"];
+
+/// The fields which differ between two configs, as dotted paths like `orderManagerConfig.layers`.
+#[derive(Debug, Default)]
+pub struct ConfigDiff {
+    /// changes which can be applied live
+    pub changed: Vec<String>,
+    /// changes which require a restart
+    pub rejected: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.rejected.is_empty()
+    }
+}
+
+impl MarketMakerConfig {
+    pub fn diff(&self, other: &MarketMakerConfig) -> Result<ConfigDiff, serde_json::Error> {
+        let current = serde_json::to_value(self)?;
+        let new = serde_json::to_value(other)?;
+
+        let mut changes: Vec<String> = Vec::new();
+        diff_values("", &current, &new, &mut changes);
+
+        let mut diff = ConfigDiff::default();
+        for change in changes {
+            let section = change.split('.').next().unwrap_or_default();
+            if RELOADABLE_SECTIONS.contains(&section)
+                && !STARTUP_ONLY_FIELDS.contains(&change.as_str())
+            {
+                diff.changed.push(change);
+            } else {
+                diff.rejected.push(change);
+            }
+        }
+        Ok(diff)
+    }
+}
+
+fn diff_values(path: &str, current: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (current, new) {
+        (Value::Object(current), Value::Object(new)) => {
+            let mut keys: Vec<&String> = current.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child_path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_values(
+                    &child_path,
+                    current.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ => {
+            if current != new {
+                changes.push(path.to_string());
+            }
+        }
+    }
+}
+
+/// loads the config as toml or yaml if the file has the matching extension, otherwise as json
+pub fn load_mm_config(path: &str) -> Result<MarketMakerConfig, ConfigError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError::new(path, "", &format!("cannot be read: {}", e)))?;
+
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "toml" => toml::from_str(&contents).map_err(|e| ConfigError::from_toml(path, e)),
+        "yaml" | "yml" => {
+            serde_yaml::from_str(&contents).map_err(|e| ConfigError::from_yaml(path, e))
+        }
+        _ => serde_json::from_str(&contents).map_err(|e| ConfigError::from_json(path, e)),
+    }
 }
@@ -1,5 +1,9 @@
 pub mod cypher_config;
 pub mod mm_config;
+pub mod overrides;
+pub mod validation;
 
 pub use cypher_config::*;
 pub use mm_config::*;
+pub use overrides::*;
+pub use validation::*;
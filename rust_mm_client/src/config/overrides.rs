@@ -0,0 +1,74 @@
+use {
+    super::{CypherConfig, MarketMakerConfig},
+    crate::market_maker::DryRunConfig,
+    log::info,
+};
+
+/// Values passed on the command line or through the environment which take precedence over the
+/// config files, `None` leaves the value from the config file untouched.
+///
+/// For every setting the precedence is: command line flag, then environment variable,
+/// then the config file, then the built-in default.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigOverrides {
+    pub wallet: Option<String>,
+    pub rpc_url: Option<String>,
+    pub pubsub_url: Option<String>,
+    pub log_level: Option<String>,
+    /// enables dry run mode with the default settings unless the config already enables it
+    pub dry_run: bool,
+}
+
+impl ConfigOverrides {
+    pub fn apply_mm_config(&self, mm_config: &mut MarketMakerConfig) {
+        if let Some(wallet) = &self.wallet {
+            mm_config.wallet = wallet.to_string();
+        }
+        if let Some(log_level) = &self.log_level {
+            mm_config.log_config.level = log_level.to_string();
+        }
+        if self.dry_run && mm_config.dry_run_config.is_none() {
+            mm_config.dry_run_config = Some(DryRunConfig::default());
+        }
+    }
+
+    /// overrides the endpoints of the cluster used by the given group
+    pub fn apply_cypher_config(&self, cypher_config: &mut CypherConfig, group: &str) {
+        let cluster = match cypher_config.get_group(group) {
+            Some(g) => g.cluster.to_string(),
+            None => return,
+        };
+        let cluster_config = match cypher_config.get_config_for_cluster_mut(&cluster) {
+            Some(c) => c,
+            None => return,
+        };
+        if let Some(rpc_url) = &self.rpc_url {
+            cluster_config.rpc_url = rpc_url.to_string();
+        }
+        if let Some(pubsub_url) = &self.pubsub_url {
+            cluster_config.pubsub_url = pubsub_url.to_string();
+        }
+    }
+
+    pub fn log_applied(&self) {
+        // the urls can embed api keys, so only log which values were overridden
+        let applied: Vec<&str> = [
+            ("wallet", self.wallet.is_some()),
+            ("rpc url", self.rpc_url.is_some()),
+            ("pubsub url", self.pubsub_url.is_some()),
+            ("log level", self.log_level.is_some()),
+            ("dry run", self.dry_run),
+        ]
+        .iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(name, _)| *name)
+        .collect();
+
+        if !applied.is_empty() {
+            info!(
+                "Overriding the config files with the {} from the command line or environment.",
+                applied.join(", ")
+            );
+        }
+    }
+}
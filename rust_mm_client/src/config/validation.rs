@@ -0,0 +1,527 @@
+use {
+    super::{CypherConfig, MarketMakerConfig},
+    crate::signer::{SignerConfig, ENV_WALLET_PREFIX},
+    log::LevelFilter,
+    solana_sdk::{derivation_path::DerivationPath, hash::Hash, pubkey::Pubkey},
+    std::{env, error::Error, fmt, fs::File, net::SocketAddr, str::FromStr},
+};
+
+// tolerances above this are almost certainly a typo
+const MAX_SANE_BPS: u64 = 1_000;
+
+/// A problem with a config file, pointing at the offending field.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub file: String,
+    /// the dotted path of the field, e.g. `inventoryManagerConfig.shapeDenom`
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(file: &str, field: &str, message: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// serde does not report the path of the field it failed on, only where in the file it was
+    pub fn from_json(file: &str, e: serde_json::Error) -> Self {
+        Self::parse_error(file, Some((e.line(), e.column())), &e.to_string())
+    }
+
+    pub fn from_toml(file: &str, e: toml::de::Error) -> Self {
+        // toml positions are zero based
+        let position = e.line_col().map(|(line, column)| (line + 1, column + 1));
+        Self::parse_error(file, position, &e.to_string())
+    }
+
+    pub fn from_yaml(file: &str, e: serde_yaml::Error) -> Self {
+        let position = e.location().map(|l| (l.line(), l.column()));
+        Self::parse_error(file, position, &e.to_string())
+    }
+
+    fn parse_error(file: &str, position: Option<(usize, usize)>, error: &str) -> Self {
+        let message = match position {
+            Some((line, column)) => format!(
+                "is not a valid config at line {} column {}: {}",
+                line, column, error
+            ),
+            None => format!("is not a valid config: {}", error),
+        };
+        Self {
+            file: file.to_string(),
+            field: String::new(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}: `{}` {}", self.file, self.field, self.message)
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Collects every problem found instead of stopping at the first one.
+struct Validator<'a> {
+    file: &'a str,
+    errors: Vec<ConfigError>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(file: &'a str) -> Self {
+        Self {
+            file,
+            errors: Vec::new(),
+        }
+    }
+
+    fn check(&mut self, condition: bool, field: &str, message: &str) {
+        if !condition {
+            self.errors.push(ConfigError::new(self.file, field, message));
+        }
+    }
+
+    fn check_pubkey(&mut self, value: &str, field: &str) {
+        if let Err(e) = Pubkey::from_str(value) {
+            self.errors.push(ConfigError::new(
+                self.file,
+                field,
+                &format!("is not a valid pubkey ({}): {:?}", e, value),
+            ));
+        }
+    }
+
+    /// `prefix` is the path of the section holding the signer config, e.g. `ownerConfig.`
+    fn check_signer(&mut self, signer_config: &SignerConfig, wallet: &str, prefix: &str) {
+        match signer_config {
+            SignerConfig::File { derivation_path } => {
+                self.check(
+                    !wallet.is_empty(),
+                    &format!("{}wallet", prefix),
+                    "must not be empty with the file signer",
+                );
+                if let Some(path) = derivation_path {
+                    self.check(
+                        DerivationPath::from_absolute_path_str(path).is_ok(),
+                        &format!("{}signerConfig.derivationPath", prefix),
+                        "is not a valid derivation path, e.g. m/44'/501'/0'/0'",
+                    );
+                }
+            }
+            SignerConfig::Keystore { path } => {
+                self.check(
+                    !path.is_empty(),
+                    &format!("{}signerConfig.path", prefix),
+                    "must not be empty",
+                );
+            }
+            SignerConfig::Remote { url, pubkey, .. } => {
+                self.check(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    &format!("{}signerConfig.url", prefix),
+                    "must be an http or https url",
+                );
+                self.check_pubkey(pubkey, &format!("{}signerConfig.pubkey", prefix));
+            }
+        }
+    }
+
+    fn check_socket_addr(&mut self, value: &str, field: &str) {
+        if value.parse::<SocketAddr>().is_err() {
+            self.errors.push(ConfigError::new(
+                self.file,
+                field,
+                &format!("is not a valid socket address: {:?}", value),
+            ));
+        }
+    }
+
+    fn check_log_level(&mut self, value: &str, field: &str) {
+        if LevelFilter::from_str(value).is_err() {
+            self.errors.push(ConfigError::new(
+                self.file,
+                field,
+                &format!(
+                    "is not a valid log level, expected one of off, error, warn, info, debug or trace: {:?}",
+                    value
+                ),
+            ));
+        }
+    }
+}
+
+impl MarketMakerConfig {
+    /// checks the fields which do not depend on any other config, returns every problem found
+    pub fn validate(&self, path: &str) -> Result<(), Vec<ConfigError>> {
+        let mut v = Validator::new(path);
+
+        v.check_signer(&self.signer_config, &self.wallet, "");
+        if let Some(owner_config) = &self.owner_config {
+            v.check_pubkey(&owner_config.pubkey, "ownerConfig.pubkey");
+            if let Some(signer_config) = &owner_config.signer_config {
+                v.check_signer(signer_config, &owner_config.wallet, "ownerConfig.");
+            }
+        }
+        v.check(!self.group.is_empty(), "group", "must not be empty");
+        v.check(!self.market.name.is_empty(), "market.name", "must not be empty");
+        if let Some(book_depth) = self.market.book_depth {
+            v.check(
+                book_depth > 0,
+                "market.bookDepth",
+                "must be greater than zero, omit it to keep the full book",
+            );
+        }
+
+        let imc = &self.inventory_manager_config;
+        v.check(
+            imc.max_quote > 0,
+            "inventoryManagerConfig.maxQuote",
+            "must be greater than zero",
+        );
+        v.check(
+            imc.shape_num > 0,
+            "inventoryManagerConfig.shapeNum",
+            "must be greater than zero",
+        );
+        v.check(
+            imc.shape_denom > 0,
+            "inventoryManagerConfig.shapeDenom",
+            "must be greater than zero",
+        );
+        v.check(
+            imc.spread > 0,
+            "inventoryManagerConfig.spread",
+            "must be greater than zero, quoting at the oracle price crosses the book",
+        );
+
+        let omc = &self.order_manager_config;
+        v.check(
+            omc.layers > 0,
+            "orderManagerConfig.layers",
+            "must be greater than zero",
+        );
+        v.check(
+            omc.layers <= 1 || omc.spacing_bps > 0,
+            "orderManagerConfig.spacingBps",
+            "must be greater than zero when quoting more than one layer",
+        );
+        v.check(
+            (omc.price_tolerance_bps as u64) <= MAX_SANE_BPS,
+            "orderManagerConfig.priceToleranceBps",
+            &format!("must be at most {} bps", MAX_SANE_BPS),
+        );
+        v.check(
+            omc.queue_priority_max_size_ahead_bps <= 10_000,
+            "orderManagerConfig.queuePriorityMaxSizeAheadBps",
+            "must be at most 10000 bps",
+        );
+        if omc.order_ttl_secs > 0 {
+            v.check(
+                omc.order_ttl_refresh_secs > 0,
+                "orderManagerConfig.orderTtlRefreshSecs",
+                "must be greater than zero when orderManagerConfig.orderTtlSecs is set",
+            );
+            v.check(
+                omc.order_ttl_refresh_secs < omc.order_ttl_secs,
+                "orderManagerConfig.orderTtlRefreshSecs",
+                "must be less than orderManagerConfig.orderTtlSecs",
+            );
+            v.check(
+                omc.order_ttl_refresh_secs * 1000 > self.requote_config.heartbeat_interval_ms,
+                "orderManagerConfig.orderTtlRefreshSecs",
+                "must be longer than requoteConfig.heartbeatIntervalMs, or orders expire before they are refreshed",
+            );
+        }
+
+        let rc = &self.requote_config;
+        v.check(
+            rc.heartbeat_interval_ms > 0,
+            "requoteConfig.heartbeatIntervalMs",
+            "must be greater than zero",
+        );
+        v.check(
+            rc.min_requote_interval_ms <= rc.heartbeat_interval_ms,
+            "requoteConfig.minRequoteIntervalMs",
+            "must not be greater than requoteConfig.heartbeatIntervalMs",
+        );
+        v.check(
+            rc.error_backoff_ms <= rc.max_error_backoff_ms,
+            "requoteConfig.errorBackoffMs",
+            "must not be greater than requoteConfig.maxErrorBackoffMs",
+        );
+        v.check(
+            self.shutdown_config.cancel_retry_interval_ms > 0,
+            "shutdownConfig.cancelRetryIntervalMs",
+            "must be greater than zero",
+        );
+
+        if let Some(ssc) = &self.state_store_config {
+            v.check(
+                !ssc.directory.is_empty(),
+                "stateStoreConfig.directory",
+                "must not be empty",
+            );
+        }
+        if let Some(alc) = &self.audit_log_config {
+            v.check(
+                !alc.directory.is_empty(),
+                "auditLogConfig.directory",
+                "must not be empty",
+            );
+        }
+        if let Some(wc) = &self.watchdog_config {
+            v.check(
+                !wc.heartbeat_file.is_empty(),
+                "watchdogConfig.heartbeatFile",
+                "must not be empty",
+            );
+            v.check(
+                wc.poll_interval_ms > 0,
+                "watchdogConfig.pollIntervalMs",
+                "must be greater than zero",
+            );
+            v.check(
+                wc.timeout_secs * 1000 > self.requote_config.heartbeat_interval_ms,
+                "watchdogConfig.timeoutSecs",
+                "must be longer than requoteConfig.heartbeatIntervalMs, the heartbeat is written at least that often",
+            );
+        }
+
+        if let Some(metrics_config) = &self.metrics_config {
+            v.check_socket_addr(&metrics_config.bind_address, "metricsConfig.bindAddress");
+        }
+        if let Some(control_config) = &self.control_config {
+            v.check_socket_addr(&control_config.bind_address, "controlConfig.bindAddress");
+        }
+
+        v.check_log_level(&self.log_config.level, "logConfig.level");
+        for (module, level) in self.log_config.modules.iter() {
+            v.check_log_level(level, &format!("logConfig.modules.{}", module));
+        }
+        if let Some(file) = &self.log_config.file {
+            v.check(
+                !file.directory.is_empty(),
+                "logConfig.file.directory",
+                "must not be empty",
+            );
+            v.check(
+                !file.prefix.is_empty(),
+                "logConfig.file.prefix",
+                "must not be empty",
+            );
+        }
+
+        v.check(
+            !self.hot_reload_config.watch || self.hot_reload_config.poll_interval_ms > 0,
+            "hotReloadConfig.pollIntervalMs",
+            "must be greater than zero",
+        );
+
+        if let Some(cmc) = &self.capital_manager_config {
+            v.check(
+                cmc.top_up_below <= cmc.target,
+                "capitalManagerConfig.topUpBelow",
+                "must not be greater than capitalManagerConfig.target",
+            );
+            v.check(
+                cmc.sweep_above >= cmc.target,
+                "capitalManagerConfig.sweepAbove",
+                "must not be less than capitalManagerConfig.target",
+            );
+            v.check(
+                cmc.max_transfer > 0,
+                "capitalManagerConfig.maxTransfer",
+                "must be greater than zero",
+            );
+            v.check(
+                cmc.min_transfer <= cmc.max_transfer,
+                "capitalManagerConfig.minTransfer",
+                "must not be greater than capitalManagerConfig.maxTransfer",
+            );
+            v.check(
+                cmc.min_margin_ratio >= 1.0,
+                "capitalManagerConfig.minMarginRatio",
+                "must be at least 1, below that the account can be liquidated",
+            );
+            v.check(
+                cmc.interval_secs != Some(0),
+                "capitalManagerConfig.intervalSecs",
+                "must be greater than zero, omit it to only rebalance on command",
+            );
+            v.check(
+                cmc.interval_secs.is_none() || self.owner_config.is_none(),
+                "capitalManagerConfig.intervalSecs",
+                "must be omitted with ownerConfig, the trading key cannot move funds, run `rebalance` with the owner key instead",
+            );
+        }
+
+        if v.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(v.errors)
+        }
+    }
+}
+
+/// validates the market maker config together with the cypher config it refers to,
+/// returns every problem found across both files
+pub fn validate_configs(
+    mm_config: &MarketMakerConfig,
+    mm_config_path: &str,
+    cypher_config: &CypherConfig,
+    cypher_config_path: &str,
+) -> Result<(), Vec<ConfigError>> {
+    let mut errors = match mm_config.validate(mm_config_path) {
+        Ok(_) => Vec::new(),
+        Err(e) => e,
+    };
+
+    let mut v = Validator::new(mm_config_path);
+    let (field, key_path) = match &mm_config.signer_config {
+        SignerConfig::File { .. } => ("wallet", Some(&mm_config.wallet)),
+        SignerConfig::Keystore { path } => ("signerConfig.path", Some(path)),
+        SignerConfig::Remote { .. } => ("signerConfig", None),
+    };
+    if let Some(key_path) = key_path.filter(|p| !p.is_empty()) {
+        if let Some(name) = key_path.strip_prefix(ENV_WALLET_PREFIX) {
+            v.check(
+                env::var(name).is_ok(),
+                field,
+                &format!("refers to environment variable {} which is not set", name),
+            );
+        } else if let Err(e) = File::open(key_path) {
+            v.errors.push(ConfigError::new(
+                mm_config_path,
+                field,
+                &format!("cannot be read ({}): {:?}", e, key_path),
+            ));
+        }
+    }
+    errors.extend(v.errors);
+
+    let group_config = match cypher_config.get_group(&mm_config.group) {
+        Some(g) => g,
+        None => {
+            errors.push(ConfigError::new(
+                mm_config_path,
+                "group",
+                &format!(
+                    "does not match any group in {}: {:?}",
+                    cypher_config_path, mm_config.group
+                ),
+            ));
+            return Err(errors);
+        }
+    };
+    let group_index = cypher_config
+        .groups
+        .iter()
+        .position(|g| g.name == group_config.name)
+        .unwrap();
+    let group_field = format!("groups[{}]", group_index);
+
+    let mut v = Validator::new(cypher_config_path);
+    v.check_pubkey(&group_config.address, &format!("{}.address", group_field));
+    if !group_config.program_id.is_empty() {
+        v.check_pubkey(
+            &group_config.program_id,
+            &format!("{}.programId", group_field),
+        );
+    }
+    if !group_config.serum_program_id.is_empty() {
+        v.check_pubkey(
+            &group_config.serum_program_id,
+            &format!("{}.serumProgramId", group_field),
+        );
+    }
+    for (i, token) in group_config.tokens.iter().enumerate() {
+        v.check_pubkey(&token.mint, &format!("{}.tokens[{}].mint", group_field, i));
+    }
+    for (i, oracle) in group_config.oracles.iter().enumerate() {
+        v.check_pubkey(
+            &oracle.address,
+            &format!("{}.oracles[{}].address", group_field, i),
+        );
+    }
+
+    match cypher_config.get_config_for_cluster(&group_config.cluster) {
+        Some(cluster_config) => {
+            let cluster_field = format!("clusters.{}", group_config.cluster);
+            v.check(
+                !cluster_config.rpc_url.is_empty(),
+                &format!("{}.rpcUrl", cluster_field),
+                "must not be empty",
+            );
+            v.check(
+                !cluster_config.pubsub_url.is_empty(),
+                &format!("{}.pubsubUrl", cluster_field),
+                "must not be empty",
+            );
+            if let Some(genesis_hash) = &cluster_config.genesis_hash {
+                if Hash::from_str(genesis_hash).is_err() {
+                    v.errors.push(ConfigError::new(
+                        cypher_config_path,
+                        &format!("{}.genesisHash", cluster_field),
+                        &format!("is not a valid hash: {:?}", genesis_hash),
+                    ));
+                }
+            }
+        }
+        None => {
+            v.errors.push(ConfigError::new(
+                cypher_config_path,
+                &format!("{}.cluster", group_field),
+                &format!(
+                    "does not match any cluster, expected one of {:?}: {:?}",
+                    cypher_config.cluster_names(),
+                    group_config.cluster
+                ),
+            ));
+        }
+    }
+
+    match group_config
+        .markets
+        .iter()
+        .position(|m| m.name == mm_config.market.name)
+    {
+        Some(market_index) => {
+            let market_config = &group_config.markets[market_index];
+            let market_field = format!("{}.markets[{}]", group_field, market_index);
+            v.check_pubkey(&market_config.address, &format!("{}.address", market_field));
+            v.check_pubkey(&market_config.bids, &format!("{}.bids", market_field));
+            v.check_pubkey(&market_config.asks, &format!("{}.asks", market_field));
+            v.check_pubkey(
+                &market_config.event_queue,
+                &format!("{}.eventQueue", market_field),
+            );
+        }
+        None => {
+            errors.push(ConfigError::new(
+                mm_config_path,
+                "market.name",
+                &format!(
+                    "does not match any market of group {:?} in {}: {:?}",
+                    group_config.name, cypher_config_path, mm_config.market.name
+                ),
+            ));
+        }
+    }
+    errors.extend(v.errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,678 @@
+use {
+    crate::{config::ConfigError, tx_logs::TxLogs},
+    base64::DecodeError,
+    serum_dex::error::DexErrorCode,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_request::RpcError,
+    },
+    solana_sdk::{
+        instruction::InstructionError, pubkey::Pubkey, signer::SignerError,
+        transaction::TransactionError,
+    },
+    std::{fmt, io},
+    thiserror::Error,
+};
+
+// anchor programs number their own errors from here, everything below belongs to the framework
+const ANCHOR_ERROR_CODE_OFFSET: u32 = 6000;
+
+/// How the caller should react to an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// try again on the next cycle, e.g. the order was already filled or the blockhash expired
+    Retryable,
+    /// try again after backing off, e.g. the rpc node is unreachable or the margin is too low
+    Transient,
+    /// retrying will not help, e.g. the trading key is not allowed to trade for the account
+    Fatal,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Retryable => "retryable",
+            Self::Transient => "transient",
+            Self::Fatal => "fatal",
+        }
+    }
+}
+
+/// A custom error code returned by an instruction, decoded by the program which defines it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramErrorCode {
+    /// returned by the serum dex, usually through a cypher cpi
+    Serum(DexErrorCode),
+    /// a failed assertion in the serum dex
+    SerumAssertion {
+        file: &'static str,
+        line: u32,
+    },
+    /// a constraint or account check of the anchor framework cypher is built on
+    Anchor(u32),
+    /// defined by the cypher program
+    Cypher(u32),
+    /// returned by another program, or by a program which could not be told from the logs
+    Unknown(u32),
+}
+
+impl ProgramErrorCode {
+    /// decodes the code by the numbering of the program which returned it, codes of any program
+    /// other than the serum dex and cypher are left undecoded
+    pub fn decode(code: u32, program: Option<&Pubkey>) -> Self {
+        match program {
+            Some(p) if *p == anchor_spl::dex::ID => Self::decode_serum(code),
+            Some(p) if *p == cypher::ID => Self::decode_cypher(code),
+            _ => Self::Unknown(code),
+        }
+    }
+
+    fn decode_serum(code: u32) -> Self {
+        // serum assertions are encoded as the source file id in the top byte and the line below it
+        let file = match code >> 24 {
+            1 => Some("state.rs"),
+            2 => Some("matching.rs"),
+            3 => Some("critbit.rs"),
+            _ => None,
+        };
+        if let Some(file) = file {
+            return Self::SerumAssertion {
+                file,
+                line: code & 0x00ff_ffff,
+            };
+        }
+
+        match DexErrorCode::try_from(code) {
+            Ok(c) => Self::Serum(c),
+            Err(_) => Self::Unknown(code),
+        }
+    }
+
+    fn decode_cypher(code: u32) -> Self {
+        if code >= ANCHOR_ERROR_CODE_OFFSET {
+            Self::Cypher(code)
+        } else if code >= 100 {
+            Self::Anchor(code)
+        } else {
+            Self::Unknown(code)
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            // the book moved on since the orders were built, the next requote sorts it out
+            Self::Serum(
+                DexErrorCode::OrderNotFound
+                | DexErrorCode::ClientIdNotFound
+                | DexErrorCode::WouldSelfTrade
+                | DexErrorCode::OrderMaxTimestampExceeded,
+            ) => ErrorClass::Retryable,
+            Self::Serum(
+                DexErrorCode::WrongSigner
+                | DexErrorCode::OrderNotYours
+                | DexErrorCode::WrongOrdersAccount
+                | DexErrorCode::MarketIsDisabled,
+            ) => ErrorClass::Fatal,
+            // wrong accounts or signers, e.g. the trading key is not the delegate of the account
+            Self::Anchor(_) => ErrorClass::Fatal,
+            _ => ErrorClass::Transient,
+        }
+    }
+}
+
+impl fmt::Display for ProgramErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serum(c) => write!(f, "serum {:?} ({})", c, *c as u32),
+            Self::SerumAssertion { file, line } => {
+                write!(f, "serum assertion failed at {}:{}", file, line)
+            }
+            Self::Anchor(code) => {
+                let kind = match code {
+                    100..=999 => "instruction",
+                    1000..=1999 => "idl",
+                    2000..=2999 => "constraint",
+                    3000..=3999 => "account",
+                    _ => "framework",
+                };
+                write!(f, "anchor {} error {}", kind, code)
+            }
+            Self::Cypher(code) => write!(
+                f,
+                "cypher error {} ({})",
+                code - ANCHOR_ERROR_CODE_OFFSET,
+                code
+            ),
+            Self::Unknown(code) => write!(f, "custom program error {:#x}", code),
+        }
+    }
+}
+
+/// Why the program refused an order, fed back into the order manager so the next quote
+/// can adapt instead of repeating the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderRejection {
+    /// a post only order would have taken liquidity
+    PostOnlyWouldCross,
+    InsufficientMargin,
+    InsufficientFunds,
+    TooManyOpenOrders,
+}
+
+impl OrderRejection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PostOnlyWouldCross => "post_only_would_cross",
+            Self::InsufficientMargin => "insufficient_margin",
+            Self::InsufficientFunds => "insufficient_funds",
+            Self::TooManyOpenOrders => "too_many_open_orders",
+        }
+    }
+}
+
+/// A transaction which could not be built, sent or confirmed.
+#[derive(Debug, Error)]
+pub enum TxError {
+    #[error("instruction {index} failed with {code}{}", describe_logs(.logs))]
+    Program {
+        index: u8,
+        code: ProgramErrorCode,
+        logs: TxLogs,
+        #[source]
+        source: ClientError,
+    },
+    #[error(transparent)]
+    Client(ClientError),
+    #[error("failed to sign transaction: {0}")]
+    Signer(#[from] SignerError),
+    #[error("no recent blockhash is available yet")]
+    NoBlockhash,
+}
+
+fn describe_logs(logs: &TxLogs) -> String {
+    match logs.error() {
+        Some(e) => format!(", {}", e),
+        None => String::new(),
+    }
+}
+
+impl From<ClientError> for TxError {
+    fn from(e: ClientError) -> Self {
+        match e.get_transaction_error() {
+            Some(TransactionError::InstructionError(index, InstructionError::Custom(code))) => {
+                let logs = TxLogs::from_client_error(&e);
+                Self::Program {
+                    index,
+                    code: ProgramErrorCode::decode(code, logs.failed_program.as_ref()),
+                    logs,
+                    source: e,
+                }
+            }
+            _ => Self::Client(e),
+        }
+    }
+}
+
+impl TxError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::Program { code, .. } => code.class(),
+            Self::Client(e) => classify_client_error(e),
+            Self::Signer(e) => classify_signer_error(e),
+            Self::NoBlockhash => ErrorClass::Retryable,
+        }
+    }
+
+    pub fn program_error(&self) -> Option<ProgramErrorCode> {
+        match self {
+            Self::Program { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn logs(&self) -> Option<&TxLogs> {
+        match self {
+            Self::Program { logs, .. } => Some(logs),
+            _ => None,
+        }
+    }
+
+    /// whether a program failed the transaction without the logs of the failure, which is the
+    /// case for transactions which passed preflight and failed once they landed
+    pub fn needs_logs(&self) -> bool {
+        matches!(self, Self::Program { logs, .. } if logs.is_empty())
+    }
+
+    /// attaches the logs of the confirmed transaction and decodes the code again now that the
+    /// failing program is known
+    pub fn with_logs(mut self, new_logs: TxLogs) -> Self {
+        if let Self::Program {
+            code, logs, source, ..
+        } = &mut self
+        {
+            if let Some(TransactionError::InstructionError(_, InstructionError::Custom(raw))) =
+                source.get_transaction_error()
+            {
+                *code = ProgramErrorCode::decode(raw, new_logs.failed_program.as_ref());
+            }
+            *logs = new_logs;
+        }
+        self
+    }
+
+    /// the reason an order was refused, from the error code or, for the errors cypher defines,
+    /// from what the program logged
+    ///
+    /// serum drops post only orders which would cross without failing, those are only noticed
+    /// once the order is missing from the open orders account
+    pub fn rejection(&self) -> Option<OrderRejection> {
+        let (code, logs) = match self {
+            Self::Program { code, logs, .. } => (code, logs),
+            _ => return None,
+        };
+
+        match code {
+            ProgramErrorCode::Serum(DexErrorCode::InsufficientFunds) => {
+                return Some(OrderRejection::InsufficientFunds)
+            }
+            ProgramErrorCode::Serum(DexErrorCode::TooManyOpenOrders) => {
+                return Some(OrderRejection::TooManyOpenOrders)
+            }
+            _ => (),
+        }
+
+        if logs.mentions("margin") {
+            Some(OrderRejection::InsufficientMargin)
+        } else if logs.mentions("too many open orders") {
+            Some(OrderRejection::TooManyOpenOrders)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn classify_client_error(e: &ClientError) -> ErrorClass {
+    if let Some(tx_error) = e.get_transaction_error() {
+        return classify_transaction_error(
+            &tx_error,
+            TxLogs::from_client_error(e).failed_program.as_ref(),
+        );
+    }
+    match e.kind() {
+        ClientErrorKind::SigningError(e) => classify_signer_error(e),
+        // the transaction was not confirmed in time, most likely because its blockhash expired
+        ClientErrorKind::RpcError(RpcError::ForUser(_)) => ErrorClass::Retryable,
+        _ => ErrorClass::Transient,
+    }
+}
+
+/// classifies a transaction error, custom codes are decoded by the numbering of `program`
+pub fn classify_transaction_error(e: &TransactionError, program: Option<&Pubkey>) -> ErrorClass {
+    match e {
+        TransactionError::BlockhashNotFound
+        | TransactionError::AlreadyProcessed
+        | TransactionError::AccountInUse
+        | TransactionError::WouldExceedMaxBlockCostLimit
+        | TransactionError::WouldExceedMaxAccountCostLimit => ErrorClass::Retryable,
+        // the fee payer is out of sol or the transaction is malformed
+        TransactionError::InsufficientFundsForFee
+        | TransactionError::AccountNotFound
+        | TransactionError::InvalidAccountForFee
+        | TransactionError::SignatureFailure
+        | TransactionError::MissingSignatureForFee
+        | TransactionError::SanitizeFailure => ErrorClass::Fatal,
+        TransactionError::InstructionError(_, e) => match e {
+            InstructionError::Custom(code) => ProgramErrorCode::decode(*code, program).class(),
+            InstructionError::MissingRequiredSignature
+            | InstructionError::IncorrectAuthority
+            | InstructionError::IllegalOwner => ErrorClass::Fatal,
+            _ => ErrorClass::Transient,
+        },
+        _ => ErrorClass::Transient,
+    }
+}
+
+fn classify_signer_error(e: &SignerError) -> ErrorClass {
+    match e {
+        // a remote signer which is briefly unreachable
+        SignerError::Connection(_) => ErrorClass::Transient,
+        _ => ErrorClass::Fatal,
+    }
+}
+
+/// A config which could not be loaded, or which lacks the section a command needs.
+#[derive(Debug, Error)]
+pub enum ConfigLoadError {
+    #[error("no config was provided")]
+    NotProvided,
+    #[error("the config paths are not valid unicode")]
+    InvalidPath,
+    #[error("found {} problems with the config", .0.len())]
+    Invalid(Vec<ConfigError>),
+    #[error("there is no `{0}` in the config")]
+    MissingSection(&'static str),
+    #[error("failed to initialize the logger")]
+    Logger,
+}
+
+/// A key which could not be loaded, or a signer which could not be set up.
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("failed to open the keypair file")]
+    FileOpen,
+    #[error("failed to read the keypair file")]
+    FileRead,
+    #[error("the keypair environment variable is not set")]
+    EnvVar,
+    #[error("the keypair bytes are invalid")]
+    InvalidBytes,
+    #[error("the keypair is neither a json byte array, base58 nor a seed phrase")]
+    InvalidFormat,
+    #[error("the seed phrase is invalid")]
+    InvalidSeedPhrase,
+    #[error("the derivation path is invalid")]
+    InvalidDerivationPath,
+    #[error("failed to load the keystore")]
+    Keystore,
+    #[error("the signer is misconfigured or unreachable")]
+    Signer,
+    #[error("the owner key is not configured")]
+    OwnerUnavailable,
+    #[error("the owner key does not match the configured owner")]
+    OwnerMismatch,
+}
+
+/// Orders which could not be placed or cancelled, or funds which could not be settled.
+#[derive(Debug, Error)]
+pub enum OrderError {
+    #[error("failed to submit orders: {0}")]
+    Submit(#[source] TxError),
+    #[error("failed to settle funds: {0}")]
+    Settle(#[source] TxError),
+    #[error("there is not enough liquidity in the book")]
+    NoLiquidity,
+    #[error("the order price must be a positive number of lots")]
+    InvalidPrice,
+    #[error("the order size does not fit in a transaction")]
+    InvalidSize,
+    /// the transaction landed but the program refused to place the orders
+    #[error("the orders were rejected: {}", .0.as_str())]
+    Rejected(OrderRejection),
+    #[error("failed to cancel the orders left by a previous run")]
+    CancelExisting,
+}
+
+impl OrderError {
+    pub fn tx_error(&self) -> Option<&TxError> {
+        match self {
+            Self::Submit(e) | Self::Settle(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// the reason the orders were refused, whether the transaction failed or not
+    pub fn rejection(&self) -> Option<OrderRejection> {
+        match self {
+            Self::Rejected(r) => Some(*r),
+            Self::Submit(e) => e.rejection(),
+            _ => None,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::Submit(e) | Self::Settle(e) => e.class(),
+            // requoting one tick wider is all it takes
+            Self::Rejected(OrderRejection::PostOnlyWouldCross) => ErrorClass::Retryable,
+            Self::Rejected(_) | Self::NoLiquidity | Self::CancelExisting => ErrorClass::Transient,
+            Self::InvalidPrice | Self::InvalidSize => ErrorClass::Fatal,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MarketMakerError {
+    #[error(transparent)]
+    Config(#[from] ConfigLoadError),
+    #[error(transparent)]
+    Key(#[from] KeyError),
+    #[error(transparent)]
+    Order(#[from] OrderError),
+    #[error("{context}: {source}")]
+    Rpc {
+        context: &'static str,
+        #[source]
+        source: ClientError,
+    },
+    #[error("failed to fetch the dex market")]
+    ErrorFetchingDexMarket,
+    #[error("failed to fetch the cypher group")]
+    ErrorFetchingCypherGroup,
+    #[error("the cypher account does not exist")]
+    ErrorFetchingCypherAccount,
+    #[error("the open orders account does not exist")]
+    ErrorFetchingOpenOrders,
+    #[error("failed to create the cypher account: {0}")]
+    ErrorCreatingCypherAccount(#[source] TxError),
+    #[error("failed to create the open orders account: {0}")]
+    ErrorCreatingOpenOrders(#[source] TxError),
+    #[error("failed to deposit: {0}")]
+    ErrorDepositing(#[source] TxError),
+    #[error("failed to withdraw: {0}")]
+    ErrorWithdrawing(#[source] TxError),
+    #[error("failed to close the open orders account: {0}")]
+    ErrorClosingOpenOrders(#[source] TxError),
+    #[error("failed to set the delegate: {0}")]
+    ErrorSettingDelegate(#[source] TxError),
+    #[error("the open orders account still has orders or unsettled funds")]
+    OpenOrdersNotEmpty,
+    #[error("the cluster does not have a faucet")]
+    AirdropUnavailable,
+    #[error("the amount must be a positive number")]
+    InvalidAmount,
+    #[error("the account response has an unexpected format")]
+    InvalidAccountResponseFormat,
+    #[error("the account data has an unexpected encoding")]
+    InvalidAccountDataEncoding,
+    #[error("failed to decode account data: {0}")]
+    AccountInfoDecoding(#[source] DecodeError),
+    #[error("failed to send to a channel, the receiver is gone")]
+    ChannelSendError,
+    #[error("failed to join a task")]
+    JoiningTaskError,
+    #[error("failed to initialize the services")]
+    InitServicesError,
+    #[error("failed to initialize the rpc client")]
+    RpcClientInitError,
+    #[error("failed to initialize the pubsub client")]
+    PubsubClientInitError,
+    #[error("the rpc node serves a different cluster")]
+    GenesisHashMismatch,
+    #[error("failed to open the state store: {0}")]
+    StateStoreError(#[source] io::Error),
+    #[error("failed to read or write the audit log: {0}")]
+    AuditLogError(#[source] io::Error),
+    #[error("failed to shut down cleanly")]
+    ShutdownError,
+}
+
+impl MarketMakerError {
+    /// the transaction error behind this error, if it was caused by a failed transaction
+    pub fn tx_error(&self) -> Option<&TxError> {
+        match self {
+            Self::Order(e) => e.tx_error(),
+            Self::ErrorCreatingCypherAccount(e)
+            | Self::ErrorCreatingOpenOrders(e)
+            | Self::ErrorDepositing(e)
+            | Self::ErrorWithdrawing(e)
+            | Self::ErrorClosingOpenOrders(e)
+            | Self::ErrorSettingDelegate(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        if let Some(e) = self.tx_error() {
+            return e.class();
+        }
+        match self {
+            Self::Order(e) => e.class(),
+            Self::Rpc { source, .. } => classify_client_error(source),
+            Self::ErrorFetchingDexMarket
+            | Self::ErrorFetchingCypherGroup
+            | Self::InvalidAccountResponseFormat
+            | Self::InvalidAccountDataEncoding
+            | Self::AccountInfoDecoding(_)
+            | Self::RpcClientInitError
+            | Self::PubsubClientInitError => ErrorClass::Transient,
+            _ => ErrorClass::Fatal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_client::rpc_request::RpcResponseErrorData};
+
+    fn serum() -> Option<&'static Pubkey> {
+        Some(&anchor_spl::dex::ID)
+    }
+
+    fn cypher() -> Option<&'static Pubkey> {
+        Some(&cypher::ID)
+    }
+
+    #[test]
+    fn decodes_serum_codes_returned_by_the_dex() {
+        let code = DexErrorCode::OrderNotFound as u32;
+        let decoded = ProgramErrorCode::decode(code, serum());
+        assert_eq!(
+            decoded,
+            ProgramErrorCode::Serum(DexErrorCode::OrderNotFound)
+        );
+        assert_eq!(decoded.class(), ErrorClass::Retryable);
+
+        let code = DexErrorCode::WrongSigner as u32;
+        assert_eq!(
+            ProgramErrorCode::decode(code, serum()).class(),
+            ErrorClass::Fatal
+        );
+    }
+
+    #[test]
+    fn decodes_serum_assertions() {
+        let decoded = ProgramErrorCode::decode(2 << 24 | 1234, serum());
+        assert_eq!(
+            decoded,
+            ProgramErrorCode::SerumAssertion {
+                file: "matching.rs",
+                line: 1234,
+            }
+        );
+        assert_eq!(
+            decoded.to_string(),
+            "serum assertion failed at matching.rs:1234"
+        );
+    }
+
+    #[test]
+    fn decodes_anchor_and_cypher_codes_returned_by_cypher() {
+        let decoded = ProgramErrorCode::decode(2003, cypher());
+        assert_eq!(decoded, ProgramErrorCode::Anchor(2003));
+        assert_eq!(decoded.class(), ErrorClass::Fatal);
+        assert_eq!(decoded.to_string(), "anchor constraint error 2003");
+
+        let decoded = ProgramErrorCode::decode(6005, cypher());
+        assert_eq!(decoded, ProgramErrorCode::Cypher(6005));
+        assert_eq!(decoded.to_string(), "cypher error 5 (6005)");
+    }
+
+    #[test]
+    fn small_codes_are_only_serum_codes_when_the_dex_returned_them() {
+        let code = DexErrorCode::OrderNotFound as u32;
+        assert_eq!(
+            ProgramErrorCode::decode(code, cypher()),
+            ProgramErrorCode::Unknown(code)
+        );
+        assert_eq!(
+            ProgramErrorCode::decode(code, Some(&Pubkey::new_unique())),
+            ProgramErrorCode::Unknown(code)
+        );
+        assert_eq!(
+            ProgramErrorCode::decode(code, None),
+            ProgramErrorCode::Unknown(code)
+        );
+    }
+
+    #[test]
+    fn codes_of_other_programs_are_not_decoded() {
+        let token_program = Pubkey::new_unique();
+        assert_eq!(
+            ProgramErrorCode::decode(6005, Some(&token_program)),
+            ProgramErrorCode::Unknown(6005)
+        );
+        assert_eq!(
+            ProgramErrorCode::decode(2 << 24 | 1234, cypher()),
+            ProgramErrorCode::Unknown(2 << 24 | 1234)
+        );
+    }
+
+    fn preflight_failure(code: u32, logs: Vec<String>) -> ClientError {
+        let result = serde_json::from_value(serde_json::json!({
+            "err": { "InstructionError": [1, { "Custom": code }] },
+            "logs": logs,
+        }))
+        .unwrap();
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32002,
+            message: "Transaction simulation failed".to_string(),
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+        })
+        .into()
+    }
+
+    fn serum_failure_logs(code: u32) -> Vec<String> {
+        let cypher = cypher::ID.to_string();
+        let dex = anchor_spl::dex::ID.to_string();
+        vec![
+            format!("Program {} invoke [1]", cypher),
+            "Program log: Instruction: NewOrder".to_string(),
+            format!("Program {} invoke [2]", dex),
+            format!("Program {} failed: custom program error: {:#x}", dex, code),
+            format!(
+                "Program {} failed: custom program error: {:#x}",
+                cypher, code
+            ),
+        ]
+    }
+
+    #[test]
+    fn decodes_preflight_failures_with_the_program_from_the_logs() {
+        let code = DexErrorCode::InsufficientFunds as u32;
+        let e = TxError::from(preflight_failure(code, serum_failure_logs(code)));
+
+        assert_eq!(
+            e.program_error(),
+            Some(ProgramErrorCode::Serum(DexErrorCode::InsufficientFunds))
+        );
+        assert_eq!(e.rejection(), Some(OrderRejection::InsufficientFunds));
+        assert!(!e.needs_logs());
+    }
+
+    #[test]
+    fn confirmed_failures_are_decoded_again_once_the_logs_are_fetched() {
+        let code = DexErrorCode::InsufficientFunds as u32;
+        let e = TxError::from(ClientError::from(ClientErrorKind::TransactionError(
+            TransactionError::InstructionError(1, InstructionError::Custom(code)),
+        )));
+        assert!(e.needs_logs());
+        assert_eq!(e.program_error(), Some(ProgramErrorCode::Unknown(code)));
+
+        let e = e.with_logs(TxLogs::parse(serum_failure_logs(code)));
+        assert_eq!(
+            e.program_error(),
+            Some(ProgramErrorCode::Serum(DexErrorCode::InsufficientFunds))
+        );
+        assert_eq!(e.rejection(), Some(OrderRejection::InsufficientFunds));
+    }
+}
@@ -1,6 +1,9 @@
-use solana_sdk::{
-    hash::Hash, instruction::Instruction, message::Message, signature::Keypair, signer::Signer,
-    transaction::Transaction,
+use {
+    crate::signer::TxSigner,
+    solana_sdk::{
+        hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey,
+        signature::Keypair, signer::SignerError, transaction::Transaction,
+    },
 };
 
 #[derive(Debug, Default)]
@@ -28,20 +31,25 @@ impl FastTxnBuilder {
         self.ixs.clear();
     }
 
-    pub fn build(
+    pub async fn build(
         &self,
         recent_blockhash: Hash,
-        payer: &Keypair,
+        payer: &dyn TxSigner,
         additional_signers: Option<&Vec<Keypair>>,
-    ) -> Transaction {
-        let message = Message::new(&self.ixs[..], Some(&payer.pubkey()));
-        let mut txn = Transaction::new_unsigned(message);
-        txn.partial_sign(&[payer], recent_blockhash);
+    ) -> Result<Transaction, SignerError> {
+        let mut txn = self.build_unsigned(&payer.pubkey());
+        payer.sign_transaction(&mut txn, recent_blockhash).await?;
         if let Some(adsigners) = additional_signers {
             for adsigner in adsigners {
-                txn.partial_sign(&[adsigner], recent_blockhash);
+                txn.try_partial_sign(&[adsigner], recent_blockhash)?;
             }
         }
-        txn
+        Ok(txn)
+    }
+
+    /// the transaction without any signatures, enough to know its serialized size
+    pub fn build_unsigned(&self, payer: &Pubkey) -> Transaction {
+        let message = Message::new(&self.ixs[..], Some(payer));
+        Transaction::new_unsigned(message)
     }
 }
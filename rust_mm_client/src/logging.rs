@@ -1,33 +1,339 @@
 use {
     chrono::Local,
-    log::{Level, LevelFilter, Metadata, Record, SetLoggerError},
+    log::{
+        kv::{self, Key, Value, Visitor},
+        Level, LevelFilter, Metadata, Record,
+    },
+    serde::{Deserialize, Serialize},
+    serde_json::{Map, Value as JsonValue},
+    std::{
+        collections::HashMap,
+        error::Error,
+        fs::{self, File, OpenOptions},
+        io::{self, BufWriter, Write},
+        path::PathBuf,
+        str::FromStr,
+        sync::Mutex,
+    },
 };
 
-static LOGGER: Logger = Logger;
-
 const DATE_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S";
 
-struct Logger;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFileConfig {
+    /// the directory the log files are written to
+    pub directory: String,
+    /// log files are named `<prefix>.<period>.log`, or `<prefix>.log` if they are never rotated
+    pub prefix: String,
+    pub rotation: LogRotation,
+    /// the number of rotated files to keep around, omit to keep them all
+    #[serde(default)]
+    pub max_files: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LogConfig {
+    /// the level used for every module without a more specific level, e.g. `info`
+    pub level: String,
+    /// levels for specific modules keyed by module path, e.g. `rust_mm_client::accounts_cache`
+    pub modules: HashMap<String, String>,
+    pub format: LogFormat,
+    /// whether to write to stdout, in addition to the log file if one is configured
+    pub stdout: bool,
+    pub file: Option<LogFileConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            modules: HashMap::new(),
+            format: LogFormat::Text,
+            stdout: true,
+            file: None,
+        }
+    }
+}
+
+/// A log file which is swapped for a new one whenever the rotation period changes.
+struct RollingFile {
+    config: LogFileConfig,
+    period: String,
+    writer: BufWriter<File>,
+}
+
+impl RollingFile {
+    fn new(config: &LogFileConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let period = Self::current_period(config.rotation);
+        let writer = Self::open(config, &period)?;
+        let rolling_file = Self {
+            config: config.clone(),
+            period,
+            writer,
+        };
+        rolling_file.prune()?;
+        Ok(rolling_file)
+    }
+
+    fn current_period(rotation: LogRotation) -> String {
+        match rotation {
+            LogRotation::Hourly => Local::now().format("%Y-%m-%d-%H").to_string(),
+            LogRotation::Daily => Local::now().format("%Y-%m-%d").to_string(),
+            LogRotation::Never => String::new(),
+        }
+    }
+
+    fn path(config: &LogFileConfig, period: &str) -> PathBuf {
+        let file_name = if period.is_empty() {
+            format!("{}.log", config.prefix)
+        } else {
+            format!("{}.{}.log", config.prefix, period)
+        };
+        PathBuf::from(&config.directory).join(file_name)
+    }
+
+    fn open(config: &LogFileConfig, period: &str) -> io::Result<BufWriter<File>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::path(config, period))?;
+        Ok(BufWriter::new(file))
+    }
+
+    /// removes the oldest log files if there are more than the configured maximum
+    fn prune(&self) -> io::Result<()> {
+        let max_files = match self.config.max_files {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
+        let prefix = format!("{}.", self.config.prefix);
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.config.directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name.starts_with(&prefix) && name.ends_with(".log"),
+                None => false,
+            })
+            .collect();
+
+        if files.len() <= max_files {
+            return Ok(());
+        }
+
+        // the period format sorts chronologically
+        files.sort();
+        for path in &files[..files.len() - max_files] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let period = Self::current_period(self.config.rotation);
+        if period != self.period {
+            self.writer.flush()?;
+            self.writer = Self::open(&self.config, &period)?;
+            self.period = period;
+            self.prune()?;
+        }
+        writeln!(self.writer, "{}", line)
+    }
+}
+
+/// Collects the key-value pairs attached to a record, e.g. `info!(client_order_id = id; "...")`.
+struct FieldCollector(Map<String, JsonValue>);
+
+impl<'kvs> Visitor<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let json_value = match value.to_u64() {
+            Some(v) => JsonValue::from(v),
+            None => match value.to_i64() {
+                Some(v) => JsonValue::from(v),
+                None => JsonValue::from(value.to_string()),
+            },
+        };
+        self.0.insert(key.as_str().to_string(), json_value);
+        Ok(())
+    }
+}
+
+struct Logger {
+    default_level: LevelFilter,
+    /// per module levels, sorted from the most to the least specific module path
+    module_levels: Vec<(String, LevelFilter)>,
+    format: LogFormat,
+    stdout: bool,
+    file: Option<Mutex<RollingFile>>,
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        for (module, level) in self.module_levels.iter() {
+            if target == module || target.starts_with(&format!("{}::", module)) {
+                return *level;
+            }
+        }
+        self.default_level
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.module_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default_level, LevelFilter::max)
+    }
+
+    fn format_text(&self, record: &Record) -> String {
+        format!(
+            "{} - {} - {}",
+            Local::now().format(DATE_FORMAT_STR),
+            record.level(),
+            record.args()
+        )
+    }
+
+    fn format_json(&self, record: &Record) -> String {
+        let message = record.args().to_string();
+
+        let mut fields = FieldCollector(Map::new());
+        let _ = record.key_values().visit(&mut fields);
+        let mut fields = fields.0;
+
+        // components prefix their messages with `[COMPONENT]` or `[COMPONENT-MARKET]`,
+        // lift those into fields unless the call site provided them explicitly
+        let message = match parse_prefix(&message) {
+            Some((component, market, rest)) => {
+                fields
+                    .entry("component")
+                    .or_insert_with(|| JsonValue::from(component));
+                if let Some(market) = market {
+                    fields
+                        .entry("market")
+                        .or_insert_with(|| JsonValue::from(market));
+                }
+                rest.to_string()
+            }
+            None => message,
+        };
+
+        let mut entry = Map::new();
+        entry.insert(
+            "timestamp".to_string(),
+            JsonValue::from(Local::now().to_rfc3339()),
+        );
+        entry.insert(
+            "level".to_string(),
+            JsonValue::from(record.level().to_string()),
+        );
+        entry.insert("target".to_string(), JsonValue::from(record.target()));
+        entry.insert("message".to_string(), JsonValue::from(message));
+        entry.extend(fields);
+
+        JsonValue::Object(entry).to_string()
+    }
+}
+
+/// splits a message like `[ORDERMGR-SOL] Placing orders.` into its component, market and the rest
+fn parse_prefix(message: &str) -> Option<(&str, Option<&str>, &str)> {
+    let stripped = message.strip_prefix('[')?;
+    let end = stripped.find(']')?;
+    let prefix = &stripped[..end];
+    let rest = stripped[end + 1..].trim_start();
+
+    match prefix.split_once('-') {
+        Some((component, market)) => Some((component, Some(market), rest)),
+        None => Some((prefix, None, rest)),
+    }
+}
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info
+        metadata.level() <= self.level_for(metadata.target())
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            println!(
-                "{} - {} - {}",
-                Local::now().format(DATE_FORMAT_STR),
-                record.level(),
-                record.args()
-            );
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = match self.format {
+            LogFormat::Text => self.format_text(record),
+            LogFormat::Json => self.format_json(record),
+        };
+
+        if self.stdout {
+            println!("{}", line);
+        }
+
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                if let Err(e) = file.write_line(&line) {
+                    eprintln!("Failed to write to log file: {}", e);
+                }
+                // make sure warnings and errors are not lost if we crash right after
+                if record.level() <= Level::Warn {
+                    let _ = file.writer.flush();
+                }
+            }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.writer.flush();
+            }
+        }
+    }
 }
 
-pub fn init_logger() -> Result<(), SetLoggerError> {
-    log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Info))
+fn parse_level(level: &str) -> Result<LevelFilter, Box<dyn Error>> {
+    LevelFilter::from_str(level).map_err(|_| format!("invalid log level: {}", level).into())
+}
+
+pub fn init_logger(config: &LogConfig) -> Result<(), Box<dyn Error>> {
+    let default_level = parse_level(&config.level)?;
+
+    let mut module_levels = Vec::new();
+    for (module, level) in config.modules.iter() {
+        module_levels.push((module.to_string(), parse_level(level)?));
+    }
+    module_levels.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+
+    let file = match &config.file {
+        Some(file_config) => Some(Mutex::new(RollingFile::new(file_config)?)),
+        None => None,
+    };
+
+    let logger = Logger {
+        default_level,
+        module_levels,
+        format: config.format,
+        stdout: config.stdout,
+        file,
+    };
+    let max_level = logger.max_level();
+
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+    Ok(())
 }
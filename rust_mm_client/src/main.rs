@@ -1,20 +1,28 @@
-use base64::DecodeError;
 use solana_client::nonblocking::pubsub_client::PubsubClient;
 
 mod accounts_cache;
+mod commands;
 mod config;
+mod error;
 mod fast_tx_builder;
 mod logging;
 mod market_maker;
 mod math;
+mod metrics;
+mod orderbook;
 mod providers;
 mod serum_slab;
 mod services;
+mod signer;
+mod tx_logs;
 mod utils;
+mod watchdog;
 
 use {
     crate::{market_maker::MarketMaker, utils::get_deposit_collateral_ix},
-    clap::Parser,
+    chrono::NaiveDate,
+    clap::{Parser, Subcommand},
+    commands::CommandContext,
     config::*,
     cypher::{
         constants::QUOTE_TOKEN_IDX,
@@ -25,139 +33,449 @@ use {
         },
         CypherGroup, CypherUser,
     },
+    error::{ConfigLoadError, KeyError, MarketMakerError},
     fast_tx_builder::FastTxnBuilder,
     faucet::request_airdrop_ix,
     jet_proto_math::Number,
     log::{info, warn},
-    logging::init_logger,
+    logging::{init_logger, LogConfig},
     serum_dex::state::OpenOrders,
+    services::SignerService,
+    signer::{
+        load_keypair, load_signer, read_passphrase, Keystore, SignerConfig, TxSigner,
+        KEYSTORE_PASSPHRASE_ENV, SIGNER_TOKEN_ENV,
+    },
     solana_client::nonblocking::rpc_client::RpcClient,
     solana_sdk::{
-        commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Keypair, signer::Signer,
+        commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey, signer::Signer,
+        transaction::Transaction,
     },
     spl_associated_token_account::instruction::create_associated_token_account,
-    std::{fs::File, io::Read, str::FromStr, sync::Arc},
-    tokio::sync::broadcast::channel,
+    std::{env, str::FromStr, sync::Arc},
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::broadcast::channel,
+    },
     utils::{
-        derive_quote_token_address, get_init_open_orders_ix, get_token_account, init_cypher_user,
+        derive_quote_token_address, get_init_open_orders_ix, get_token_account,
+        get_withdraw_collateral_ix, init_cypher_user,
     },
 };
 
-// rework this, maybe ask user for input as well
-pub const CYPHER_CONFIG_PATH: &str = "./cfg/group.json";
-
+/// The market maker is configured by two files, the market maker config and the cypher group config.
+///
+/// Every option below can also be set through the environment variable listed next to it.
+/// A value passed on the command line takes precedence over the environment variable,
+/// which takes precedence over the config files, which take precedence over the defaults.
 #[derive(Parser)]
 struct Cli {
-    #[clap(short = 'c', long = "config", parse(from_os_str))]
-    config: std::path::PathBuf,
+    /// The market maker config, in json, toml or yaml depending on the file extension,
+    /// required by every command except `encrypt-keypair` and `signer-server`
+    #[clap(short = 'c', long = "config", env = "MM_CONFIG", parse(from_os_str))]
+    config: Option<std::path::PathBuf>,
+    /// The cypher group config which lists the clusters, groups and markets
+    #[clap(
+        short = 'g',
+        long = "group-config",
+        env = "MM_GROUP_CONFIG",
+        default_value = "./cfg/group.json",
+        parse(from_os_str)
+    )]
+    group_config: std::path::PathBuf,
+    /// Overrides the rpc url of the cluster in the cypher group config
+    #[clap(long = "rpc-url", env = "MM_RPC_URL")]
+    rpc_url: Option<String>,
+    /// Overrides the pubsub url of the cluster in the cypher group config
+    #[clap(long = "pubsub-url", env = "MM_PUBSUB_URL")]
+    pubsub_url: Option<String>,
+    /// Overrides the wallet keypair path in the market maker config
+    #[clap(short = 'w', long = "wallet", env = "MM_WALLET")]
+    wallet: Option<String>,
+    /// Overrides the log level in the market maker config, per module levels still apply
+    #[clap(long = "log-level", env = "MM_LOG_LEVEL")]
+    log_level: Option<String>,
+    /// Log and simulate the transactions the market maker would send instead of sending them
+    #[clap(long = "dry-run", env = "MM_DRY_RUN")]
+    dry_run: bool,
+    /// runs the market maker if omitted
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug)]
-pub enum MarketMakerError {
-    ConfigLoadError,
-    ErrorFetchingDexMarket,
-    ErrorFetchingCypherGroup,
-    ErrorFetchingCypherAccount,
-    ErrorFetchingOpenOrders,
-    ErrorCreatingCypherAccount,
-    ErrorCreatingOpenOrders,
-    ErrorDepositing,
-    ErrorSubmittingOrders,
-    InvalidAccountResponseFormat,
-    InvalidAccountDataEncoding,
-    AccountInfoDecoding(DecodeError),
-    ChannelSendError,
-    JoiningTaskError,
-    InitServicesError,
-    RpcClientInitError,
-    PubsubClientInitError,
-    KeypairFileOpenError,
-    KeypairFileReadError,
-    KeypairLoadError,
-    ShutdownError,
+impl Cli {
+    fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            wallet: self.wallet.clone(),
+            rpc_url: self.rpc_url.clone(),
+            pubsub_url: self.pubsub_url.clone(),
+            log_level: self.log_level.clone(),
+            dry_run: self.dry_run,
+        }
+    }
 }
 
-fn load_keypair(path: &str) -> Result<Keypair, MarketMakerError> {
-    let fd = File::open(path);
+#[derive(Subcommand)]
+enum Command {
+    /// Create the accounts if needed, top up the initial capital and start quoting, the default
+    Run,
+    /// Validate the config and the cypher group config it refers to, reporting every problem found
+    ValidateConfig,
+    /// Create the cypher user account and the open orders account for the configured market
+    InitAccount,
+    /// Deposit quote from the wallet into the cypher account
+    Deposit {
+        /// the amount of quote in ui units, e.g. 100.5
+        amount: f64,
+    },
+    /// Withdraw quote from the cypher account into the wallet
+    Withdraw {
+        /// the amount of quote in ui units, e.g. 100.5
+        amount: f64,
+    },
+    /// Request quote from the faucet, only on clusters with `airdrop` enabled
+    Airdrop,
+    /// Cancel every resting order in the configured market
+    CancelAll,
+    /// Cancel every order once the market maker stops writing its heartbeat, see `watchdogConfig`
+    Watchdog,
+    /// Export the audit log rows of a date range as a single csv, see `auditLogConfig`
+    ExportAudit {
+        /// the first utc day, e.g. 2022-07-01
+        from: NaiveDate,
+        /// the last utc day, included
+        to: NaiveDate,
+        /// where to write the csv, stdout if omitted
+        #[clap(long)]
+        output: Option<String>,
+    },
+    /// Settle the free funds of the open orders account
+    Settle,
+    /// Show the balances, position, margin and open orders
+    Status,
+    /// Close the open orders account of the configured market, it must have no orders or unsettled funds
+    CloseOpenOrders,
+    /// Let the trading key trade for the owner's cypher account, only with `ownerConfig`
+    SetDelegate {
+        /// remove the delegate instead, e.g. after the trading host has been compromised
+        #[clap(long)]
+        revoke: bool,
+    },
+    /// Move quote between the wallet and the cypher account according to `capitalManagerConfig`
+    Rebalance {
+        /// only log the transfer which would be made
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Encrypt a keypair file into a keystore, the passphrase is read from `MM_KEYSTORE_PASSPHRASE` or prompted for
+    EncryptKeypair {
+        /// the plaintext key, a json or base58 keypair or a seed phrase, in a file or `env:<NAME>`
+        input: String,
+        /// where to write the keystore
+        output: String,
+        /// derives the key from a seed phrase with this path, e.g. m/44'/501'/0'/0'
+        #[clap(long)]
+        derivation_path: Option<String>,
+    },
+    /// Serve the remote signer protocol for a keypair or keystore, authenticated with `MM_SIGNER_TOKEN`
+    SignerServer {
+        #[clap(long, default_value = "127.0.0.1:9092")]
+        bind_address: String,
+        /// the plaintext key, a json or base58 keypair or a seed phrase, in a file or `env:<NAME>`
+        #[clap(long, conflicts_with = "keystore", required_unless_present = "keystore")]
+        keypair: Option<String>,
+        /// derives the key from a seed phrase with this path, e.g. m/44'/501'/0'/0'
+        #[clap(long, requires = "keypair")]
+        derivation_path: Option<String>,
+        /// a keystore created with `encrypt-keypair`
+        #[clap(long)]
+        keystore: Option<String>,
+    },
+}
 
-    let mut file = match fd {
-        Ok(f) => f,
-        Err(e) => {
-            warn!("Failed to load keypair file: {}", e.to_string());
-            return Err(MarketMakerError::KeypairFileOpenError);
+/// loads the market maker config and the cypher config it refers to and validates them together,
+/// returns every problem found across both files
+fn load_configs(
+    config_path: &str,
+    cypher_config_path: &str,
+    overrides: &ConfigOverrides,
+) -> Result<(MarketMakerConfig, CypherConfig), Vec<ConfigError>> {
+    let mm_config = load_mm_config(config_path);
+    let cypher_config = load_cypher_config(cypher_config_path);
+
+    match (mm_config, cypher_config) {
+        (Ok(mut mm_config), Ok(mut cypher_config)) => {
+            overrides.apply_mm_config(&mut mm_config);
+            overrides.apply_cypher_config(&mut cypher_config, &mm_config.group);
+            validate_configs(&mm_config, config_path, &cypher_config, cypher_config_path)?;
+            Ok((mm_config, cypher_config))
+        }
+        (mm_config, cypher_config) => Err(mm_config
+            .err()
+            .into_iter()
+            .chain(cypher_config.err())
+            .collect()),
+    }
+}
+
+fn invalid_config(file: &str, field: &str, message: &str) -> MarketMakerError {
+    ConfigLoadError::Invalid(vec![ConfigError::new(file, field, message)]).into()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), MarketMakerError> {
+    let args = Cli::parse();
+    let overrides = args.overrides();
+    let command = args.command.unwrap_or(Command::Run);
+
+    // these do not touch the cluster, so they work without a config
+    if let Command::EncryptKeypair { .. } | Command::SignerServer { .. } = command {
+        if let Err(e) = init_logger(&LogConfig::default()) {
+            eprintln!("Failed to initialize logger: {}", e);
+            return Err(ConfigLoadError::Logger.into());
+        }
+    }
+    match command {
+        Command::EncryptKeypair {
+            input,
+            output,
+            derivation_path,
+        } => return encrypt_keypair(&input, &output, derivation_path.as_deref()),
+        Command::SignerServer {
+            bind_address,
+            keypair,
+            derivation_path,
+            keystore,
+        } => return signer_server(&bind_address, keypair, derivation_path, keystore).await,
+        _ => (),
+    }
+
+    let config = match &args.config {
+        Some(c) => c,
+        None => {
+            eprintln!("No config provided, pass one with --config or set MM_CONFIG.");
+            return Err(ConfigLoadError::NotProvided.into());
         }
     };
 
-    let file_string = &mut String::new();
-    let file_read_res = file.read_to_string(file_string);
+    // load config
+    let (config_path, cypher_config_path) =
+        match (config.to_str(), args.group_config.to_str()) {
+            (Some(c), Some(g)) => (c, g),
+            _ => {
+                eprintln!(
+                    "The config paths are not valid unicode: {:?}, {:?}",
+                    config, args.group_config
+                );
+                return Err(ConfigLoadError::InvalidPath.into());
+            }
+        };
 
-    let _ = if let Err(e) = file_read_res {
-        warn!(
-            "Failed to read keypair bytes from keypair file: {}",
-            e.to_string()
-        );
-        return Err(MarketMakerError::KeypairFileReadError);
+    // the logger is configured by the config, so problems with it are printed directly
+    let (mm_config, cypher_config) = match load_configs(config_path, cypher_config_path, &overrides)
+    {
+        Ok(c) => c,
+        Err(errors) => {
+            eprintln!("Found {} problems with the config:", errors.len());
+            for error in errors.iter() {
+                eprintln!("  {}", error);
+            }
+            return Err(ConfigLoadError::Invalid(errors).into());
+        }
     };
 
-    let keypair_bytes: Vec<u8> = file_string
-        .replace('[', "")
-        .replace(']', "")
-        .replace(',', " ")
-        .split(' ')
-        .map(|x| u8::from_str(x).unwrap())
-        .collect();
+    if let Command::ValidateConfig = command {
+        println!(
+            "Config {} and cypher config {} are valid.",
+            config_path, cypher_config_path
+        );
+        return Ok(());
+    }
 
-    let keypair = Keypair::from_bytes(keypair_bytes.as_ref());
+    // only reads local files, and runs before the logger so the csv can go to stdout on its own
+    if let Command::ExportAudit { from, to, output } = &command {
+        return commands::export_audit(
+            mm_config.audit_log_config.as_ref(),
+            *from,
+            *to,
+            output.as_deref(),
+        );
+    }
+
+    let mm_config = Arc::new(mm_config);
+    let cypher_config = Arc::new(cypher_config);
 
-    match keypair {
-        Ok(kp) => Ok(kp),
+    match init_logger(&mm_config.log_config) {
+        Ok(_) => (),
         Err(e) => {
-            warn!("Failed to load keypair from bytes: {}", e.to_string());
-            Err(MarketMakerError::KeypairLoadError)
+            eprintln!("Failed to initialize logger: {}", e);
+            return Err(ConfigLoadError::Logger.into());
+        }
+    };
+
+    info!("Loaded config from {}", config_path);
+    overrides.log_applied();
+
+    let ctx = init_command_context(&mm_config, config_path, &cypher_config, cypher_config_path)
+        .await?;
+
+    match command {
+        Command::Run => {
+            run(
+                ctx,
+                mm_config,
+                config_path,
+                overrides,
+                cypher_config,
+            )
+            .await
+        }
+        Command::ValidateConfig => Ok(()),
+        Command::InitAccount => commands::init_account(&ctx).await,
+        Command::Deposit { amount } => commands::deposit(&ctx, amount).await,
+        Command::Withdraw { amount } => commands::withdraw(&ctx, amount).await,
+        Command::Airdrop => commands::airdrop(&ctx).await,
+        Command::CancelAll => commands::cancel_all(&ctx).await,
+        Command::Watchdog => commands::watchdog(&ctx, mm_config.watchdog_config.as_ref()).await,
+        Command::Settle => commands::settle(&ctx).await,
+        Command::Status => commands::status(&ctx).await,
+        Command::CloseOpenOrders => commands::close_open_orders(&ctx).await,
+        Command::SetDelegate { revoke } => commands::set_delegate(&ctx, revoke).await,
+        Command::Rebalance { dry_run } => {
+            commands::rebalance(&ctx, mm_config.capital_manager_config.as_ref(), dry_run).await
         }
+        Command::EncryptKeypair { .. }
+        | Command::SignerServer { .. }
+        | Command::ExportAudit { .. } => Ok(()),
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), MarketMakerError> {
-    let args = Cli::parse();
+fn encrypt_keypair(
+    input: &str,
+    output: &str,
+    derivation_path: Option<&str>,
+) -> Result<(), MarketMakerError> {
+    let keypair = load_keypair(input, derivation_path)?;
+    let passphrase = read_passphrase("Keystore passphrase: ")?;
+    if env::var(KEYSTORE_PASSPHRASE_ENV).is_err()
+        && read_passphrase("Repeat the passphrase: ")? != passphrase
+    {
+        eprintln!("The passphrases do not match.");
+        return Err(KeyError::Keystore.into());
+    }
+    if passphrase.is_empty() {
+        eprintln!("The passphrase must not be empty.");
+        return Err(KeyError::Keystore.into());
+    }
 
-    _ = init_logger();
+    Keystore::encrypt(&keypair, &passphrase)?.save(output)?;
+    println!(
+        "Encrypted keypair {} into {}, the plaintext file can now be removed.",
+        keypair.pubkey(),
+        output
+    );
+    Ok(())
+}
 
-    // load config
-    let config_path = args.config.as_path().to_str().unwrap();
+/// serves the remote signer protocol until ctrl-c is received
+async fn signer_server(
+    bind_address: &str,
+    keypair: Option<String>,
+    derivation_path: Option<String>,
+    keystore: Option<String>,
+) -> Result<(), MarketMakerError> {
+    let auth_token = match env::var(SIGNER_TOKEN_ENV) {
+        Ok(t) if !t.is_empty() => t,
+        _ => {
+            warn!("Set {} to the token clients must provide.", SIGNER_TOKEN_ENV);
+            return Err(KeyError::Signer.into());
+        }
+    };
 
-    info!("Loading config from {}", config_path);
+    let signer_config = match keystore {
+        Some(path) => SignerConfig::Keystore { path },
+        None => SignerConfig::File { derivation_path },
+    };
+    let signer = load_signer(&signer_config, keypair.as_deref().unwrap_or_default()).await?;
+
+    let (shutdown_send, shutdown_recv) = channel::<bool>(1);
+    let service = Arc::new(SignerService::new(
+        bind_address,
+        auth_token,
+        signer,
+        shutdown_recv,
+    ));
+    let service_t = tokio::spawn(async move { service.start_service().await });
 
-    let mm_config = Arc::new(load_mm_config(config_path).unwrap());
-    let cypher_config = Arc::new(load_cypher_config(CYPHER_CONFIG_PATH).unwrap());
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            if shutdown_send.send(true).is_err() {
+                return Err(MarketMakerError::ShutdownError);
+            }
+        },
+    };
+
+    service_t
+        .await
+        .map_err(|_| MarketMakerError::JoiningTaskError)
+}
 
-    let cluster_config = cypher_config.get_config_for_cluster(mm_config.group.as_str());
+/// resolves the wallet, cluster, group and market from the configs and fetches the cypher group
+async fn init_command_context(
+    mm_config: &MarketMakerConfig,
+    config_path: &str,
+    cypher_config: &CypherConfig,
+    cypher_config_path: &str,
+) -> Result<CommandContext, MarketMakerError> {
+    let signer = load_signer(&mm_config.signer_config, mm_config.wallet.as_str()).await?;
+    let pubkey = signer.pubkey();
+    info!("Loaded signer with pubkey: {}", pubkey.to_string());
+
+    // with a separate owner the cypher account belongs to the owner and the signer trades as its delegate
+    let owner_pubkey = match &mm_config.owner_config {
+        Some(owner_config) => {
+            let owner_pubkey = Pubkey::from_str(&owner_config.pubkey).map_err(|_| {
+                invalid_config(config_path, "ownerConfig.pubkey", "is not a valid pubkey")
+            })?;
+            if owner_pubkey == pubkey {
+                return Err(invalid_config(
+                    config_path,
+                    "ownerConfig.pubkey",
+                    "must not be the trading key, omit ownerConfig to trade with the owner key",
+                ));
+            }
+            info!("Trading as delegate of owner {}.", owner_pubkey);
+            owner_pubkey
+        }
+        None => pubkey,
+    };
 
-    let keypair = load_keypair(mm_config.wallet.as_str()).unwrap();
-    let pubkey = keypair.pubkey();
-    info!("Loaded keypair with pubkey: {}", pubkey.to_string());
+    let cypher_group_config = cypher_config
+        .get_group(mm_config.group.as_str())
+        .ok_or_else(|| invalid_config(config_path, "group", "does not match any group"))?;
 
-    let cypher_group_config = Arc::new(cypher_config.get_group(mm_config.group.as_str()).unwrap());
+    let cypher_group_key = Pubkey::from_str(cypher_group_config.address.as_str())
+        .map_err(|_| invalid_config(cypher_config_path, "address", "is not a valid pubkey"))?;
 
-    let cypher_group_key = Pubkey::from_str(cypher_group_config.address.as_str()).unwrap();
+    let cluster_config = cypher_config
+        .get_config_for_cluster(cypher_group_config.cluster.as_str())
+        .ok_or_else(|| {
+            invalid_config(cypher_config_path, "cluster", "does not match any cluster")
+        })?;
 
     // initialize rpc client with cluster and cluster url provided in config
     info!(
         "Initializing rpc client for cluster-{} with url: {}",
-        mm_config.group, cluster_config.rpc_url
+        cypher_group_config.cluster, cluster_config.rpc_url
     );
     let rpc_client = Arc::new(RpcClient::new_with_commitment(
         cluster_config.rpc_url.to_string(),
         CommitmentConfig::confirmed(),
     ));
-    info!(
-        "Initializing pubsub client for cluster-{} with url: {}",
-        mm_config.group, cluster_config.rpc_url
-    );
-    let pubsub_client = Arc::new(PubsubClient::new(
-        &cluster_config.pubsub_url
-    ).await.unwrap());
+
+    if let Some(expected) = &cluster_config.genesis_hash {
+        _check_genesis_hash(Arc::clone(&rpc_client), &cypher_group_config.cluster, expected)
+            .await?;
+    }
 
     info!(
         "Attempting to get the cypher group account with key: {}",
@@ -172,54 +490,128 @@ async fn main() -> Result<(), MarketMakerError> {
         }
     };
 
-    let (cypher_user_key, _bump) = derive_cypher_user_address(&cypher_group_key, &keypair.pubkey());
+    let (cypher_user_key, _bump) = derive_cypher_user_address(&cypher_group_key, &owner_pubkey);
+
+    let market_config = cypher_group_config
+        .get_market(mm_config.market.name.as_str())
+        .ok_or_else(|| {
+            invalid_config(config_path, "market.name", "does not match any market of the group")
+        })?;
+
+    let market_pubkey = Pubkey::from_str(market_config.address.as_str()).map_err(|_| {
+        invalid_config(cypher_config_path, "markets.address", "is not a valid pubkey")
+    })?;
+    let open_orders = derive_open_orders_address(&market_pubkey, &cypher_user_key).0;
+
+    Ok(CommandContext {
+        rpc_client,
+        signer,
+        owner_pubkey,
+        owner_config: mm_config.owner_config.clone(),
+        airdrop: cluster_config.airdrop,
+        cypher_group_pubkey: cypher_group_key,
+        cypher_group,
+        cypher_user_pubkey: cypher_user_key,
+        market_pubkey,
+        market_index: market_config.market_index,
+        base_decimals: market_config.base_decimals,
+        symbol: market_config.name.to_string(),
+        open_orders_pubkey: open_orders,
+        pubsub_url: cluster_config.pubsub_url.to_string(),
+    })
+}
+
+/// sets up the accounts if needed and runs the market maker until ctrl-c is received
+async fn run(
+    ctx: CommandContext,
+    mm_config: Arc<MarketMakerConfig>,
+    config_path: &str,
+    overrides: ConfigOverrides,
+    cypher_config: Arc<CypherConfig>,
+) -> Result<(), MarketMakerError> {
+    let CommandContext {
+        rpc_client,
+        signer,
+        airdrop,
+        cypher_group_pubkey: cypher_group_key,
+        cypher_group,
+        cypher_user_pubkey: cypher_user_key,
+        market_pubkey,
+        open_orders_pubkey: open_orders,
+        pubsub_url,
+        owner_config,
+        ..
+    } = ctx;
+
+    info!("Initializing pubsub client with url: {}", pubsub_url);
+    let pubsub_client = match PubsubClient::new(&pubsub_url).await {
+        Ok(c) => Arc::new(c),
+        Err(e) => {
+            warn!("Failed to initialize the pubsub client: {}", e.to_string());
+            return Err(MarketMakerError::PubsubClientInitError);
+        }
+    };
+
+    // a dry run must not send anything, so the accounts have to exist already
+    let dry_run = mm_config.dry_run_config.is_some();
+    if dry_run {
+        info!("Dry run, transactions will be logged instead of sent. Existing accounts will not be created or funded.");
+    }
+    // neither can a delegate, which never holds the owner key
+    let delegated = owner_config.is_some();
+    if delegated {
+        info!("Trading as a delegate, the owner key is not loaded. Existing accounts will not be created or funded.");
+    }
+    let fetch_only = dry_run || delegated;
 
     info!(
         "Attempting to get the cypher user account with key: {}",
         cypher_user_key
     );
-    let cypher_account_res = _get_or_init_cypher_user(
-        &keypair,
-        &cypher_group_key,
-        &cypher_group,
-        &cypher_user_key,
-        Arc::clone(&rpc_client),
-        &mm_config,
-    )
-    .await;
+    let cypher_account_res = if fetch_only {
+        _fetch_cypher_user(&cypher_user_key, Arc::clone(&rpc_client)).await
+    } else {
+        _get_or_init_cypher_user(
+            signer.as_ref(),
+            &cypher_group_key,
+            &cypher_group,
+            &cypher_user_key,
+            Arc::clone(&rpc_client),
+            &mm_config,
+            airdrop,
+        )
+        .await
+    };
     let cypher_account = match cypher_account_res {
         Ok(cg) => cg,
-        Err(_) => {
-            warn!("An error occurred while getting or creating the cypher user account.");
-            return Err(MarketMakerError::ErrorCreatingCypherAccount);
+        Err(e) => {
+            warn!("An error occurred while getting or creating the cypher user account: {}. When dry running or trading as a delegate run `init-account` first.", e);
+            return Err(e);
         }
     };
 
-    let market_config = cypher_group_config
-        .get_market(mm_config.market.name.as_str())
-        .unwrap();
-
-    let market_pubkey = Pubkey::from_str(market_config.address.as_str()).unwrap();
-    let open_orders = derive_open_orders_address(&market_pubkey, &cypher_user_key).0;
-
     info!(
         "Attempting to get the open orders account for market: {}",
-        market_config.address
+        market_pubkey
     );
-    let open_orders_res = _get_or_init_open_orders(
-        &keypair,
-        &cypher_group_key,
-        &cypher_user_key,
-        &market_pubkey,
-        &open_orders,
-        Arc::clone(&rpc_client),
-    )
-    .await;
+    let open_orders_res = if fetch_only {
+        _fetch_open_orders(&open_orders, Arc::clone(&rpc_client)).await
+    } else {
+        _get_or_init_open_orders(
+            signer.as_ref(),
+            &cypher_group_key,
+            &cypher_user_key,
+            &market_pubkey,
+            &open_orders,
+            Arc::clone(&rpc_client),
+        )
+        .await
+    };
     let _open_orders = match open_orders_res {
         Ok(cg) => cg,
-        Err(_) => {
-            warn!("An error occurred while getting or creating the open orders account.");
-            return Err(MarketMakerError::ErrorCreatingOpenOrders);
+        Err(e) => {
+            warn!("An error occurred while getting or creating the open orders account: {}. When dry running or trading as a delegate run `init-account` first.", e);
+            return Err(e);
         }
     };
 
@@ -231,16 +623,20 @@ async fn main() -> Result<(), MarketMakerError> {
         Arc::clone(&rpc_client),
         Arc::clone(&pubsub_client),
         Arc::clone(&mm_config),
+        config_path,
+        overrides,
         Arc::clone(&cypher_config),
         cypher_group,
         cypher_group_key,
-        keypair,
+        Arc::clone(&signer),
+        // only the owner can move funds, so without it the capital manager stays off
+        if delegated { None } else { Some(signer) },
         cypher_account,
         cypher_user_key,
         shutdown_send.clone(),
     ).await;
 
-    let mm_t = tokio::spawn(async move {
+    let mut mm_t = tokio::spawn(async move {
         let start_res = mm.start().await;
         match start_res {
             Ok(_) => (),
@@ -252,34 +648,57 @@ async fn main() -> Result<(), MarketMakerError> {
         Ok(())
     });
 
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e.to_string());
+            return Err(MarketMakerError::InitServicesError);
+        }
+    };
+
+    // the market maker also stops by itself, e.g. on an error it cannot recover from
     tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            match shutdown_send.send(true) {
-                Ok(_) => {
-                    info!("Sucessfully sent shutdown signal. Waiting for tasks to complete...")
-                },
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+        mm_res = &mut mm_t => {
+            return match mm_res {
+                Ok(res) => res,
                 Err(e) => {
-                    warn!("Failed to send shutdown error: {}", e.to_string());
-                    return Err(MarketMakerError::ShutdownError);
+                    warn!("There was an error joining with the market maker: {}", e.to_string());
+                    Err(MarketMakerError::JoiningTaskError)
                 }
             };
-        },
+        }
     };
 
-    let (mm_res,) = tokio::join!(mm_t);
+    match shutdown_send.send(true) {
+        Ok(_) => {
+            info!("Sucessfully sent shutdown signal. Cancelling orders before stopping, interrupt again to exit immediately...")
+        }
+        Err(e) => {
+            warn!("Failed to send shutdown error: {}", e.to_string());
+            return Err(MarketMakerError::ShutdownError);
+        }
+    };
+
+    let mm_res = tokio::select! {
+        mm_res = mm_t => mm_res,
+        _ = tokio::signal::ctrl_c() => {
+            warn!("Interrupted again, exiting without confirming the orders were cancelled.");
+            return Err(MarketMakerError::ShutdownError);
+        }
+    };
 
     match mm_res {
-        Ok(_) => (),
+        Ok(res) => res,
         Err(e) => {
             warn!(
-                "There was an error while shutting down the account info service: {}",
+                "There was an error while shutting down the market maker: {}",
                 e.to_string()
             );
-            return Err(MarketMakerError::ShutdownError);
+            Err(MarketMakerError::ShutdownError)
         }
-    };
-
-    Ok(())
+    }
 }
 
 async fn _get_cypher_group(
@@ -288,20 +707,23 @@ async fn _get_cypher_group(
 ) -> Result<Box<CypherGroup>, MarketMakerError> {
     let res = rpc_client.get_account(&cypher_group_pubkey).await;
     let acc = match res {
-        Ok(a) => Some(a),
+        Ok(a) => a,
         Err(e) => {
             warn!("Failed to fetch cypher group: {}", e.to_string());
-            None
+            return Err(MarketMakerError::Rpc {
+                context: "failed to fetch the cypher group",
+                source: e,
+            });
         }
     };
 
-    let cypher_group = get_zero_copy_account::<CypherGroup>(&acc.unwrap().data);
+    let cypher_group = get_zero_copy_account::<CypherGroup>(&acc.data);
 
     Ok(cypher_group)
 }
 
 async fn _get_or_init_open_orders(
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     cypher_group_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
     cypher_market: &Pubkey,
@@ -337,20 +759,19 @@ async fn _get_or_init_open_orders(
                 return Err(e);
             }
         };
-        let open_orders = _fetch_open_orders(open_orders, Arc::clone(&rpc_client))
-            .await
-            .unwrap();
+        let open_orders = _fetch_open_orders(open_orders, Arc::clone(&rpc_client)).await?;
         Ok(open_orders)
     }
 }
 
 async fn _get_or_init_cypher_user(
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     cypher_group_pubkey: &Pubkey,
     cypher_group: &CypherGroup,
     cypher_user_pubkey: &Pubkey,
     rpc_client: Arc<RpcClient>,
     config: &MarketMakerConfig,
+    airdrop: bool,
 ) -> Result<Box<CypherUser>, MarketMakerError> {
     let account_state = _fetch_cypher_user(cypher_user_pubkey, Arc::clone(&rpc_client)).await;
 
@@ -364,9 +785,9 @@ async fn _get_or_init_cypher_user(
             cypher_group,
             config,
             rpc_client,
+            airdrop,
         )
-        .await
-        .unwrap();
+        .await?;
         Ok(account)
     } else {
         info!("Cypher user account does not existing, creating account.");
@@ -380,9 +801,7 @@ async fn _get_or_init_cypher_user(
             }
         };
 
-        let cypher_user = _fetch_cypher_user(cypher_user_pubkey, Arc::clone(&rpc_client))
-            .await
-            .unwrap();
+        let cypher_user = _fetch_cypher_user(cypher_user_pubkey, Arc::clone(&rpc_client)).await?;
 
         _check_cypher_balance(
             owner,
@@ -391,21 +810,22 @@ async fn _get_or_init_cypher_user(
             cypher_group,
             config,
             rpc_client,
+            airdrop,
         )
-        .await
-        .unwrap();
+        .await?;
 
         Ok(cypher_user)
     }
 }
 
 async fn _check_cypher_balance(
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     cypher_user_pubkey: &Pubkey,
     cypher_user: &CypherUser,
     cypher_group: &CypherGroup,
     config: &MarketMakerConfig,
     rpc_client: Arc<RpcClient>,
+    airdrop: bool,
 ) -> Result<(), MarketMakerError> {
     let position = cypher_user.get_position(QUOTE_TOKEN_IDX).unwrap();
     let quote_token = cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
@@ -432,12 +852,12 @@ async fn _check_cypher_balance(
 
     info!("Depositing quote token (native): {}.", amount_rem);
 
-    if config.group.contains("devnet") {
+    if airdrop {
         match request_airdrop(owner, Arc::clone(&rpc_client)).await {
             Ok(_) => (),
             Err(e) => {
-                warn!("There was an error requesting airdrop: {:?}", e);
-                return Err(MarketMakerError::ErrorDepositing);
+                warn!("There was an error requesting airdrop: {}", e);
+                return Err(e);
             }
         }
     }
@@ -452,8 +872,34 @@ async fn _check_cypher_balance(
     .await
 }
 
+/// makes sure the rpc node serves the cluster the group is configured for
+async fn _check_genesis_hash(
+    rpc_client: Arc<RpcClient>,
+    cluster: &str,
+    expected: &str,
+) -> Result<(), MarketMakerError> {
+    let genesis_hash = match rpc_client.get_genesis_hash().await {
+        Ok(h) => h,
+        Err(e) => {
+            warn!("Failed to fetch the genesis hash: {}", e.to_string());
+            return Err(MarketMakerError::RpcClientInitError);
+        }
+    };
+
+    if genesis_hash.to_string() != expected {
+        warn!(
+            "The rpc node reported genesis hash {} but cluster-{} expects {}, is the rpc url pointing at the wrong cluster?",
+            genesis_hash, cluster, expected
+        );
+        return Err(MarketMakerError::GenesisHashMismatch);
+    }
+
+    info!("Verified genesis hash {} for cluster-{}.", genesis_hash, cluster);
+    Ok(())
+}
+
 async fn request_airdrop(
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     rpc_client: Arc<RpcClient>,
 ) -> Result<(), MarketMakerError> {
     let token_account = derive_quote_token_address(owner.pubkey());
@@ -479,8 +925,8 @@ async fn request_airdrop(
     }
     builder.add(airdrop_ix);
 
-    let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let hash = _get_latest_blockhash(&rpc_client).await?;
+    let tx = _sign_transaction(&builder, hash, owner).await?;
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -494,13 +940,13 @@ async fn request_airdrop(
         }
         Err(e) => {
             warn!("There was an error requesting airdrop: {}", e.to_string());
-            Err(MarketMakerError::ErrorDepositing)
+            Err(MarketMakerError::ErrorDepositing(e.into()))
         }
     }
 }
 
 async fn deposit_quote_token(
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     cypher_user_pubkey: &Pubkey,
     cypher_group: &CypherGroup,
     rpc_client: Arc<RpcClient>,
@@ -518,8 +964,8 @@ async fn deposit_quote_token(
     );
     let mut builder = FastTxnBuilder::new();
     builder.add(ix);
-    let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, owner, None);
+    let hash = _get_latest_blockhash(&rpc_client).await?;
+    let tx = _sign_transaction(&builder, hash, owner).await?;
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -537,11 +983,89 @@ async fn deposit_quote_token(
                 "There was an error depositing funds into cypher account: {}",
                 e.to_string()
             );
-            Err(MarketMakerError::ErrorDepositing)
+            Err(MarketMakerError::ErrorDepositing(e.into()))
         }
     }
 }
 
+async fn withdraw_quote_token(
+    owner: &dyn TxSigner,
+    cypher_user_pubkey: &Pubkey,
+    cypher_group: &CypherGroup,
+    rpc_client: Arc<RpcClient>,
+    amount: u64,
+) -> Result<(), MarketMakerError> {
+    let destination_ata = derive_quote_token_address(owner.pubkey());
+    let mut builder = FastTxnBuilder::new();
+
+    let token_account_res = get_token_account(Arc::clone(&rpc_client), &destination_ata).await;
+    if token_account_res.is_err() {
+        info!(
+            "Quote token account does not exist, creating account with key: {} for mint {}.",
+            destination_ata,
+            quote_mint::ID
+        );
+        builder.add(create_associated_token_account(
+            &owner.pubkey(),
+            &owner.pubkey(),
+            &quote_mint::ID,
+        ));
+    }
+
+    builder.add(get_withdraw_collateral_ix(
+        &cypher_group.self_address,
+        cypher_user_pubkey,
+        &cypher_group.quote_vault(),
+        &cypher_group.vault_signer,
+        &destination_ata,
+        &owner.pubkey(),
+        amount,
+    ));
+    let hash = _get_latest_blockhash(&rpc_client).await?;
+    let tx = _sign_transaction(&builder, hash, owner).await?;
+    let res = rpc_client
+        .send_and_confirm_transaction_with_spinner(&tx)
+        .await;
+
+    match res {
+        Ok(s) => {
+            info!(
+                "Successfully withdrew funds from cypher account. Transaction signature: {}",
+                s.to_string()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            warn!(
+                "There was an error withdrawing funds from cypher account: {}",
+                e.to_string()
+            );
+            Err(MarketMakerError::ErrorWithdrawing(e.into()))
+        }
+    }
+}
+
+async fn _sign_transaction(
+    builder: &FastTxnBuilder,
+    hash: Hash,
+    signer: &dyn TxSigner,
+) -> Result<Transaction, MarketMakerError> {
+    builder.build(hash, signer, None).await.map_err(|e| {
+        warn!("Failed to sign transaction: {}", e.to_string());
+        KeyError::Signer.into()
+    })
+}
+
+async fn _get_latest_blockhash(rpc_client: &RpcClient) -> Result<Hash, MarketMakerError> {
+    rpc_client
+        .get_latest_blockhash()
+        .await
+        .map_err(|e| MarketMakerError::Rpc {
+            context: "failed to fetch a recent blockhash",
+            source: e,
+        })
+}
+
 async fn _fetch_cypher_user(
     cypher_user_pubkey: &Pubkey,
     rpc_client: Arc<RpcClient>,
@@ -549,7 +1073,10 @@ async fn _fetch_cypher_user(
     let res = rpc_client
         .get_account_with_commitment(cypher_user_pubkey, CommitmentConfig::confirmed())
         .await
-        .unwrap()
+        .map_err(|e| MarketMakerError::Rpc {
+            context: "failed to fetch the cypher user account",
+            source: e,
+        })?
         .value;
 
     if res.is_some() {
@@ -568,7 +1095,10 @@ async fn _fetch_open_orders(
     let res = rpc_client
         .get_account_with_commitment(open_orders, CommitmentConfig::confirmed())
         .await
-        .unwrap()
+        .map_err(|e| MarketMakerError::Rpc {
+            context: "failed to fetch the open orders account",
+            source: e,
+        })?
         .value;
 
     if res.is_some() {
@@ -582,7 +1112,7 @@ async fn _fetch_open_orders(
 
 async fn _init_cypher_user(
     cypher_group_pubkey: &Pubkey,
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     rpc_client: Arc<RpcClient>,
 ) -> Result<(), MarketMakerError> {
     let res = init_cypher_user(cypher_group_pubkey, owner, &rpc_client).await;
@@ -594,7 +1124,7 @@ async fn _init_cypher_user(
                 "There was an error creating the cypher account: {}",
                 e.to_string()
             );
-            return Err(MarketMakerError::ErrorCreatingCypherAccount);
+            return Err(MarketMakerError::ErrorCreatingCypherAccount(e.into()));
         }
     }
 
@@ -606,7 +1136,7 @@ async fn _init_open_orders(
     cypher_user_pubkey: &Pubkey,
     cypher_market: &Pubkey,
     open_orders: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn TxSigner,
     rpc_client: Arc<RpcClient>,
 ) -> Result<(), MarketMakerError> {
     let ix = get_init_open_orders_ix(
@@ -619,8 +1149,8 @@ async fn _init_open_orders(
 
     let mut builder = FastTxnBuilder::new();
     builder.add(ix);
-    let hash = rpc_client.get_latest_blockhash().await.unwrap();
-    let tx = builder.build(hash, signer, None);
+    let hash = _get_latest_blockhash(&rpc_client).await?;
+    let tx = _sign_transaction(&builder, hash, signer).await?;
     let res = rpc_client
         .send_and_confirm_transaction_with_spinner(&tx)
         .await;
@@ -637,7 +1167,7 @@ async fn _init_open_orders(
                 "There was an error creating the open orders account: {}",
                 e.to_string()
             );
-            Err(MarketMakerError::ErrorCreatingOpenOrders)
+            Err(MarketMakerError::ErrorCreatingOpenOrders(e.into()))
         }
     }
 }
@@ -0,0 +1,196 @@
+use {
+    chrono::{DateTime, NaiveDate, SecondsFormat, Utc},
+    serde::{Deserialize, Serialize},
+    serum_dex::matching::Side,
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{self, BufRead, BufReader, Write},
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+};
+
+const AUDIT_HEADER: &str =
+    "timestamp,market,action,status,side,client_order_id,order_id,price,quantity,signature,reason";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditLogConfig {
+    /// where the audit files are written, one csv file per utc day named `audit-<date>.csv`
+    pub directory: String,
+}
+
+impl Default for AuditLogConfig {
+    fn default() -> Self {
+        Self {
+            directory: "audit".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Place,
+    Cancel,
+    Fill,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Place => "place",
+            Self::Cancel => "cancel",
+            Self::Fill => "fill",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditStatus {
+    /// the transaction was confirmed
+    Confirmed,
+    /// the transaction failed, the reason says why
+    Failed,
+    /// seen in the book, fills are not sent by us
+    Observed,
+    /// logged by a dry run, the transaction was never sent
+    Simulated,
+}
+
+impl AuditStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::Failed => "failed",
+            Self::Observed => "observed",
+            Self::Simulated => "simulated",
+        }
+    }
+}
+
+/// A row of the audit log. The price and quantity are in native units, as the book reports them.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub action: AuditAction,
+    pub status: AuditStatus,
+    pub side: Side,
+    pub client_order_id: u64,
+    /// only known once the order rests in the book
+    pub order_id: Option<u128>,
+    pub price: u64,
+    pub quantity: u64,
+    pub signatures: Vec<String>,
+    /// why the strategy placed or cancelled the order, and why it failed if it did
+    pub reason: String,
+}
+
+impl AuditRecord {
+    fn to_csv(&self, market: &str) -> String {
+        let side = match self.side {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        };
+        let order_id = self.order_id.map(|id| id.to_string()).unwrap_or_default();
+        [
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            csv_field(market),
+            self.action.as_str().to_string(),
+            self.status.as_str().to_string(),
+            side.to_string(),
+            self.client_order_id.to_string(),
+            order_id,
+            self.price.to_string(),
+            self.quantity.to_string(),
+            self.signatures.join(" "),
+            csv_field(&self.reason),
+        ]
+        .join(",")
+    }
+}
+
+/// Writes every order placed, cancelled and filled to append only csv files, one per utc day.
+pub struct AuditLog {
+    market: String,
+    directory: PathBuf,
+    /// the day of the file currently open
+    file: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl AuditLog {
+    pub fn new(config: &AuditLogConfig, market: &str) -> Self {
+        Self {
+            market: market.to_string(),
+            directory: PathBuf::from(&config.directory),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn write(&self, records: &[AuditRecord]) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+
+        for record in records {
+            let day = record.timestamp.date().naive_utc();
+            // a new file is started with the first record of each day
+            if !matches!(&*file, Some((d, _)) if *d == day) {
+                *file = Some((day, self.open(day)?));
+            }
+            if let Some((_, f)) = file.as_mut() {
+                writeln!(f, "{}", record.to_csv(&self.market))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn open(&self, day: NaiveDate) -> io::Result<File> {
+        fs::create_dir_all(&self.directory)?;
+        let path = file_path(&self.directory, day);
+        let mut f = OpenOptions::new().create(true).append(true).open(&path)?;
+        if f.metadata()?.len() == 0 {
+            writeln!(f, "{}", AUDIT_HEADER)?;
+        }
+        Ok(f)
+    }
+}
+
+/// copies the rows of the audit files from `from` to `to`, both included, under a single header,
+/// returns the number of rows copied
+pub fn export_audit_log(
+    directory: &Path,
+    from: NaiveDate,
+    to: NaiveDate,
+    output: &mut dyn Write,
+) -> io::Result<usize> {
+    let mut rows: usize = 0;
+    writeln!(output, "{}", AUDIT_HEADER)?;
+
+    let mut day = from;
+    while day <= to {
+        let path = file_path(directory, day);
+        day = day.succ();
+        if !path.exists() {
+            continue;
+        }
+
+        for line in BufReader::new(File::open(&path)?).lines().skip(1) {
+            writeln!(output, "{}", line?)?;
+            rows += 1;
+        }
+    }
+
+    Ok(rows)
+}
+
+fn file_path(directory: &Path, day: NaiveDate) -> PathBuf {
+    directory.join(format!("audit-{}.csv", day.format("%Y-%m-%d")))
+}
+
+/// quotes a field which contains a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,249 @@
+use {
+    crate::{
+        _fetch_cypher_user, _get_cypher_group, deposit_quote_token,
+        signer::TxSigner,
+        utils::{derive_quote_token_address, get_token_account},
+        withdraw_quote_token, MarketMakerError,
+    },
+    cypher::{constants::QUOTE_TOKEN_IDX, CypherGroup, CypherUser},
+    jet_proto_math::Number,
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::pubkey::Pubkey,
+    std::sync::Arc,
+    tokio::{
+        sync::{
+            broadcast::{channel, Receiver},
+            Mutex,
+        },
+        time::{sleep, Duration},
+    },
+};
+
+fn default_min_margin_ratio() -> f64 {
+    1.5
+}
+
+/// All amounts are in ui units of the quote token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapitalManagerConfig {
+    /// the quote balance the cypher account is brought back to
+    pub target: u64,
+    /// quote above this is swept back to the wallet
+    pub sweep_above: u64,
+    /// the account is topped up from the wallet when its quote falls below this
+    pub top_up_below: u64,
+    /// the largest amount moved in a single rebalance
+    pub max_transfer: u64,
+    /// rebalances which would move less than this are skipped
+    #[serde(default)]
+    pub min_transfer: u64,
+    /// withdrawals never bring the ratio between assets and liabilities below this
+    #[serde(default = "default_min_margin_ratio")]
+    pub min_margin_ratio: f64,
+    /// how often to rebalance while running, omit to only rebalance on command
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    /// log the transfers which would be made without sending them
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// A transfer between the wallet and the cypher account, in native units of the quote token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rebalance {
+    /// withdraw from the cypher account into the wallet
+    Sweep(u64),
+    /// deposit from the wallet into the cypher account
+    TopUp(u64),
+    Hold,
+}
+
+/// Keeps the quote in the cypher account between a floor and a ceiling by moving it
+/// to and from the owner's wallet.
+pub struct CapitalManager {
+    config: CapitalManagerConfig,
+    rpc_client: Arc<RpcClient>,
+    signer: Arc<dyn TxSigner>,
+    cypher_group_pubkey: Pubkey,
+    cypher_user_pubkey: Pubkey,
+    /// held for the whole of a rebalance, so the scheduled ones and those requested through the
+    /// control api never plan a transfer from the same balance
+    rebalance_lock: Mutex<()>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl CapitalManager {
+    pub fn new(
+        config: CapitalManagerConfig,
+        rpc_client: Arc<RpcClient>,
+        signer: Arc<dyn TxSigner>,
+        cypher_group_pubkey: Pubkey,
+        cypher_user_pubkey: Pubkey,
+        shutdown_receiver: Receiver<bool>,
+    ) -> Self {
+        Self {
+            config,
+            rpc_client,
+            signer,
+            cypher_group_pubkey,
+            cypher_user_pubkey,
+            rebalance_lock: Mutex::new(()),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    /// for one-off rebalances outside of the market maker, which never need to be shut down
+    pub fn new_standalone(
+        config: CapitalManagerConfig,
+        rpc_client: Arc<RpcClient>,
+        signer: Arc<dyn TxSigner>,
+        cypher_group_pubkey: Pubkey,
+        cypher_user_pubkey: Pubkey,
+    ) -> Self {
+        Self::new(
+            config,
+            rpc_client,
+            signer,
+            cypher_group_pubkey,
+            cypher_user_pubkey,
+            channel::<bool>(1).1,
+        )
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let interval = match self.config.interval_secs {
+            Some(i) => Duration::from_secs(i),
+            None => {
+                info!("[CAPMGR] No rebalance interval configured, only rebalancing on command.");
+                return;
+            }
+        };
+
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        loop {
+            tokio::select! {
+                _ = sleep(interval) => {
+                    if let Err(e) = self.rebalance(self.config.dry_run).await {
+                        warn!("[CAPMGR] Scheduled rebalance failed: {:?}", e);
+                    }
+                },
+                _ = shutdown.recv() => {
+                    info!("[CAPMGR] Received shutdown signal, stopping.");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// fetches the latest account state and moves quote if it is outside of the configured band,
+    /// in dry run mode the transfer is only logged
+    pub async fn rebalance(self: &Arc<Self>, dry_run: bool) -> Result<Rebalance, MarketMakerError> {
+        let _guard = self.rebalance_lock.lock().await;
+        let cypher_group =
+            _get_cypher_group(Arc::clone(&self.rpc_client), self.cypher_group_pubkey).await?;
+        let cypher_user =
+            _fetch_cypher_user(&self.cypher_user_pubkey, Arc::clone(&self.rpc_client)).await?;
+        let wallet_balance = self.get_wallet_balance().await;
+
+        let rebalance = self.plan(&cypher_user, &cypher_group, wallet_balance);
+        let dry_run = dry_run || self.config.dry_run;
+
+        match rebalance {
+            Rebalance::Hold => {
+                info!("[CAPMGR] Quote balance is within the configured band, nothing to do.");
+            }
+            Rebalance::Sweep(amount) if dry_run => {
+                info!("[CAPMGR] Dry run, would sweep {} native quote to the wallet.", amount);
+            }
+            Rebalance::TopUp(amount) if dry_run => {
+                info!("[CAPMGR] Dry run, would top up {} native quote from the wallet.", amount);
+            }
+            Rebalance::Sweep(amount) => {
+                info!("[CAPMGR] Sweeping {} native quote to the wallet.", amount);
+                withdraw_quote_token(
+                    self.signer.as_ref(),
+                    &self.cypher_user_pubkey,
+                    &cypher_group,
+                    Arc::clone(&self.rpc_client),
+                    amount,
+                )
+                .await?;
+            }
+            Rebalance::TopUp(amount) => {
+                info!("[CAPMGR] Topping up {} native quote from the wallet.", amount);
+                deposit_quote_token(
+                    self.signer.as_ref(),
+                    &self.cypher_user_pubkey,
+                    &cypher_group,
+                    Arc::clone(&self.rpc_client),
+                    Number::from(amount),
+                )
+                .await?;
+            }
+        }
+
+        Ok(rebalance)
+    }
+
+    fn plan(
+        &self,
+        cypher_user: &CypherUser,
+        cypher_group: &CypherGroup,
+        wallet_balance: u64,
+    ) -> Rebalance {
+        let quote_token = cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        let multiplier = 10_u64.pow(quote_token.decimals().into()) as i128;
+        let to_native = |amount: u64| amount as i128 * multiplier;
+
+        let quote_balance = match cypher_user.get_position(QUOTE_TOKEN_IDX) {
+            Some(position) => {
+                position.total_deposits(quote_token).as_u64(0) as i128
+                    - position.total_borrows(quote_token).as_u64(0) as i128
+            }
+            None => 0,
+        };
+        let target = to_native(self.config.target);
+
+        if quote_balance > to_native(self.config.sweep_above) {
+            // withdrawing reduces the assets but not the liabilities
+            let assets = cypher_user.get_assets_value(cypher_group).as_u64(0) as f64;
+            let liabilities = cypher_user.get_liabilities_value(cypher_group).as_u64(0) as f64;
+            let margin_headroom = if liabilities > 0.0 {
+                (assets - liabilities * self.config.min_margin_ratio).max(0.0) as i128
+            } else {
+                i128::MAX
+            };
+            self.limit(margin_headroom.min(quote_balance - target), multiplier)
+                .map_or(Rebalance::Hold, Rebalance::Sweep)
+        } else if quote_balance < to_native(self.config.top_up_below) {
+            self.limit((wallet_balance as i128).min(target - quote_balance), multiplier)
+                .map_or(Rebalance::Hold, Rebalance::TopUp)
+        } else {
+            Rebalance::Hold
+        }
+    }
+
+    /// caps the amount at the maximum transfer, `None` if it is below the minimum transfer
+    fn limit(&self, amount: i128, multiplier: i128) -> Option<u64> {
+        let amount = amount.min(self.config.max_transfer as i128 * multiplier);
+        if amount <= 0 || amount < self.config.min_transfer as i128 * multiplier {
+            info!(
+                "[CAPMGR] Quote balance is outside of the configured band but only {} native quote can be moved, skipping.",
+                amount.max(0)
+            );
+            return None;
+        }
+        Some(amount as u64)
+    }
+
+    async fn get_wallet_balance(self: &Arc<Self>) -> u64 {
+        let ata = derive_quote_token_address(self.signer.pubkey());
+        match get_token_account(Arc::clone(&self.rpc_client), &ata).await {
+            Ok(ta) => ta.amount.parse().unwrap_or_default(),
+            Err(_) => 0,
+        }
+    }
+}
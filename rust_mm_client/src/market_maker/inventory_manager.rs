@@ -1,13 +1,14 @@
 use {
-    crate::config::MarketMakerConfig,
-    cypher::{CypherGroup, CypherUser},
+    crate::{config::MarketMakerConfig, metrics},
+    cypher::{constants::QUOTE_TOKEN_IDX, CypherGroup, CypherUser},
     jet_proto_math::Number,
     log::info,
+    prometheus::{Gauge, IntGauge},
     serde::{Deserialize, Serialize},
-    std::sync::Arc,
+    std::sync::{Arc, RwLock},
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InventoryManagerConfig {
     pub initial_capital: u64,
@@ -17,14 +18,83 @@ pub struct InventoryManagerConfig {
     pub spread: u8,
 }
 
+struct InventoryManagerMetrics {
+    delta: IntGauge,
+    assets_value: Gauge,
+    liabilities_value: Gauge,
+    pnl: Gauge,
+    margin_ratio: Gauge,
+}
+
+impl InventoryManagerMetrics {
+    fn new(market: &str) -> Self {
+        Self {
+            delta: metrics::int_gauge(
+                "mm_position_delta",
+                "Net base position of the account in the market.",
+                market,
+            ),
+            assets_value: metrics::gauge(
+                "mm_assets_value",
+                "Value of the account's assets, in quote.",
+                market,
+            ),
+            liabilities_value: metrics::gauge(
+                "mm_liabilities_value",
+                "Value of the account's liabilities, in quote.",
+                market,
+            ),
+            pnl: metrics::gauge(
+                "mm_pnl",
+                "Value of the account net of liabilities minus the initial capital configured at startup, in quote.",
+                market,
+            ),
+            margin_ratio: metrics::gauge(
+                "mm_margin_ratio",
+                "Ratio between the account's assets and liabilities.",
+                market,
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.delta);
+        metrics::register(&self.assets_value);
+        metrics::register(&self.liabilities_value);
+        metrics::register(&self.pnl);
+        metrics::register(&self.margin_ratio);
+    }
+}
+
+/// The parameters of the inventory manager which can be changed while the market maker is running.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryParams {
+    pub max_quote: i64,
+    pub shape_num: u32,
+    pub shape_denom: u32,
+    pub spread: u8,
+}
+
+impl From<&InventoryManagerConfig> for InventoryParams {
+    fn from(config: &InventoryManagerConfig) -> Self {
+        Self {
+            max_quote: config.max_quote,
+            shape_num: config.shape_num,
+            shape_denom: config.shape_denom,
+            spread: config.spread,
+        }
+    }
+}
+
 pub struct InventoryManager {
-    config: Arc<MarketMakerConfig>,
+    config: RwLock<Arc<MarketMakerConfig>>,
     decimals: u8,
     market_idx: usize,
-    max_quote: i64,
-    shape_num: u32,
-    shape_denom: u32,
-    spread: u8,
+    params: RwLock<InventoryParams>,
+    /// the pnl baseline, taken from the config once so later config changes do not move the pnl
+    initial_capital: f64,
+    metrics: InventoryManagerMetrics,
 }
 
 #[derive(Debug, Default)]
@@ -41,13 +111,12 @@ const BPS_UNIT: u64 = 10000;
 impl InventoryManager {
     pub fn default() -> Self {
         Self {
-            config: Arc::new(MarketMakerConfig::default()),
+            config: RwLock::new(Arc::new(MarketMakerConfig::default())),
             decimals: u8::default(),
             market_idx: usize::default(),
-            max_quote: i64::default(),
-            shape_num: u32::default(),
-            shape_denom: u32::default(),
-            spread: u8::default(),
+            params: RwLock::new(InventoryParams::default()),
+            initial_capital: f64::default(),
+            metrics: InventoryManagerMetrics::new(""),
         }
     }
 
@@ -60,17 +129,46 @@ impl InventoryManager {
         shape_denom: u32,
         spread: u8,
     ) -> Self {
+        let metrics = InventoryManagerMetrics::new(&config.market.name);
+        metrics.register();
+        let initial_capital = config.inventory_manager_config.initial_capital as f64;
+
         Self {
-            config,
+            config: RwLock::new(config),
             decimals,
             market_idx: market_index,
-            max_quote,
-            shape_num,
-            shape_denom,
-            spread,
+            params: RwLock::new(InventoryParams {
+                max_quote,
+                shape_num,
+                shape_denom,
+                spread,
+            }),
+            initial_capital,
+            metrics,
         }
     }
 
+    fn config(&self) -> Arc<MarketMakerConfig> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    /// replaces the config after a reload, the parameters are set separately with `set_params`
+    pub fn set_config(&self, config: Arc<MarketMakerConfig>) {
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn params(&self) -> InventoryParams {
+        *self.params.read().unwrap()
+    }
+
+    pub fn set_params(&self, params: InventoryParams) {
+        info!(
+            "[INVMGR-{}] Updating parameters: {:?}",
+            self.config().market.name, params
+        );
+        *self.params.write().unwrap() = params;
+    }
+
     pub async fn get_quote_volumes(
         &self,
         user: &CypherUser,
@@ -78,11 +176,12 @@ impl InventoryManager {
     ) -> QuoteVolumes {
         let current_delta = self.get_user_delta(user, group);
 
-        let adjusted_vol = self.adj_quote_size(current_delta.abs().try_into().unwrap());
+        let params = self.params();
+        let adjusted_vol = self.adj_quote_size(&params, current_delta.abs().try_into().unwrap());
         let (bid_size, ask_size) = if current_delta < 0 {
-            (self.max_quote as i128, adjusted_vol)
+            (params.max_quote as i128, adjusted_vol)
         } else {
-            (adjusted_vol, self.max_quote as i128)
+            (adjusted_vol, params.max_quote as i128)
         };
         QuoteVolumes {
             delta: current_delta,
@@ -107,11 +206,13 @@ impl InventoryManager {
 
         info!(
             "[INVMGR-{}] Base Borrows: {}. Base Deposits: {}",
-            self.config.market.name,
+            self.config().market.name,
             user_pos.base_borrows(),
             user_pos.base_deposits(),
         );
-        let div: Number = 10_u64.checked_pow(6).unwrap().into();
+        let quote_token = cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        let quote_divisor = 10_u64.checked_pow(quote_token.decimals().into()).unwrap();
+        let div: Number = quote_divisor.into();
         let c_asset_divisor = 10_u64.checked_pow(self.decimals as u32).unwrap();
 
         let long_pos = user_pos.base_deposits().as_u64(0) as i64 / c_asset_divisor as i64;
@@ -121,12 +222,12 @@ impl InventoryManager {
 
         info!(
             "[INVMGR-{}] Open Orders Coin Free: {}. Open Orders Coin Total: {}.",
-            self.config.market.name, user_pos.oo_info.coin_free, user_pos.oo_info.coin_total,
+            self.config().market.name, user_pos.oo_info.coin_free, user_pos.oo_info.coin_total,
         );
 
         info!(
             "[INVMGR-{}] Open Orders Price Coin Free: {}. Open Orders Price Coin Total: {}.",
-            self.config.market.name, user_pos.oo_info.pc_free, user_pos.oo_info.pc_total,
+            self.config().market.name, user_pos.oo_info.pc_free, user_pos.oo_info.pc_total,
         );
 
         let assets_val = cypher_user.get_assets_value(cypher_group);
@@ -136,21 +237,47 @@ impl InventoryManager {
 
         info!(
             "[INVMGR-{}] Assets value: {} - Liabilities value: {} ",
-            self.config.market.name, assets_val_ui, liabs_val_ui
+            self.config().market.name, assets_val_ui, liabs_val_ui
         );
 
+        let assets_value = assets_val.as_u64(0) as f64 / quote_divisor as f64;
+        let liabilities_value = liabs_val.as_u64(0) as f64 / quote_divisor as f64;
+        self.metrics.delta.set(delta);
+        self.metrics.assets_value.set(assets_value);
+        self.metrics.liabilities_value.set(liabilities_value);
+        self.metrics
+            .pnl
+            .set(assets_value - liabilities_value - self.initial_capital);
+        if liabilities_value > 0.0 {
+            self.metrics
+                .margin_ratio
+                .set(assets_value / liabilities_value);
+        }
+
         delta
     }
 
-    fn adj_quote_size(&self, abs_delta: u32) -> i128 {
-        let shaped_delta = self.shape_num * abs_delta;
-        let divided_shaped_delta = shaped_delta / self.shape_denom;
+    fn adj_quote_size(&self, params: &InventoryParams, abs_delta: u32) -> i128 {
+        let shaped_delta = params.shape_num * abs_delta;
+        let divided_shaped_delta = shaped_delta / params.shape_denom;
         let divisor: i128 = EXP_BASE.pow(divided_shaped_delta).into();
-        self.max_quote as i128 / divisor
+        params.max_quote as i128 / divisor
+    }
+
+    /// the user's net position in the market in native units of the base asset
+    pub fn get_native_position(&self, cypher_user: &CypherUser) -> i128 {
+        match cypher_user.get_position(self.market_idx) {
+            Some(position) => {
+                position.base_deposits().as_u64(0) as i128
+                    - position.base_borrows().as_u64(0) as i128
+            }
+            None => 0,
+        }
     }
 
     pub fn get_spread(&self, oracle_price: u64) -> (u64, u64) {
-        let num = (BPS_UNIT + self.spread as u64) as f64 / BPS_UNIT as f64;
+        let spread = self.params().spread;
+        let num = (BPS_UNIT + spread as u64) as f64 / BPS_UNIT as f64;
         let best_ask = oracle_price as f64 * num;
         let best_bid = oracle_price as f64 / num;
 
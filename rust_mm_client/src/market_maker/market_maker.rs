@@ -3,27 +3,36 @@
 use solana_client::nonblocking::pubsub_client::PubsubClient;
 use {
     super::order_manager::OrderManager,
-    super::{InventoryManager, Worker, WorkerConfig},
+    super::{
+        AuditLog, CapitalManager, InventoryManager, PnlTracker, StateStore, Worker, WorkerConfig,
+    },
     crate::providers::{CypherAccountProvider, CypherGroupProvider, OpenOrdersProvider},
     crate::{
         accounts_cache::AccountsCache,
-        config::{cypher_config::CypherConfig, MarketMakerConfig},
-        providers::orderbook_provider::{OrderBook, OrderBookProvider},
-        services::{AccountInfoService, ChainMetaService},
+        config::{cypher_config::CypherConfig, ConfigOverrides, MarketMakerConfig},
+        orderbook::OrderBookUpdate,
+        providers::orderbook_provider::OrderBookProvider,
+        services::{
+            AccountInfoService, ChainMetaService, ConfigWatcherService, ControlRequest,
+            ControlService, MetricsService,
+        },
+        signer::TxSigner,
         MarketMakerError,
     },
     anchor_lang::AnchorDeserialize,
     cypher::utils::{derive_open_orders_address, parse_dex_account},
-    cypher::{CypherGroup, CypherUser},
+    cypher::{constants::QUOTE_TOKEN_IDX, CypherGroup, CypherUser},
     log::{info, warn},
     safe_transmute::transmute_to_bytes,
     serum_dex::state::{MarketStateV2, OpenOrders},
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
-    solana_sdk::signature::Keypair,
     solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
     std::convert::identity,
     std::{str::FromStr, sync::Arc},
-    tokio::sync::broadcast::{channel, Receiver, Sender},
+    tokio::sync::{
+        broadcast::{channel, Receiver, Sender},
+        mpsc,
+    },
     tokio::task::JoinHandle,
 };
 
@@ -35,8 +44,12 @@ pub struct MarketMaker {
     polling_keys: Vec<Pubkey>,
     ai_service: AccountInfoService,
     cm_service: Arc<ChainMetaService>,
+    metrics_service: Option<Arc<MetricsService>>,
+    control_service: Option<Arc<ControlService>>,
+    config_watcher_service: Arc<ConfigWatcherService>,
     inventory_manager: Arc<InventoryManager>,
     order_manager: Arc<OrderManager>,
+    capital_manager: Option<Arc<CapitalManager>>,
 
     // providers
     accounts_cache: AccountsCacheWrapper,
@@ -50,16 +63,23 @@ pub struct MarketMaker {
 
     // the configs
     config: Arc<MarketMakerConfig>,
+    config_path: String,
+    config_overrides: ConfigOverrides,
     cypher_config: Arc<CypherConfig>,
 
-    owner_keypair: Arc<Keypair>,
+    signer: Arc<dyn TxSigner>,
+    /// `None` when trading as a delegate of an owner key which is kept offline
+    owner: Option<Arc<dyn TxSigner>>,
     cypher_user_pubkey: Pubkey,
     cypher_user: Box<CypherUser>,
     cypher_group_pubkey: Pubkey,
     cypher_group: Box<CypherGroup>,
 
     // async tasks
+    /// requests the shutdown, the worker stops quoting and cancels its orders when it is sent
     shutdown_sender: Arc<Sender<bool>>,
+    /// stops the services and providers, only sent once the worker has finished cancelling
+    services_shutdown_sender: Arc<Sender<bool>>,
     tasks: Vec<JoinHandle<()>>,
 }
 
@@ -69,10 +89,13 @@ impl MarketMaker {
         rpc_client: Arc<RpcClient>,
         pubsub_client: Arc<PubsubClient>,
         config: Arc<MarketMakerConfig>,
+        config_path: &str,
+        config_overrides: ConfigOverrides,
         cypher_config: Arc<CypherConfig>,
         cypher_group: Box<CypherGroup>,
         cypher_group_pubkey: Pubkey,
-        owner_keypair: Keypair,
+        signer: Arc<dyn TxSigner>,
+        owner: Option<Arc<dyn TxSigner>>,
         cypher_user: Box<CypherUser>,
         cypher_user_pubkey: Pubkey,
         shutdown_sender: Sender<bool>,
@@ -81,13 +104,17 @@ impl MarketMaker {
             rpc_client,
             pubsub_client,
             config,
+            config_path: config_path.to_string(),
+            config_overrides,
             cypher_config,
-            owner_keypair: Arc::new(owner_keypair),
+            signer,
+            owner,
             cypher_user,
             cypher_user_pubkey,
             cypher_group,
             cypher_group_pubkey,
             shutdown_sender: Arc::new(shutdown_sender),
+            services_shutdown_sender: Arc::new(channel::<bool>(1).0),
             tasks: Vec::new(),
             worker: Worker::default(),
             polling_keys: Vec::new(),
@@ -98,8 +125,12 @@ impl MarketMaker {
             open_orders_provider: OpenOrdersProviderWrapper::default(),
             ai_service: AccountInfoService::default().await,
             cm_service: Arc::new(ChainMetaService::default()),
+            metrics_service: None,
+            control_service: None,
+            config_watcher_service: Arc::new(ConfigWatcherService::default()),
             inventory_manager: Arc::new(InventoryManager::default()),
             order_manager: Arc::new(OrderManager::default()),
+            capital_manager: None,
         }
     }
 
@@ -127,6 +158,32 @@ impl MarketMaker {
         });
         self.tasks.push(cm_t);
 
+        if let Some(metrics_service) = self.metrics_service {
+            let metrics_t = tokio::spawn(async move {
+                metrics_service.start_service().await;
+            });
+            self.tasks.push(metrics_t);
+        }
+
+        let cw_t = tokio::spawn(async move {
+            self.config_watcher_service.start_service().await;
+        });
+        self.tasks.push(cw_t);
+
+        if let Some(control_service) = self.control_service {
+            let control_t = tokio::spawn(async move {
+                control_service.start_service().await;
+            });
+            self.tasks.push(control_t);
+        }
+
+        if let Some(capital_manager) = self.capital_manager {
+            let capital_t = tokio::spawn(async move {
+                capital_manager.start().await;
+            });
+            self.tasks.push(capital_t);
+        }
+
         // start the providers
         let group_t = tokio::spawn(async move {
             self.cypher_group_provider.provider.start().await;
@@ -156,7 +213,22 @@ impl MarketMaker {
         let worker_t = tokio::spawn(async move {
             self.worker.start().await;
         });
-        self.tasks.push(worker_t);
+
+        // the services and providers keep running until the worker has cancelled its orders,
+        // which needs recent blockhashes and the open orders updates
+        match tokio::join!(worker_t) {
+            (Ok(_),) => (),
+            (Err(e),) => {
+                warn!(
+                    "There was an error joining with the worker: {}",
+                    e.to_string()
+                );
+            }
+        };
+        info!("Worker stopped, stopping services and providers.");
+        if self.services_shutdown_sender.send(true).is_err() {
+            warn!("Failed to send the shutdown signal to the services.");
+        }
 
         for task in self.tasks {
             let res = tokio::join!(task);
@@ -184,9 +256,16 @@ impl MarketMaker {
 
         self.cm_service = Arc::new(ChainMetaService::new(
             Arc::clone(&self.rpc_client),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
         ));
 
+        self.metrics_service = self.config.metrics_config.as_ref().map(|metrics_config| {
+            Arc::new(MetricsService::new(
+                metrics_config,
+                self.services_shutdown_sender.subscribe(),
+            ))
+        });
+
         // process the market configs before proceeding
         let pcfg_res = self.process_market_configs().await;
         match pcfg_res {
@@ -206,8 +285,9 @@ impl MarketMaker {
             Arc::clone(&self.accounts_cache.cache),
             Arc::clone(&self.pubsub_client),
             Arc::clone(&self.rpc_client),
+            Arc::clone(&self.cm_service),
             &self.polling_keys,
-            self.shutdown_sender.clone(),
+            Arc::clone(&self.services_shutdown_sender),
         );
 
         Ok(())
@@ -254,19 +334,21 @@ impl MarketMaker {
             symbol: market_config.name.to_string(),
         };
 
-        let (ob_s, ob_r) = channel::<Arc<OrderBook>>(u16::MAX as usize);
+        let (ob_s, ob_r) = channel::<Arc<OrderBookUpdate>>(u16::MAX as usize);
         let arc_ob_s = Arc::new(ob_s);
         let ob_provider = Arc::new(OrderBookProvider::new(
             Arc::clone(&self.accounts_cache.cache),
             Arc::clone(&arc_ob_s),
             self.accounts_cache.sender.subscribe(),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
             market_pubkey,
+            open_orders_pubkey,
             market_bids,
             market_asks,
             market_state.coin_lot_size,
             market_state.pc_lot_size,
             0_u64,
+            mm_market_config.book_depth,
         ));
 
         let (ca_s, ca_r) = channel::<Box<CypherUser>>(u16::MAX as usize);
@@ -275,7 +357,7 @@ impl MarketMaker {
             Arc::clone(&self.accounts_cache.cache),
             Arc::clone(&arc_ca_s),
             self.accounts_cache.sender.subscribe(),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
             self.cypher_user_pubkey,
         ));
 
@@ -285,7 +367,7 @@ impl MarketMaker {
             Arc::clone(&self.accounts_cache.cache),
             Arc::clone(&arc_cg_s),
             self.accounts_cache.sender.subscribe(),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
             self.cypher_group_pubkey,
         ));
 
@@ -295,7 +377,7 @@ impl MarketMaker {
             Arc::clone(&self.accounts_cache.cache),
             Arc::clone(&arc_oo_s),
             self.accounts_cache.sender.subscribe(),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
             open_orders_pubkey,
         ));
 
@@ -309,27 +391,109 @@ impl MarketMaker {
             self.config.inventory_manager_config.spread,
         ));
 
+        let state_store = match &self.config.state_store_config {
+            Some(_) if self.config.dry_run_config.is_some() => {
+                warn!("Dry running, the state store is not used.");
+                None
+            }
+            Some(state_store_config) => match StateStore::open(state_store_config) {
+                Ok(s) => Some(Arc::new(s)),
+                Err(e) => {
+                    warn!(
+                        "Failed to open the state store at {}: {}",
+                        state_store_config.directory, e
+                    );
+                    return Err(MarketMakerError::StateStoreError(e));
+                }
+            },
+            None => None,
+        };
+        let quote_token = self.cypher_group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+        let pnl_tracker = Arc::new(PnlTracker::new(
+            worker_config.symbol.to_string(),
+            self.config.pnl_config.clone(),
+            quote_token.decimals(),
+            state_store.as_ref().map(Arc::clone),
+        ));
+
+        // dry runs are written too, with their records flagged as simulated
+        let audit_log = match &self.config.audit_log_config {
+            Some(audit_log_config) => Some(Arc::new(AuditLog::new(
+                audit_log_config,
+                &worker_config.symbol,
+            ))),
+            None => None,
+        };
+
         self.order_manager = Arc::new(OrderManager::new(
             worker_config.symbol.to_string(),
+            self.config.order_manager_config.clone(),
             Arc::clone(&self.rpc_client),
             Arc::clone(&self.cm_service),
             arc_oo_s.subscribe(),
             arc_ob_s.subscribe(),
-            self.shutdown_sender.subscribe(),
+            self.services_shutdown_sender.subscribe(),
             market_state,
-            Arc::clone(&self.owner_keypair),
+            Arc::clone(&self.signer),
             self.cypher_user_pubkey,
-            open_orders_pubkey
+            open_orders_pubkey,
+            self.config.dry_run_config.clone(),
+            pnl_tracker,
+            state_store,
+            audit_log,
+        ));
+
+        if self.config.capital_manager_config.is_some() && self.owner.is_none() {
+            warn!("Trading as a delegate, the capital manager needs the owner key and is disabled. Run `rebalance` with the owner key instead.");
+        }
+        self.capital_manager = self
+            .config
+            .capital_manager_config
+            .as_ref()
+            .zip(self.owner.as_ref())
+            .map(|(capital_manager_config, owner)| {
+                let mut capital_manager_config = capital_manager_config.clone();
+                capital_manager_config.dry_run |= self.config.dry_run_config.is_some();
+                Arc::new(CapitalManager::new(
+                    capital_manager_config,
+                    Arc::clone(&self.rpc_client),
+                    Arc::clone(owner),
+                    self.cypher_group_pubkey,
+                    self.cypher_user_pubkey,
+                    self.shutdown_sender.subscribe(),
+                ))
+            });
+
+        // operator commands are forwarded to the worker, which applies them between requotes
+        let (control_s, control_r) = mpsc::channel::<ControlRequest>(16);
+        self.control_service = self.config.control_config.as_ref().map(|control_config| {
+            Arc::new(ControlService::new(
+                control_config,
+                control_s.clone(),
+                self.capital_manager.clone(),
+                self.shutdown_sender.subscribe(),
+            ))
+        });
+        self.config_watcher_service = Arc::new(ConfigWatcherService::new(
+            &self.config_path,
+            self.config_overrides.clone(),
+            Arc::clone(&self.config),
+            control_s,
+            self.shutdown_sender.subscribe(),
         ));
 
         self.worker = Worker::new(
             worker_config,
+            Arc::clone(&self.config),
+            self.config.requote_config.clone(),
             Arc::clone(&self.inventory_manager),
             Arc::clone(&self.order_manager),
             arc_ca_s.subscribe(),
             arc_cg_s.subscribe(),
+            arc_ob_s.subscribe(),
+            arc_oo_s.subscribe(),
+            control_r,
             Arc::clone(&self.shutdown_sender),
-            Arc::clone(&self.owner_keypair),
             self.cypher_user_pubkey,
             open_orders_pubkey,
         );
@@ -364,7 +528,7 @@ impl MarketMaker {
     }
 }
 
-async fn get_serum_market(
+pub async fn get_serum_market(
     client: Arc<RpcClient>,
     market: Pubkey,
 ) -> Result<MarketStateV2, ClientError> {
@@ -406,16 +570,16 @@ impl AccountsCacheWrapper {
 
 struct OrderBookProviderWrapper {
     provider: Arc<OrderBookProvider>,
-    sender: Arc<Sender<Arc<OrderBook>>>,
-    receiver: Receiver<Arc<OrderBook>>,
+    sender: Arc<Sender<Arc<OrderBookUpdate>>>,
+    receiver: Receiver<Arc<OrderBookUpdate>>,
 }
 
 impl OrderBookProviderWrapper {
     pub fn default() -> Self {
         Self {
             provider: Arc::new(OrderBookProvider::default()),
-            sender: Arc::new(channel::<Arc<OrderBook>>(u16::MAX as usize).0),
-            receiver: channel::<Arc<OrderBook>>(u16::MAX as usize).1,
+            sender: Arc::new(channel::<Arc<OrderBookUpdate>>(u16::MAX as usize).0),
+            receiver: channel::<Arc<OrderBookUpdate>>(u16::MAX as usize).1,
         }
     }
 }
@@ -1,11 +1,21 @@
+pub mod audit_log;
+pub mod capital_manager;
 pub mod inventory_manager;
 pub mod market_maker;
 pub mod order_manager;
+pub mod pnl_tracker;
+pub mod requote_policy;
+pub mod state_store;
 pub mod utils;
 pub mod worker;
 
+pub use audit_log::*;
+pub use capital_manager::*;
 pub use inventory_manager::*;
 pub use market_maker::*;
 pub use order_manager::*;
+pub use pnl_tracker::*;
+pub use requote_policy::*;
+pub use state_store::*;
 pub use utils::*;
 pub use worker::*;
@@ -1,17 +1,28 @@
 use {
-    super::QuoteVolumes,
+    super::{
+        AuditAction, AuditLog, AuditRecord, AuditStatus, PnlTracker, QuoteVolumes, RequotePolicy,
+        StaleReason, StateEvent, StateStore,
+    },
     crate::{
+        _fetch_open_orders,
+        error::{OrderError, OrderRejection, ProgramErrorCode, TxError},
         fast_tx_builder::FastTxnBuilder,
-        market_maker::{get_cancel_order_ix, get_new_order_ix},
-        providers::OrderBook,
+        market_maker::{get_cancel_order_ix, get_new_order_ix, get_settle_funds_ix},
+        metrics,
+        orderbook::{OrderBook, OrderBookUpdate, QueueTracker, TrackedQueuePosition},
         serum_slab::OrderBookOrder,
         services::ChainMetaService,
+        signer::{KeypairSigner, TxSigner},
+        tx_logs::TxLogs,
+        utils::unix_timestamp,
         MarketMakerError,
     },
+    chrono::Utc,
     cypher::{
         CypherGroup, CypherMarket, CypherToken,
     },
-    log::{info, warn},
+    log::{debug, info, warn},
+    prometheus::{Histogram, IntCounter, IntCounterVec, IntGauge},
     serde::{Deserialize, Serialize},
     serum_dex::{
         instruction::{CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior},
@@ -21,24 +32,87 @@ use {
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
     solana_sdk::{
         hash::Hash,
-        instruction::Instruction,
+        instruction::{Instruction, InstructionError},
         pubkey::Pubkey,
         signature::{Keypair, Signature},
-        transaction::Transaction,
+        transaction::{Transaction, TransactionError},
+    },
+    solana_transaction_status::UiTransactionEncoding,
+    std::{
+        collections::HashMap,
+        num::NonZeroU64,
+        sync::Arc,
+        time::{Duration, Instant},
     },
-    std::{num::NonZeroU64, sync::Arc},
     tokio::sync::{
         broadcast::{channel, Receiver},
         Mutex, RwLock,
     },
 };
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderManagerConfig {
     pub layers: u8,
     pub spacing_bps: u8,
     pub step_amount: u32,
+    /// resting orders priced within this many bps of the desired price are kept
+    #[serde(default)]
+    pub price_tolerance_bps: u16,
+    /// resting orders sized within this many lots of the desired size are kept
+    #[serde(default)]
+    pub size_tolerance_lots: u64,
+    /// resting orders older than this are replaced even if they are within tolerance, zero disables it
+    #[serde(default)]
+    pub max_order_age_secs: u64,
+    /// orders with queue priority are kept while their size is within this many bps of the desired size
+    #[serde(default)]
+    pub queue_priority_size_tolerance_bps: u16,
+    /// an order has queue priority while the size ahead of it is at most this share of its price level
+    #[serde(default)]
+    pub queue_priority_max_size_ahead_bps: u16,
+    /// orders are placed with `max_ts` this many seconds ahead so they stop resting if the market
+    /// maker goes away, zero places them without an expiry
+    #[serde(default)]
+    pub order_ttl_secs: u64,
+    /// orders expiring within this many seconds are replaced, must be shorter than the ttl
+    #[serde(default)]
+    pub order_ttl_refresh_secs: u64,
+    /// what to do with the orders a previous run left resting, checked once at startup
+    #[serde(default)]
+    pub existing_orders: ExistingOrders,
+}
+
+/// What to do at startup with the orders found in the open orders account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExistingOrders {
+    /// manage them like the orders placed by this run, they are replaced once they are stale and
+    /// right away with an order ttl since their `max_ts` is not known
+    Adopt,
+    /// cancel them before quoting, with the timeouts of `shutdownConfig`
+    Cancel,
+}
+
+impl Default for ExistingOrders {
+    fn default() -> Self {
+        Self::Cancel
+    }
+}
+
+/// Runs the full pipeline without sending anything, the transactions which would be sent are
+/// logged and optionally simulated against the current state of the cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DryRunConfig {
+    /// whether to simulate the transactions, which reports whether they would have succeeded
+    pub simulate: bool,
+}
+
+impl Default for DryRunConfig {
+    fn default() -> Self {
+        Self { simulate: true }
+    }
 }
 
 // for level in range(0, self._buy_levels):
@@ -56,6 +130,14 @@ pub struct OrderManagerConfig {
 //     if size > 0:
 //         sells.append(PriceSize(price, size))
 
+// the pc quantity of taker orders is padded by this much to account for fees
+const TAKER_FEE_BUFFER_BPS: u64 = 100;
+const BPS_UNIT: u64 = 10_000;
+// cancel instructions carry a lot of accounts, this keeps the transactions below the size limit
+const MAX_CANCELS_PER_TX: usize = 4;
+
+/// A resting order, with its price in lots and its quantity in native units.
+#[derive(Clone)]
 pub struct ManagedOrder {
     pub order_id: u128,
     pub client_order_id: u64,
@@ -64,6 +146,127 @@ pub struct ManagedOrder {
     pub side: Side,
 }
 
+struct OrderManagerMetrics {
+    resting_orders: IntGauge,
+    quoted_bid_price: IntGauge,
+    quoted_ask_price: IntGauge,
+    quoted_bid_size: IntGauge,
+    quoted_ask_size: IntGauge,
+    quoted_spread_bps: IntGauge,
+    requotes: IntCounter,
+    transactions_sent: IntCounter,
+    transactions_landed: IntCounter,
+    transactions_failed: IntCounter,
+    confirmation_latency: Histogram,
+    transactions_simulated: IntCounter,
+    simulations_failed: IntCounter,
+    order_rejections: IntCounterVec,
+}
+
+impl OrderManagerMetrics {
+    fn new(market: &str) -> Self {
+        Self {
+            resting_orders: metrics::int_gauge(
+                "mm_resting_orders",
+                "Number of our orders resting in the book.",
+                market,
+            ),
+            quoted_bid_price: metrics::int_gauge(
+                "mm_quoted_bid_price",
+                "Price of the bid we are quoting.",
+                market,
+            ),
+            quoted_ask_price: metrics::int_gauge(
+                "mm_quoted_ask_price",
+                "Price of the ask we are quoting.",
+                market,
+            ),
+            quoted_bid_size: metrics::int_gauge(
+                "mm_quoted_bid_size",
+                "Size of the bid we are quoting.",
+                market,
+            ),
+            quoted_ask_size: metrics::int_gauge(
+                "mm_quoted_ask_size",
+                "Size of the ask we are quoting.",
+                market,
+            ),
+            quoted_spread_bps: metrics::int_gauge(
+                "mm_quoted_spread_bps",
+                "Spread between our quotes relative to their mid price.",
+                market,
+            ),
+            requotes: metrics::int_counter(
+                "mm_requotes_total",
+                "Number of times our orders were cancelled or replaced.",
+                market,
+            ),
+            transactions_sent: metrics::int_counter(
+                "mm_transactions_sent_total",
+                "Number of transactions sent.",
+                market,
+            ),
+            transactions_landed: metrics::int_counter(
+                "mm_transactions_landed_total",
+                "Number of transactions confirmed.",
+                market,
+            ),
+            transactions_failed: metrics::int_counter(
+                "mm_transactions_failed_total",
+                "Number of transactions which failed to land.",
+                market,
+            ),
+            confirmation_latency: metrics::histogram(
+                "mm_transaction_confirmation_seconds",
+                "Time between sending a transaction and its confirmation.",
+                market,
+                vec![0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0],
+            ),
+            transactions_simulated: metrics::int_counter(
+                "mm_transactions_simulated_total",
+                "Number of transactions simulated instead of sent in dry run mode.",
+                market,
+            ),
+            simulations_failed: metrics::int_counter(
+                "mm_simulations_failed_total",
+                "Number of simulated transactions which would have failed.",
+                market,
+            ),
+            order_rejections: metrics::int_counter_vec(
+                "mm_order_rejections_total",
+                "Number of order transactions the program rejected, by reason.",
+                market,
+                &["reason"],
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.resting_orders);
+        metrics::register(&self.quoted_bid_price);
+        metrics::register(&self.quoted_ask_price);
+        metrics::register(&self.quoted_bid_size);
+        metrics::register(&self.quoted_ask_size);
+        metrics::register(&self.quoted_spread_bps);
+        metrics::register(&self.requotes);
+        metrics::register(&self.transactions_sent);
+        metrics::register(&self.transactions_landed);
+        metrics::register(&self.transactions_failed);
+        metrics::register(&self.confirmation_latency);
+        metrics::register(&self.transactions_simulated);
+        metrics::register(&self.simulations_failed);
+        metrics::register(&self.order_rejections);
+    }
+}
+
+/// How many ticks our quotes are moved away from the desired prices after post only orders
+/// were rejected for crossing the book, reset once an order on that side is placed.
+#[derive(Debug, Default, Clone, Copy)]
+struct PostOnlyOffsets {
+    bid: u64,
+    ask: u64,
+}
+
 #[derive(Debug, Default)]
 pub struct InflightOrders {
     pub new_orders: RwLock<Vec<u64>>,
@@ -72,55 +275,91 @@ pub struct InflightOrders {
 
 pub struct OrderManager {
     symbol: String,
+    config: RwLock<OrderManagerConfig>,
+    requote_policy: RwLock<RequotePolicy>,
     rpc_client: Arc<RpcClient>,
     chain_meta_service: Arc<ChainMetaService>,
     oo_receiver: Mutex<Receiver<OpenOrders>>,
-    ob_receiver: Mutex<Receiver<Arc<OrderBook>>>,
+    ob_receiver: Mutex<Receiver<Arc<OrderBookUpdate>>>,
     shutdown_receiver: Mutex<Receiver<bool>>,
     market_state: Option<MarketStateV2>,
     open_orders: RwLock<Option<OpenOrders>>,
     orderbook: RwLock<Arc<OrderBook>>,
+    queue_tracker: RwLock<QueueTracker>,
+    /// when each of our resting orders was first seen in the book, by client order id
+    order_timestamps: RwLock<HashMap<u64, Instant>>,
+    /// the `max_ts` of the orders placed with a ttl, by client order id
+    order_expiries: RwLock<HashMap<u64, i64>>,
     inflight_orders: RwLock<InflightOrders>,
+    post_only_offsets: RwLock<PostOnlyOffsets>,
     client_order_id: RwLock<u64>,
-    signer: Arc<Keypair>,
+    signer: Arc<dyn TxSigner>,
     cypher_user_pubkey: Pubkey,
-    open_orders_pubkey: Pubkey
+    open_orders_pubkey: Pubkey,
+    /// when set, transactions are logged and simulated instead of sent
+    dry_run: Option<DryRunConfig>,
+    pnl_tracker: Arc<PnlTracker>,
+    state_store: Option<Arc<StateStore>>,
+    audit_log: Option<Arc<AuditLog>>,
+    metrics: OrderManagerMetrics,
 }
 
 impl OrderManager {
     pub fn default() -> Self {
         Self {
             symbol: "".to_string(),
+            config: RwLock::new(OrderManagerConfig::default()),
+            requote_policy: RwLock::new(RequotePolicy::default()),
             rpc_client: Arc::new(RpcClient::new("http://localhost:8899".to_string())),
             chain_meta_service: Arc::new(ChainMetaService::default()),
             oo_receiver: Mutex::new(channel::<OpenOrders>(u16::MAX as usize).1),
-            ob_receiver: Mutex::new(channel::<Arc<OrderBook>>(u16::MAX as usize).1),
+            ob_receiver: Mutex::new(channel::<Arc<OrderBookUpdate>>(u16::MAX as usize).1),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
             market_state: None,
             open_orders: RwLock::new(None),
             orderbook: RwLock::new(Arc::new(OrderBook::default())),
+            queue_tracker: RwLock::new(QueueTracker::default()),
+            order_timestamps: RwLock::new(HashMap::new()),
+            order_expiries: RwLock::new(HashMap::new()),
             inflight_orders: RwLock::new(InflightOrders::default()),
+            post_only_offsets: RwLock::new(PostOnlyOffsets::default()),
             client_order_id: RwLock::new(1_u64),
-            signer: Arc::new(Keypair::new()),
+            signer: Arc::new(KeypairSigner::new(Keypair::new())),
             cypher_user_pubkey: Pubkey::default(),
             open_orders_pubkey: Pubkey::default(),
+            dry_run: None,
+            pnl_tracker: Arc::new(PnlTracker::default()),
+            state_store: None,
+            audit_log: None,
+            metrics: OrderManagerMetrics::new(""),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         symbol: String,
+        config: OrderManagerConfig,
         rpc_client: Arc<RpcClient>,
         chain_meta_service: Arc<ChainMetaService>,
         oo_receiver: Receiver<OpenOrders>,
-        ob_receiver: Receiver<Arc<OrderBook>>,
+        ob_receiver: Receiver<Arc<OrderBookUpdate>>,
         shutdown_receiver: Receiver<bool>,
         market_state: MarketStateV2,
-        signer: Arc<Keypair>,
+        signer: Arc<dyn TxSigner>,
         cypher_user_pubkey: Pubkey,
         open_orders_pubkey: Pubkey,
+        dry_run: Option<DryRunConfig>,
+        pnl_tracker: Arc<PnlTracker>,
+        state_store: Option<Arc<StateStore>>,
+        audit_log: Option<Arc<AuditLog>>,
     ) -> Self {
+        let metrics = OrderManagerMetrics::new(&symbol);
+        metrics.register();
+
         Self {
             symbol,
+            requote_policy: RwLock::new(RequotePolicy::new(&config)),
+            config: RwLock::new(config),
             rpc_client,
             chain_meta_service,
             oo_receiver: Mutex::new(oo_receiver),
@@ -130,6 +369,12 @@ impl OrderManager {
             signer,
             cypher_user_pubkey,
             open_orders_pubkey,
+            queue_tracker: RwLock::new(QueueTracker::new(open_orders_pubkey)),
+            dry_run,
+            pnl_tracker,
+            state_store,
+            audit_log,
+            metrics,
             ..OrderManager::default()
         }
     }
@@ -175,49 +420,116 @@ impl OrderManager {
     }
 
     async fn _process_oo_update(self: &Arc<Self>, oo: OpenOrders) {
-        info!("[ORDERMGR-{}] Received open orders update.", self.symbol);
+        debug!("[ORDERMGR-{}] Received open orders update.", self.symbol);
         *self.open_orders.write().await = Some(oo);
     }
 
-    async fn _process_ob_update(self: &Arc<Self>, ob: Arc<OrderBook>) {
-        info!("[ORDERMGR-{}] Received order book update.", self.symbol);
-        let bids = ob.bids.read().await;
-        let asks = ob.asks.read().await;
-        if asks.is_empty() && bids.is_empty() {
-            info!("[ORDERMGR-{}] Latest ob for market is empty!", self.symbol)
-        } else if asks.is_empty() && !bids.is_empty() {
-            info!(
-                "[ORDERMGR-{}] Latest ob for market: {} bids / best bid {}@{} - 0 asks ",
-                self.symbol,
-                bids.len(),
-                bids[0].quantity,
-                bids[0].price,
-            );
-        } else if bids.is_empty() && !asks.is_empty() {
-            info!(
-                "[ORDERMGR-{}] Latest ob for market: 0 bids - {} asks / best ask {}@{}",
-                self.symbol,
-                asks.len(),
-                asks[0].quantity,
-                asks[0].price,
-            );
-        } else {
-            info!("[ORDERMGR-{}] Latest ob for market: {} bids / best bid {}@{} - {} asks / best ask {}@{}",
-                self.symbol,
-                bids.len(),
-                bids[0].quantity,
-                bids[0].price,
-                asks.len(),
-                asks[0].quantity,
-                asks[0].price,
-            );
+    async fn _process_ob_update(self: &Arc<Self>, update: Arc<OrderBookUpdate>) {
+        let diff = &update.diff;
+        debug!(
+            "[ORDERMGR-{}] Received {:?} order book update: {} added / {} removed / {} changed orders across {} levels.",
+            self.symbol,
+            diff.side,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len(),
+            diff.levels.len(),
+        );
+
+        let hits = diff.own_orders_hit(&self.open_orders_pubkey);
+        if !hits.is_empty() {
+            let inflight_orders = self.inflight_orders.read().await;
+            let cancelling_orders = inflight_orders.cancelling_orders.read().await;
+            for hit in hits
+                .iter()
+                .filter(|h| !cancelling_orders.contains(&h.client_order_id))
+            {
+                info!(
+                    client_order_id = hit.client_order_id;
+                    "[ORDERMGR-{}] Order with id {} and coid {} was hit for {}@{}.",
+                    self.symbol, hit.order_id, hit.client_order_id, hit.quantity, hit.price
+                );
+                self.pnl_tracker
+                    .record_fill(
+                        diff.side.into(),
+                        hit.order_id,
+                        hit.client_order_id,
+                        hit.price,
+                        hit.quantity,
+                    )
+                    .await;
+                self.write_audit(&[AuditRecord {
+                    timestamp: Utc::now(),
+                    action: AuditAction::Fill,
+                    status: AuditStatus::Observed,
+                    side: diff.side,
+                    client_order_id: hit.client_order_id,
+                    order_id: Some(hit.order_id),
+                    price: hit.price,
+                    quantity: hit.quantity,
+                    signatures: Vec::new(),
+                    reason: if hit.removed {
+                        "filled".to_string()
+                    } else {
+                        "partially filled".to_string()
+                    },
+                }]);
+            }
         }
-        drop(bids);
-        drop(asks);
-        *self.orderbook.write().await = ob;
+
+        self.queue_tracker.write().await.update(&update);
+        *self.orderbook.write().await = Arc::clone(&update.book);
     }
 
-    async fn get_orders(self: &Arc<Self>) -> Vec<ManagedOrder> {
+    /// the estimated queue positions of our resting orders
+    pub async fn get_queue_positions(self: &Arc<Self>) -> Vec<TrackedQueuePosition> {
+        self.queue_tracker.read().await.positions()
+    }
+
+    /// the client order ids of the orders we sent cancels for
+    pub async fn get_cancelling_orders(self: &Arc<Self>) -> Vec<u64> {
+        let inflight_orders = self.inflight_orders.read().await;
+        let cancelling_orders = inflight_orders.cancelling_orders.read().await;
+        cancelling_orders.clone()
+    }
+
+    /// forgets the cancelled orders which neither occupy a slot of the open orders account nor
+    /// rest in the book anymore, neither can mistake them for fills from then on
+    pub async fn forget_cancelled_orders(self: &Arc<Self>, oo: &OpenOrders) {
+        // the account is packed, so its arrays are copied out before they are borrowed
+        let orders = oo.orders;
+        let client_order_ids = oo.client_order_ids;
+        let orderbook = Arc::clone(&*self.orderbook.read().await);
+        let inflight_orders = self.inflight_orders.read().await;
+        inflight_orders
+            .cancelling_orders
+            .write()
+            .await
+            .retain(|coid| {
+                orders
+                    .iter()
+                    .zip(client_order_ids.iter())
+                    .any(|(o, c)| *o != u128::default() && c == coid)
+                    || orderbook
+                        .bids
+                        .iter()
+                        .chain(orderbook.asks.iter())
+                        .any(|o| o.client_order_id == *coid && orderbook.is_own_order(o))
+            });
+    }
+
+    /// replaces the config and the requote policy derived from it, resting orders are
+    /// evaluated against the new config on the next requote
+    pub async fn set_config(self: &Arc<Self>, config: OrderManagerConfig) {
+        info!(
+            "[ORDERMGR-{}] Updating config: {:?}",
+            self.symbol, config
+        );
+        *self.requote_policy.write().await = RequotePolicy::new(&config);
+        *self.config.write().await = config;
+    }
+
+    pub async fn get_orders(self: &Arc<Self>) -> Vec<ManagedOrder> {
         let maybe_oo = *self.open_orders.read().await;
         let oo = match maybe_oo {
             Some(oo) => oo,
@@ -226,7 +538,8 @@ impl OrderManager {
             }
         };
         let ob = self.orderbook.read().await;
-        let orders = get_open_orders_with_qty(&oo, &ob).await;
+        let orders = get_open_orders_with_qty(&oo, &ob);
+        self.metrics.resting_orders.set(orders.len() as i64);
 
         info!(
             "[ORDERMGR-{}] Found {} orders resting.",
@@ -238,34 +551,62 @@ impl OrderManager {
 
     async fn get_stale_orders(
         self: &Arc<Self>,
+        orders: &[ManagedOrder],
         quote_vols: &QuoteVolumes,
         best_bid: u64,
         best_ask: u64,
-    ) -> Vec<ManagedOrder> {
+    ) -> (Vec<ManagedOrder>, Vec<StaleReason>) {
         let mut stale_orders: Vec<ManagedOrder> = Vec::new();
-        let orders = self.get_orders().await;
+        let mut stale_reasons: Vec<StaleReason> = Vec::new();
+        let requote_policy = self.requote_policy.read().await;
+        let queue_tracker = self.queue_tracker.read().await;
+        let mut order_timestamps = self.order_timestamps.write().await;
+        let mut order_expiries = self.order_expiries.write().await;
+        let coin_lot_size = self.market_state.unwrap().coin_lot_size;
+        let now = Instant::now();
+        let now_ts = unix_timestamp();
+
+        // forget about orders which are no longer resting
+        order_timestamps.retain(|coid, _| orders.iter().any(|o| o.client_order_id == *coid));
+        // orders still in flight are not resting yet, but expired ones are gone either way
+        order_expiries.retain(|_, max_ts| *max_ts >= now_ts);
 
         for order in orders {
-            if order.side == Side::Ask
-                && (order.price != best_ask || order.quantity != quote_vols.ask_size as u64)
-            {
-                stale_orders.push(ManagedOrder {
-                    order_id: order.order_id,
-                    client_order_id: order.client_order_id,
-                    price: order.price,
-                    quantity: order.quantity,
-                    side: Side::Ask,
-                });
-            } else if order.side == Side::Bid
-                && (order.price != best_bid || order.quantity != quote_vols.bid_size as u64)
-            {
+            let (desired_price, desired_size) = match order.side {
+                Side::Bid => (best_bid, quote_vols.bid_size as u64),
+                Side::Ask => (best_ask, quote_vols.ask_size as u64),
+            };
+            let placed_at = *order_timestamps.entry(order.client_order_id).or_insert(now);
+
+            let max_ts = order_expiries.get(&order.client_order_id).copied();
+
+            let reason = if requote_policy.is_expiring(max_ts, now_ts) {
+                Some(StaleReason::Expiring)
+            } else {
+                requote_policy.evaluate(
+                    order,
+                    order.quantity / coin_lot_size,
+                    desired_price,
+                    desired_size,
+                    now.duration_since(placed_at),
+                    queue_tracker.get(order.order_id),
+                )
+            };
+
+            if let Some(reason) = reason {
+                info!(
+                    client_order_id = order.client_order_id;
+                    "[ORDERMGR-{}] Order with id {} is stale: {:?}.",
+                    self.symbol, order.order_id, reason
+                );
                 stale_orders.push(ManagedOrder {
                     order_id: order.order_id,
                     client_order_id: order.client_order_id,
                     price: order.price,
                     quantity: order.quantity,
-                    side: Side::Bid,
+                    side: order.side,
                 });
+                stale_reasons.push(reason);
             }
         }
 
@@ -274,7 +615,7 @@ impl OrderManager {
             self.symbol,
             stale_orders.len()
         );
-        stale_orders
+        (stale_orders, stale_reasons)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -286,10 +627,36 @@ impl OrderManager {
         quote_vols: &QuoteVolumes,
         best_bid: u64,
         best_ask: u64,
+        reason: &str,
     ) -> Result<(), MarketMakerError> {
+        let offsets = *self.post_only_offsets.read().await;
+        let best_bid = best_bid.saturating_sub(offsets.bid).max(1);
+        let best_ask = best_ask + offsets.ask;
+
+        self.metrics.quoted_bid_price.set(best_bid as i64);
+        self.metrics.quoted_ask_price.set(best_ask as i64);
+        self.metrics.quoted_bid_size.set(quote_vols.bid_size as i64);
+        self.metrics.quoted_ask_size.set(quote_vols.ask_size as i64);
+        let mid_price = (best_bid as u128 + best_ask as u128) / 2;
+        if mid_price != 0 {
+            let spread_bps = best_ask.saturating_sub(best_bid) as u128 * 10_000 / mid_price;
+            self.metrics.quoted_spread_bps.set(spread_bps as i64);
+        }
+
         let mut ixs: Vec<Instruction> = Vec::new();
+        let mut audit_records: Vec<AuditRecord> = Vec::new();
         let orders = self.get_orders().await;
-        let stale_orders = self.get_stale_orders(quote_vols, best_bid, best_ask).await;
+        let (stale_orders, stale_reasons) = self
+            .get_stale_orders(&orders, quote_vols, best_bid, best_ask)
+            .await;
+
+        for (order, stale_reason) in stale_orders.iter().zip(stale_reasons.iter()) {
+            audit_records.push(self.audit_record(
+                AuditAction::Cancel,
+                order,
+                format!("stale: {:?}", stale_reason),
+            ));
+        }
 
         if !stale_orders.is_empty() {
             let cancel_ixs = self
@@ -298,7 +665,7 @@ impl OrderManager {
                     cypher_group,
                     cypher_market,
                     cypher_token,
-                    &self.signer,
+                    self.signer.as_ref(),
                 )
                 .await;
             info!(
@@ -309,14 +676,27 @@ impl OrderManager {
             ixs.extend(cancel_ixs);
         }
 
-        if orders.is_empty() || !stale_orders.is_empty() {
-            let new_order_ixs = self
+        // only quote the sides which are not left with a resting order
+        let resting = |side: Side| {
+            orders.iter().any(|o| {
+                o.side == side && !stale_orders.iter().any(|s| s.order_id == o.order_id)
+            })
+        };
+        let new_quote_vols = QuoteVolumes {
+            delta: quote_vols.delta,
+            bid_size: if resting(Side::Bid) { 0 } else { quote_vols.bid_size },
+            ask_size: if resting(Side::Ask) { 0 } else { quote_vols.ask_size },
+        };
+
+        let first_client_order_id = *self.client_order_id.read().await;
+        if new_quote_vols.bid_size > 0 || new_quote_vols.ask_size > 0 {
+            let (new_order_ixs, new_orders) = self
                 .get_new_orders_ixs(
                     cypher_group,
                     cypher_market,
                     cypher_token,
-                    quote_vols,
-                    &self.signer,
+                    &new_quote_vols,
+                    self.signer.as_ref(),
                     best_bid,
                     best_ask,
                 )
@@ -327,12 +707,29 @@ impl OrderManager {
                 new_order_ixs.len()
             );
             ixs.extend(new_order_ixs);
+            for order in new_orders.iter() {
+                audit_records.push(self.audit_record(
+                    AuditAction::Place,
+                    order,
+                    reason.to_string(),
+                ));
+            }
         }
 
         if !ixs.is_empty() {
-            match self.submit_orders(ixs, &self.signer).await {
-                Ok(_) => (),
+            self.metrics.requotes.inc();
+            let res = self.submit_orders(ixs, self.signer.as_ref()).await;
+            self.forget_new_orders(first_client_order_id).await;
+            self.audit(audit_records, &res);
+            match res {
+                Ok(_) => {
+                    if new_quote_vols.bid_size > 0 || new_quote_vols.ask_size > 0 {
+                        self.check_post_only_orders(first_client_order_id, &new_quote_vols)
+                            .await?;
+                    }
+                }
                 Err(e) => {
+                    self.process_failed_orders(&e, &stale_orders).await;
                     return Err(e);
                 }
             }
@@ -341,40 +738,518 @@ impl OrderManager {
         Ok(())
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub async fn cancel_orders_remain_neutral(
+    /// serum drops a post only order which would cross the book without failing the transaction,
+    /// so once it is confirmed the sides which are missing the orders just placed are quoted
+    /// one tick wider and the sides which have them go back to the desired prices
+    async fn check_post_only_orders(
+        self: &Arc<Self>,
+        first_client_order_id: u64,
+        quote_vols: &QuoteVolumes,
+    ) -> Result<(), MarketMakerError> {
+        // nothing was placed
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+
+        let open_orders = match _fetch_open_orders(
+            &self.open_orders_pubkey,
+            Arc::clone(&self.rpc_client),
+        )
+        .await
+        {
+            Ok(o) => o,
+            Err(e) => {
+                warn!(
+                    "[ORDERMGR-{}] Failed to check whether the new orders were placed: {}",
+                    self.symbol, e
+                );
+                return Ok(());
+            }
+        };
+        let orders = get_open_orders(&open_orders).await;
+        let dropped_bid =
+            quote_vols.bid_size > 0 && !is_placed(&orders, Side::Bid, first_client_order_id);
+        let dropped_ask =
+            quote_vols.ask_size > 0 && !is_placed(&orders, Side::Ask, first_client_order_id);
+
+        let mut offsets = self.post_only_offsets.write().await;
+        if quote_vols.bid_size > 0 {
+            offsets.bid = if dropped_bid { offsets.bid + 1 } else { 0 };
+        }
+        if quote_vols.ask_size > 0 {
+            offsets.ask = if dropped_ask { offsets.ask + 1 } else { 0 };
+        }
+        if !dropped_bid && !dropped_ask {
+            return Ok(());
+        }
+
+        let rejection = OrderRejection::PostOnlyWouldCross;
+        self.metrics
+            .order_rejections
+            .with_label_values(&[rejection.as_str()])
+            .inc();
+        info!(
+            "[ORDERMGR-{}] Post only orders would have crossed the book, quoting {} ticks wider on the bid and {} on the ask.",
+            self.symbol, offsets.bid, offsets.ask
+        );
+        Err(OrderError::Rejected(rejection).into())
+    }
+
+    /// forgets about the cancels of a failed transaction and counts the reason the program
+    /// rejected its orders, if it did
+    async fn process_failed_orders(
+        self: &Arc<Self>,
+        error: &MarketMakerError,
+        stale_orders: &[ManagedOrder],
+    ) {
+        let inflight_orders = self.inflight_orders.read().await;
+        inflight_orders
+            .cancelling_orders
+            .write()
+            .await
+            .retain(|coid| !stale_orders.iter().any(|o| o.client_order_id == *coid));
+        drop(inflight_orders);
+
+        let rejection = match error.tx_error().and_then(|e| e.rejection()) {
+            Some(r) => r,
+            None => return,
+        };
+        self.metrics
+            .order_rejections
+            .with_label_values(&[rejection.as_str()])
+            .inc();
+        warn!(
+            "[ORDERMGR-{}] Orders were rejected: {:?}.",
+            self.symbol, rejection
+        );
+    }
+
+    /// deals with the orders a previous run left in the open orders account before the first
+    /// quote, the client order ids are seeded past theirs and the orders are then adopted or
+    /// cancelled according to `existing_orders`
+    pub async fn reconcile(
         self: &Arc<Self>,
         cypher_group: &CypherGroup,
         cypher_market: &CypherMarket,
         cypher_token: &CypherToken,
-        signer: &Keypair,
-        quote_vols: &QuoteVolumes,
-        best_bid: u64,
-        best_ask: u64,
+        cancel_timeout: Duration,
+        cancel_retry_interval: Duration,
     ) -> Result<(), MarketMakerError> {
-        let mut ixs: Vec<Instruction> = Vec::new();
-        let orders = self.get_orders().await;
+        let open_orders =
+            _fetch_open_orders(&self.open_orders_pubkey, Arc::clone(&self.rpc_client)).await?;
+        let orders = get_open_orders(&open_orders).await;
+        self.seed_client_order_id(&orders).await;
 
-        let cancel_ixs = self
-            .get_cancel_orders_ixs(&orders, cypher_group, cypher_market, cypher_token, signer)
-            .await;
+        if orders.is_empty() {
+            info!(
+                "[ORDERMGR-{}] There are no orders left by a previous run.",
+                self.symbol
+            );
+            return Ok(());
+        }
+
+        let orderbook = Arc::clone(&*self.orderbook.read().await);
+        for order in orders.iter() {
+            let resting = orderbook.find_order(order.side, order.order_id).is_some();
+            info!(
+                client_order_id = order.client_order_id;
+                "[ORDERMGR-{}] Found order with id {} left by a previous run, {:?} at {}, resting in the book: {}.",
+                self.symbol, order.order_id, order.side, order.price, resting
+            );
+        }
+
+        let existing_orders = self.config.read().await.existing_orders;
+        match existing_orders {
+            ExistingOrders::Adopt => {
+                info!(
+                    "[ORDERMGR-{}] Adopting {} orders left by a previous run.",
+                    self.symbol,
+                    orders.len()
+                );
+                Ok(())
+            }
+            ExistingOrders::Cancel => {
+                info!(
+                    "[ORDERMGR-{}] Cancelling {} orders left by a previous run.",
+                    self.symbol,
+                    orders.len()
+                );
+                let clean = self
+                    .cancel_all_until_clean(
+                        cypher_group,
+                        cypher_market,
+                        cypher_token,
+                        cancel_timeout,
+                        cancel_retry_interval,
+                        "left by a previous run",
+                    )
+                    .await;
+                if clean {
+                    Ok(())
+                } else {
+                    Err(OrderError::CancelExisting.into())
+                }
+            }
+        }
+    }
+
+    /// client order ids start from the current time in milliseconds so they do not collide with
+    /// those of a previous run, and always above the ids of the orders which are still open
+    async fn seed_client_order_id(self: &Arc<Self>, orders: &[ManagedOrder]) {
+        let max_existing = orders
+            .iter()
+            .map(|o| o.client_order_id)
+            .max()
+            .unwrap_or_default();
+        let seed = (unix_timestamp() as u64 * 1000).max(max_existing.saturating_add(1));
+        // and above the ids submitted by previous runs, as far as the state store knows
+        let seed = match &self.state_store {
+            Some(state_store) => seed.max(state_store.next_client_order_id()),
+            None => seed,
+        };
+
+        let mut client_order_id = self.client_order_id.write().await;
+        *client_order_id = (*client_order_id).max(seed);
         info!(
-            "[ORDERMGR-{}] Cancelling {} stale orders.",
-            self.symbol,
-            cancel_ixs.len()
+            "[ORDERMGR-{}] Seeded the client order ids at {}.",
+            self.symbol, *client_order_id
         );
-        ixs.extend(cancel_ixs);
+    }
 
-        if !ixs.is_empty() {
-            match self.submit_orders(ixs, signer).await {
-                Ok(_) => (),
+    /// an audit record of one of our orders, its price in lots, as every `ManagedOrder` holds it,
+    /// is converted to the units of the book
+    fn audit_record(
+        self: &Arc<Self>,
+        action: AuditAction,
+        order: &ManagedOrder,
+        reason: String,
+    ) -> AuditRecord {
+        let market_state = self.market_state.unwrap();
+        let price = order.price as u128 * market_state.pc_lot_size as u128
+            / market_state.coin_lot_size as u128;
+        AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            status: AuditStatus::Confirmed,
+            side: order.side,
+            client_order_id: order.client_order_id,
+            order_id: if order.order_id == u128::default() {
+                None
+            } else {
+                Some(order.order_id)
+            },
+            price: price as u64,
+            quantity: order.quantity,
+            signatures: Vec::new(),
+            reason,
+        }
+    }
+
+    /// writes the records of orders which were sent together along with the outcome of sending them
+    fn audit(
+        self: &Arc<Self>,
+        mut records: Vec<AuditRecord>,
+        res: &Result<Vec<Signature>, MarketMakerError>,
+    ) {
+        for record in records.iter_mut() {
+            match res {
+                Ok(signatures) => {
+                    // dry runs never send the transactions, the signatures were never seen on chain
+                    if self.dry_run.is_some() {
+                        record.status = AuditStatus::Simulated;
+                    }
+                    record.signatures = signatures.iter().map(|s| s.to_string()).collect();
+                }
                 Err(e) => {
-                    return Err(e);
+                    record.status = AuditStatus::Failed;
+                    record.reason = format!("{}: {}", record.reason, e);
                 }
             }
         }
+        self.write_audit(&records);
+    }
 
-        Ok(())
+    fn audit_orders(
+        self: &Arc<Self>,
+        action: AuditAction,
+        orders: &[ManagedOrder],
+        reason: &str,
+        res: &Result<Vec<Signature>, MarketMakerError>,
+    ) {
+        let records = orders
+            .iter()
+            .map(|o| self.audit_record(action, o, reason.to_string()))
+            .collect();
+        self.audit(records, res);
+    }
+
+    fn write_audit(&self, records: &[AuditRecord]) {
+        if let Some(audit_log) = &self.audit_log {
+            if let Err(e) = audit_log.write(records) {
+                warn!(
+                    "[ORDERMGR-{}] Failed to write to the audit log: {}",
+                    self.symbol, e
+                );
+            }
+        }
+    }
+
+    /// writes an event to the state store, if there is one
+    fn record(self: &Arc<Self>, event: StateEvent) {
+        if let Some(state_store) = &self.state_store {
+            if let Err(e) = state_store.record(event) {
+                warn!(
+                    "[ORDERMGR-{}] Failed to write to the state store: {}",
+                    self.symbol, e
+                );
+            }
+        }
+    }
+
+    /// cancels the orders of a freshly fetched open orders account until none are left or the
+    /// timeout elapses, so orders which were in flight or missing from the book are caught too,
+    /// returns whether the account was confirmed to be clean
+    pub async fn cancel_all_until_clean(
+        self: &Arc<Self>,
+        cypher_group: &CypherGroup,
+        cypher_market: &CypherMarket,
+        cypher_token: &CypherToken,
+        timeout: Duration,
+        retry_interval: Duration,
+        reason: &str,
+    ) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match _fetch_open_orders(&self.open_orders_pubkey, Arc::clone(&self.rpc_client)).await {
+                Ok(open_orders) => {
+                    let orders = get_open_orders(&open_orders).await;
+                    if orders.is_empty() {
+                        info!(
+                            "[ORDERMGR-{}] Confirmed there are no orders left in the open orders account.",
+                            self.symbol
+                        );
+                        return true;
+                    }
+                    // the open orders account does not hold the quantities, the book does for the
+                    // orders resting in it
+                    let orderbook = Arc::clone(&*self.orderbook.read().await);
+                    let orders = with_book_quantities(orders, &orderbook);
+
+                    info!(
+                        "[ORDERMGR-{}] Cancelling {} orders left in the open orders account.",
+                        self.symbol,
+                        orders.len()
+                    );
+                    for chunk in orders.chunks(MAX_CANCELS_PER_TX) {
+                        let cancel_ixs = self
+                            .get_cancel_orders_ixs(
+                                &chunk.to_vec(),
+                                cypher_group,
+                                cypher_market,
+                                cypher_token,
+                                self.signer.as_ref(),
+                            )
+                            .await;
+                        let res = self.submit_orders(cancel_ixs, self.signer.as_ref()).await;
+                        self.audit_orders(AuditAction::Cancel, chunk, reason, &res);
+                        if let Err(e) = res {
+                            warn!("[ORDERMGR-{}] Failed to cancel orders: {}", self.symbol, e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "[ORDERMGR-{}] Failed to fetch the open orders account: {}",
+                        self.symbol, e
+                    );
+                }
+            }
+
+            if Instant::now() + retry_interval > deadline {
+                return false;
+            }
+            tokio::time::sleep(retry_interval).await;
+        }
+    }
+
+    /// moves the free funds of the open orders account back into the cypher account
+    pub async fn settle_funds(
+        self: &Arc<Self>,
+        cypher_group: &CypherGroup,
+        cypher_market: &CypherMarket,
+        cypher_token: &CypherToken,
+    ) -> Result<(), MarketMakerError> {
+        let market_state = match self.market_state {
+            Some(m) => m,
+            None => return Err(MarketMakerError::ErrorFetchingDexMarket),
+        };
+
+        let ix = get_settle_funds_ix(
+            cypher_group,
+            cypher_market,
+            cypher_token,
+            &market_state,
+            &self.open_orders_pubkey,
+            &self.cypher_user_pubkey,
+            self.signer.as_ref(),
+        );
+        info!("[ORDERMGR-{}] Settling funds.", self.symbol);
+
+        self.submit_orders(vec![ix], self.signer.as_ref())
+            .await
+            .map(|_| ())
+            .map_err(|e| match e {
+                MarketMakerError::Order(OrderError::Submit(e)) => OrderError::Settle(e).into(),
+                e => e,
+            })
+    }
+
+    /// cancels every resting order, returns the number of orders cancelled
+    pub async fn cancel_all_orders(
+        self: &Arc<Self>,
+        cypher_group: &CypherGroup,
+        cypher_market: &CypherMarket,
+        cypher_token: &CypherToken,
+    ) -> Result<usize, MarketMakerError> {
+        // the local view misses orders placed since the last account update, so it is fetched
+        let open_orders =
+            _fetch_open_orders(&self.open_orders_pubkey, Arc::clone(&self.rpc_client)).await?;
+        let orders = get_open_orders(&open_orders).await;
+        if orders.is_empty() {
+            return Ok(0);
+        }
+        let orderbook = Arc::clone(&*self.orderbook.read().await);
+        let orders = with_book_quantities(orders, &orderbook);
+
+        info!(
+            "[ORDERMGR-{}] Cancelling all {} resting orders.",
+            self.symbol,
+            orders.len()
+        );
+        for chunk in orders.chunks(MAX_CANCELS_PER_TX) {
+            let chunk = chunk.to_vec();
+            let cancel_ixs = self
+                .get_cancel_orders_ixs(
+                    &chunk,
+                    cypher_group,
+                    cypher_market,
+                    cypher_token,
+                    self.signer.as_ref(),
+                )
+                .await;
+            let res = self.submit_orders(cancel_ixs, self.signer.as_ref()).await;
+            self.audit_orders(AuditAction::Cancel, &chunk, "cancel all requested", &res);
+            res?;
+        }
+        Ok(orders.len())
+    }
+
+    /// takes liquidity from the book with an immediate-or-cancel order to bring the given position,
+    /// in native units of the base asset, back to zero
+    pub async fn flatten(
+        self: &Arc<Self>,
+        cypher_group: &CypherGroup,
+        cypher_market: &CypherMarket,
+        cypher_token: &CypherToken,
+        native_position: i128,
+    ) -> Result<(), MarketMakerError> {
+        let market_state = match self.market_state {
+            Some(m) => m,
+            None => return Err(MarketMakerError::ErrorFetchingDexMarket),
+        };
+
+        let lots = (native_position.unsigned_abs() / market_state.coin_lot_size as u128) as u64;
+        if lots == 0 {
+            info!("[ORDERMGR-{}] Position is already flat.", self.symbol);
+            return Ok(());
+        }
+
+        // a long position is flattened by selling into the bids and a short one by buying the asks
+        let (side, taken_side) = if native_position > 0 {
+            (Side::Ask, Side::Bid)
+        } else {
+            (Side::Bid, Side::Ask)
+        };
+
+        let book = Arc::clone(&*self.orderbook.read().await);
+        let size = lots * market_state.coin_lot_size;
+        let order_to_fill = match book.order_to_fill(taken_side, size) {
+            Some(o) => o,
+            // not enough depth to flatten entirely, take whatever is there
+            None => match book.side(taken_side).last() {
+                Some(o) => o,
+                None => {
+                    warn!(
+                        "[ORDERMGR-{}] Cannot flatten position, the {:?} side of the book is empty.",
+                        self.symbol, taken_side
+                    );
+                    return Err(OrderError::NoLiquidity.into());
+                }
+            },
+        };
+        // the book holds prices in quote per base, the order needs the price in lots which
+        // the order id carries in its upper half
+        let limit_price = match NonZeroU64::new((order_to_fill.order_id >> 64) as u64) {
+            Some(p) => p,
+            None => return Err(OrderError::InvalidPrice.into()),
+        };
+
+        let max_native_pc_qty = (lots as u128)
+            .checked_mul(limit_price.get() as u128)
+            .and_then(|q| q.checked_mul(market_state.pc_lot_size as u128))
+            .and_then(|q| q.checked_mul((BPS_UNIT + TAKER_FEE_BUFFER_BPS) as u128))
+            .map(|q| q / BPS_UNIT as u128)
+            .and_then(|q| u64::try_from(q).ok())
+            .and_then(NonZeroU64::new);
+        let max_native_pc_qty = match max_native_pc_qty {
+            Some(q) => q,
+            None => return Err(OrderError::InvalidSize.into()),
+        };
+        let client_order_id = *self.client_order_id.read().await;
+        info!(
+            client_order_id = client_order_id;
+            "[ORDERMGR-{}] Flattening position of {} with {:?} at {} for {} lots with coid: {}",
+            self.symbol, native_position, side, limit_price, lots, client_order_id
+        );
+
+        let ix = get_new_order_ix(
+            cypher_group,
+            cypher_market,
+            cypher_token,
+            &market_state,
+            &self.open_orders_pubkey,
+            &self.cypher_user_pubkey,
+            self.signer.as_ref(),
+            NewOrderInstructionV3 {
+                client_order_id,
+                limit: u16::MAX,
+                limit_price,
+                side,
+                max_coin_qty: NonZeroU64::new(lots).unwrap(),
+                max_native_pc_qty_including_fees: max_native_pc_qty,
+                order_type: OrderType::ImmediateOrCancel,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                max_ts: i64::MAX,
+            },
+        );
+        *self.client_order_id.write().await += 1;
+
+        let res = self.submit_orders(vec![ix], self.signer.as_ref()).await;
+        let order = ManagedOrder {
+            order_id: u128::default(),
+            client_order_id,
+            price: limit_price,
+            quantity: lots * market_state.coin_lot_size,
+            side,
+        };
+        self.audit_orders(
+            AuditAction::Place,
+            &[order],
+            &format!("flatten position of {}", native_position),
+            &res,
+        );
+        res.map(|_| ())
     }
 
     async fn get_cancel_orders_ixs(
@@ -383,7 +1258,7 @@ impl OrderManager {
         cypher_group: &CypherGroup,
         cypher_market: &CypherMarket,
         cypher_token: &CypherToken,
-        signer: &Keypair,
+        signer: &dyn TxSigner,
     ) -> Vec<Instruction> {
         let inflight_orders = self.inflight_orders.read().await;
         let mut cancelling_orders = inflight_orders.cancelling_orders.write().await;
@@ -391,6 +1266,7 @@ impl OrderManager {
 
         for order in stale_orders {
             info!(
+                client_order_id = order.client_order_id;
                 "[ORDERMGR-{}] Cancelling order with id {}",
                 self.symbol, order.order_id
             );
@@ -408,6 +1284,10 @@ impl OrderManager {
                 },
             ));
             cancelling_orders.push(order.client_order_id);
+            self.record(StateEvent::CancelSubmitted {
+                order_id: order.order_id,
+                client_order_id: order.client_order_id,
+            });
         }
 
         drop(cancelling_orders);
@@ -415,6 +1295,18 @@ impl OrderManager {
         ixs
     }
 
+    /// forgets the orders placed from `first_client_order_id` on, their transactions were
+    /// confirmed or failed so either way they are no longer in flight
+    async fn forget_new_orders(self: &Arc<Self>, first_client_order_id: u64) {
+        let inflight_orders = self.inflight_orders.read().await;
+        inflight_orders
+            .new_orders
+            .write()
+            .await
+            .retain(|coid| *coid < first_client_order_id);
+    }
+
+    /// the instructions placing the quotes, along with the orders they place
     #[allow(clippy::too_many_arguments)]
     async fn get_new_orders_ixs(
         self: &Arc<Self>,
@@ -422,18 +1314,24 @@ impl OrderManager {
         cypher_market: &CypherMarket,
         cypher_token: &CypherToken,
         quote_vols: &QuoteVolumes,
-        signer: &Keypair,
+        signer: &dyn TxSigner,
         best_bid: u64,
         best_ask: u64,
-    ) -> Vec<Instruction> {
+    ) -> (Vec<Instruction>, Vec<ManagedOrder>) {
         let mut ixs: Vec<Instruction> = Vec::new();
+        let mut placed: Vec<ManagedOrder> = Vec::new();
+        let coin_lot_size = self.market_state.unwrap().coin_lot_size;
         let inflight_orders = self.inflight_orders.read().await;
         let mut new_orders = inflight_orders.new_orders.write().await;
+        let requote_policy = self.requote_policy.read().await;
+        let mut order_expiries = self.order_expiries.write().await;
+        let max_ts = requote_policy.max_ts(unix_timestamp());
 
         if quote_vols.ask_size > 0 {
             let max_native_pc_qty_ask = quote_vols.ask_size as u64 * best_ask;
             let client_order_id = *self.client_order_id.read().await;
             info!(
+                client_order_id = client_order_id;
                 "[ORDERMGR-{}] Submitting new ask at {} for {} units at max qty pc {} with coid: {}",
                 self.symbol, best_ask, quote_vols.ask_size, max_native_pc_qty_ask, client_order_id
             );
@@ -456,17 +1354,35 @@ impl OrderManager {
                         .unwrap(),
                     order_type: OrderType::PostOnly,
                     self_trade_behavior: SelfTradeBehavior::CancelProvide,
-                    max_ts: i64::MAX,
+                    max_ts,
                 },
             ));
             *self.client_order_id.write().await += 1;
             new_orders.push(client_order_id);
+            if requote_policy.has_order_ttl() {
+                order_expiries.insert(client_order_id, max_ts);
+            }
+            self.record(StateEvent::OrderSubmitted {
+                client_order_id,
+                side: Side::Ask.into(),
+                price: best_ask,
+                quantity: quote_vols.ask_size as u64,
+                max_ts,
+            });
+            placed.push(ManagedOrder {
+                order_id: u128::default(),
+                client_order_id,
+                price: best_ask,
+                quantity: quote_vols.ask_size as u64 * coin_lot_size,
+                side: Side::Ask,
+            });
         }
 
         if quote_vols.bid_size > 0 {
             let max_native_pc_qty_bid = quote_vols.bid_size as u64 * best_bid;
             let client_order_id = *self.client_order_id.read().await;
             info!(
+                client_order_id = client_order_id;
                 "[ORDERMGR-{}] Submitting new bid at {} for {} units at max pc qty {} with coid: {}",
                 self.symbol, best_bid, quote_vols.bid_size, max_native_pc_qty_bid, client_order_id
             );
@@ -489,39 +1405,59 @@ impl OrderManager {
                         .unwrap(),
                     order_type: OrderType::PostOnly,
                     self_trade_behavior: SelfTradeBehavior::CancelProvide,
-                    max_ts: i64::MAX,
+                    max_ts,
                 },
             ));
             *self.client_order_id.write().await += 1;
             new_orders.push(client_order_id);
+            if requote_policy.has_order_ttl() {
+                order_expiries.insert(client_order_id, max_ts);
+            }
+            self.record(StateEvent::OrderSubmitted {
+                client_order_id,
+                side: Side::Bid.into(),
+                price: best_bid,
+                quantity: quote_vols.bid_size as u64,
+                max_ts,
+            });
+            placed.push(ManagedOrder {
+                order_id: u128::default(),
+                client_order_id,
+                price: best_bid,
+                quantity: quote_vols.bid_size as u64 * coin_lot_size,
+                side: Side::Bid,
+            });
         }
 
+        drop(order_expiries);
         drop(new_orders);
         drop(inflight_orders);
-        ixs
+        (ixs, placed)
     }
 
     async fn submit_orders(
         self: &Arc<Self>,
         ixs: Vec<Instruction>,
-        signer: &Keypair,
-    ) -> Result<(), MarketMakerError> {
+        signer: &dyn TxSigner,
+    ) -> Result<Vec<Signature>, MarketMakerError> {
         let blockhash = self.chain_meta_service.get_latest_blockhash().await;
         if blockhash == Hash::default() {
-            return Err(MarketMakerError::ErrorSubmittingOrders);
+            return Err(OrderError::Submit(TxError::NoBlockhash).into());
         };
 
-        info!("[ORDERMGR-{}] Using blockhash {}", self.symbol, blockhash);
+        debug!("[ORDERMGR-{}] Using blockhash {}", self.symbol, blockhash);
 
         let res = self.submit_transactions(ixs, signer, blockhash).await;
         match res {
             Ok(s) => {
-                for sig in s {
+                for sig in s.iter() {
                     info!(
+                        signature = sig.to_string();
                         "[ORDERMGR-{}] Successfully submitted transaction: {}",
                         self.symbol, sig
                     );
                 }
+                Ok(s)
             }
             Err(e) => {
                 warn!(
@@ -529,56 +1465,49 @@ impl OrderManager {
                     self.symbol,
                     e.to_string()
                 );
-                return Err(MarketMakerError::ErrorSubmittingOrders);
+                Err(OrderError::Submit(e).into())
             }
-        };
-
-        Ok(())
+        }
     }
 
     async fn submit_transactions(
         self: &Arc<Self>,
         ixs: Vec<Instruction>,
-        signer: &Keypair,
+        signer: &dyn TxSigner,
         blockhash: Hash,
-    ) -> Result<Vec<Signature>, ClientError> {
+    ) -> Result<Vec<Signature>, TxError> {
         let mut txn_builder = FastTxnBuilder::new();
-        let mut submitted: bool = false;
         let mut signatures: Vec<Signature> = Vec::new();
         let mut prev_tx: Transaction = Transaction::default();
 
         for ix in ixs {
-            if txn_builder.len() != 0 {
-                let tx = txn_builder.build(blockhash, signer, None);
-                // we do this to attempt to pack as many ixs in a tx as possible
-                // there's more efficient ways to do it but we'll do it in the future
-                if tx.message_data().len() > 1000 {
-                    let res = self.send_and_confirm_transaction(&prev_tx).await;
-                    match res {
-                        Ok(s) => {
-                            submitted = true;
-                            txn_builder.clear();
-                            signatures.push(s);
-                        }
-                        Err(e) => {
-                            warn!("[ORDERMGR-{}] There was an error submitting transaction and waiting for confirmation: {}",
-                            self.symbol, e.to_string());
-                        }
+            txn_builder.add(ix.clone());
+            // the size does not depend on the signatures, so only sign what is sent
+            let tx = txn_builder.build_unsigned(&signer.pubkey());
+            // we do this to attempt to pack as many ixs in a tx as possible
+            // there's more efficient ways to do it but we'll do it in the future
+            if txn_builder.len() > 1 && tx.message_data().len() > 1000 {
+                // the ix does not fit, send the ones before it and start the next tx with it
+                signer.sign_transaction(&mut prev_tx, blockhash).await?;
+                match self.send_and_confirm_transaction(&prev_tx).await {
+                    Ok(s) => signatures.push(s),
+                    Err(e) => {
+                        warn!("[ORDERMGR-{}] There was an error submitting transaction and waiting for confirmation: {}", self.symbol, e.to_string());
+                        return Err(e);
                     }
-                } else {
-                    txn_builder.add(ix);
-                    prev_tx = tx;
                 }
-            } else {
+                txn_builder.clear();
                 txn_builder.add(ix);
+                prev_tx = txn_builder.build_unsigned(&signer.pubkey());
+            } else {
+                prev_tx = tx;
             }
         }
 
-        if !submitted {
-            let tx = txn_builder.build(blockhash, signer, None);
-            let res = self.send_and_confirm_transaction(&tx).await;
-            match res {
-                Ok(_) => (),
+        if txn_builder.len() != 0 {
+            signer.sign_transaction(&mut prev_tx, blockhash).await?;
+            match self.send_and_confirm_transaction(&prev_tx).await {
+                Ok(s) => signatures.push(s),
                 Err(e) => {
                     warn!("[ORDERMGR-{}] There was an error submitting transaction and waiting for confirmation: {}", self.symbol, e.to_string());
                     return Err(e);
@@ -592,12 +1521,23 @@ impl OrderManager {
     async fn send_and_confirm_transaction(
         self: &Arc<Self>,
         tx: &Transaction,
-    ) -> Result<Signature, ClientError> {
+    ) -> Result<Signature, TxError> {
+        if let Some(dry_run) = &self.dry_run {
+            return Ok(self.dry_run_transaction(tx, dry_run.simulate).await?);
+        }
+
+        self.metrics.transactions_sent.inc();
+        let sent_at = Instant::now();
         let submit_res = self.rpc_client.send_and_confirm_transaction(tx).await;
 
         match submit_res {
             Ok(s) => {
+                self.metrics.transactions_landed.inc();
+                self.metrics
+                    .confirmation_latency
+                    .observe(sent_at.elapsed().as_secs_f64());
                 info!(
+                    signature = s.to_string();
                     "[ORDERMGR-{}] Successfully submitted transaction. Transaction signature: {}",
                     self.symbol,
                     s.to_string()
@@ -605,18 +1545,130 @@ impl OrderManager {
                 Ok(s)
             }
             Err(e) => {
+                self.metrics.transactions_failed.inc();
+                let mut error = TxError::from(e);
+                if error.needs_logs() {
+                    let logs = self.fetch_transaction_logs(&tx.signatures[0]).await;
+                    error = error.with_logs(logs);
+                }
                 warn!(
                     "[ORDERMGR-{}] There was an error submitting transaction: {}",
+                    self.symbol, error
+                );
+                if let Some(logs) = error.logs() {
+                    for log in logs.logs.iter() {
+                        debug!("[ORDERMGR-{}] Transaction log: {}", self.symbol, log);
+                    }
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// the logs of a transaction which failed after it landed
+    async fn fetch_transaction_logs(self: &Arc<Self>, signature: &Signature) -> TxLogs {
+        match self
+            .rpc_client
+            .get_transaction(signature, UiTransactionEncoding::Json)
+            .await
+        {
+            Ok(tx) => TxLogs::parse(
+                tx.transaction
+                    .meta
+                    .and_then(|m| m.log_messages)
+                    .unwrap_or_default(),
+            ),
+            Err(e) => {
+                warn!(
+                    "[ORDERMGR-{}] Failed to fetch the logs of transaction {}: {}",
                     self.symbol,
+                    signature,
                     e.to_string()
                 );
-                Err(e)
+                TxLogs::default()
             }
         }
     }
+
+    /// logs the transaction which would have been sent and simulates it if configured to,
+    /// the signature is returned as if the transaction had been sent unless the simulation failed
+    async fn dry_run_transaction(
+        self: &Arc<Self>,
+        tx: &Transaction,
+        simulate: bool,
+    ) -> Result<Signature, ClientError> {
+        let signature = tx.signatures.first().copied().unwrap_or_default();
+        info!(
+            signature = signature.to_string();
+            "[ORDERMGR-{}] Dry run, not sending transaction with {} instructions and {} bytes of message data.",
+            self.symbol,
+            tx.message.instructions.len(),
+            tx.message_data().len()
+        );
+
+        if !simulate {
+            return Ok(signature);
+        }
+
+        self.metrics.transactions_simulated.inc();
+        let result = match self.rpc_client.simulate_transaction(tx).await {
+            Ok(r) => r.value,
+            Err(e) => {
+                warn!(
+                    "[ORDERMGR-{}] Failed to simulate transaction: {}",
+                    self.symbol,
+                    e.to_string()
+                );
+                return Err(e);
+            }
+        };
+
+        let logs = result.logs.unwrap_or_default();
+        match result.err {
+            None => {
+                info!(
+                    signature = signature.to_string();
+                    "[ORDERMGR-{}] Simulated transaction succeeded, compute units consumed: {}",
+                    self.symbol,
+                    result.units_consumed.unwrap_or_default()
+                );
+                for log in logs.iter() {
+                    debug!("[ORDERMGR-{}] Simulation log: {}", self.symbol, log);
+                }
+            }
+            Some(e) => {
+                self.metrics.simulations_failed.inc();
+                let tx_logs = TxLogs::parse(logs);
+                let reason = match e {
+                    TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+                        format!(
+                            "instruction {} failed with {}",
+                            index,
+                            ProgramErrorCode::decode(code, tx_logs.failed_program.as_ref())
+                        )
+                    }
+                    e => e.to_string(),
+                };
+                warn!(
+                    signature = signature.to_string();
+                    "[ORDERMGR-{}] Simulated transaction would have failed: {}{}",
+                    self.symbol,
+                    reason,
+                    tx_logs.error().map(|e| format!(", {}", e)).unwrap_or_default()
+                );
+                for log in tx_logs.logs.iter() {
+                    warn!("[ORDERMGR-{}] Simulation log: {}", self.symbol, log);
+                }
+                // failed like a transaction rejected in preflight would have
+                return Err(e.into());
+            }
+        }
+
+        Ok(signature)
+    }
 }
 
-async fn get_open_orders(open_orders: &OpenOrders) -> Vec<ManagedOrder> {
+pub async fn get_open_orders(open_orders: &OpenOrders) -> Vec<ManagedOrder> {
     let mut oo: Vec<ManagedOrder> = Vec::new();
     let orders = open_orders.orders;
 
@@ -641,10 +1693,7 @@ async fn get_open_orders(open_orders: &OpenOrders) -> Vec<ManagedOrder> {
     oo
 }
 
-async fn get_open_orders_with_qty(
-    open_orders: &OpenOrders,
-    orderbook: &OrderBook,
-) -> Vec<ManagedOrder> {
+fn get_open_orders_with_qty(open_orders: &OpenOrders, orderbook: &OrderBook) -> Vec<ManagedOrder> {
     let mut oo: Vec<ManagedOrder> = Vec::new();
     let orders = open_orders.orders;
 
@@ -655,15 +1704,15 @@ async fn get_open_orders_with_qty(
         if order_id != u128::default() {
             let price = (order_id >> 64) as u64;
             let side = open_orders.slot_side(i as u8).unwrap();
-            let ob_order = get_order_book_line(orderbook, order_id, side).await;
+            let ob_order = get_order_book_line(orderbook, order_id, side);
 
-            if ob_order.is_some() {
+            if let Some(ob_order) = ob_order {
                 oo.push(ManagedOrder {
                     order_id,
                     client_order_id,
                     side,
                     price,
-                    quantity: ob_order.unwrap().quantity,
+                    quantity: ob_order.quantity,
                 });
             }
         }
@@ -672,36 +1721,58 @@ async fn get_open_orders_with_qty(
     oo
 }
 
-async fn get_order_book_line(
-    orderbook: &OrderBook,
-    order_id: u128,
-    side: Side,
-) -> Option<OrderBookOrder> {
-    if side == Side::Ask {
-        for order in orderbook.asks.read().await.iter() {
-            if order.order_id == order_id {
-                return Some(OrderBookOrder {
-                    order_id: order.order_id,
-                    price: order.price,
-                    quantity: order.quantity,
-                    client_order_id: order.client_order_id,
-                });
-            }
-        }
-    }
+/// sets the quantity of the orders which rest in the book, those which do not have nothing left
+fn with_book_quantities(orders: Vec<ManagedOrder>, orderbook: &OrderBook) -> Vec<ManagedOrder> {
+    orders
+        .into_iter()
+        .map(|o| match get_order_book_line(orderbook, o.order_id, o.side) {
+            Some(ob_order) => ManagedOrder {
+                quantity: ob_order.quantity,
+                ..o
+            },
+            None => o,
+        })
+        .collect()
+}
 
-    if side == Side::Bid {
-        for order in orderbook.bids.read().await.iter() {
-            if order.order_id == order_id {
-                return Some(OrderBookOrder {
-                    order_id: order.order_id,
-                    price: order.price,
-                    quantity: order.quantity,
-                    client_order_id: order.client_order_id,
-                });
-            }
+fn get_order_book_line(orderbook: &OrderBook, order_id: u128, side: Side) -> Option<OrderBookOrder> {
+    orderbook.find_order(side, order_id).copied()
+}
+
+/// whether an order of the given side from `first_client_order_id` on rests in the account
+fn is_placed(orders: &[ManagedOrder], side: Side, first_client_order_id: u64) -> bool {
+    orders
+        .iter()
+        .any(|o| o.side == side && o.client_order_id >= first_client_order_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(side: Side, client_order_id: u64) -> ManagedOrder {
+        ManagedOrder {
+            order_id: (100_u128 << 64) | client_order_id as u128,
+            client_order_id,
+            price: 100,
+            quantity: 0,
+            side,
         }
     }
 
-    None
+    #[test]
+    fn only_orders_from_the_first_client_order_id_were_placed() {
+        let orders = vec![
+            order(Side::Bid, 3),
+            order(Side::Ask, 5),
+            order(Side::Bid, 6),
+        ];
+
+        assert!(is_placed(&orders, Side::Bid, 6));
+        assert!(is_placed(&orders, Side::Ask, 5));
+        assert!(!is_placed(&orders, Side::Ask, 6));
+        // an order resting from an earlier requote does not count
+        assert!(!is_placed(&orders[..1], Side::Bid, 4));
+        assert!(!is_placed(&[], Side::Bid, 0));
+    }
 }
@@ -0,0 +1,251 @@
+use {
+    super::{OrderSide, StateEvent, StateStore},
+    crate::metrics,
+    chrono::{TimeZone, Utc},
+    log::{info, warn},
+    prometheus::{Gauge, IntCounter},
+    serde::{Deserialize, Serialize},
+    std::sync::Arc,
+    tokio::sync::RwLock,
+};
+
+const BPS_UNIT: f64 = 10_000.0;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PnlConfig {
+    /// the fee charged on our fills, which always provide liquidity, negative for a rebate
+    pub maker_fee_bps: f64,
+}
+
+/// The realized pnl of our fills, tracked with the average cost of the position, in native units
+/// of quote. The daily figures start over with the first fill of each utc day.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PnlState {
+    /// the net position built by our fills, in native units of base
+    pub position: i64,
+    pub avg_entry_price: f64,
+    /// net of fees
+    pub realized_pnl: f64,
+    pub fees: f64,
+    pub volume: f64,
+    pub fills: u64,
+    /// the utc day the daily figures are for, e.g. 2022-07-01
+    pub day: String,
+    pub daily_realized_pnl: f64,
+    pub daily_fees: f64,
+    pub daily_volume: f64,
+}
+
+impl PnlState {
+    /// applies a fill of `quantity` native units of base at `price` which was charged `fee`
+    pub fn apply_fill(
+        &mut self,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+        fee: f64,
+        timestamp_ms: i64,
+    ) {
+        let day = Utc
+            .timestamp_millis(timestamp_ms)
+            .format("%Y-%m-%d")
+            .to_string();
+        if day != self.day {
+            self.day = day;
+            self.daily_realized_pnl = 0.0;
+            self.daily_fees = 0.0;
+            self.daily_volume = 0.0;
+        }
+
+        let price = price as f64;
+        let notional = price * quantity as f64;
+        let signed_quantity = match side {
+            OrderSide::Bid => quantity as i64,
+            OrderSide::Ask => -(quantity as i64),
+        };
+
+        let mut pnl = 0.0;
+        if self.position == 0 || self.position.signum() == signed_quantity.signum() {
+            let size = self.position.unsigned_abs() as f64;
+            self.avg_entry_price =
+                (self.avg_entry_price * size + notional) / (size + quantity as f64);
+        } else {
+            let closed = self.position.unsigned_abs().min(quantity);
+            pnl = closed as f64 * (price - self.avg_entry_price) * self.position.signum() as f64;
+            if quantity > closed {
+                // the fill flipped the position, the rest of it opened one at this price
+                self.avg_entry_price = price;
+            }
+        }
+        self.position += signed_quantity;
+        if self.position == 0 {
+            self.avg_entry_price = 0.0;
+        }
+
+        self.realized_pnl += pnl - fee;
+        self.daily_realized_pnl += pnl - fee;
+        self.fees += fee;
+        self.daily_fees += fee;
+        self.volume += notional;
+        self.daily_volume += notional;
+        self.fills += 1;
+    }
+}
+
+struct PnlTrackerMetrics {
+    realized_pnl: Gauge,
+    daily_realized_pnl: Gauge,
+    fees: Gauge,
+    volume: Gauge,
+    fills: IntCounter,
+}
+
+impl PnlTrackerMetrics {
+    fn new(market: &str) -> Self {
+        Self {
+            realized_pnl: metrics::gauge(
+                "mm_realized_pnl",
+                "Realized pnl of our fills net of fees, in quote, kept across restarts with a state store.",
+                market,
+            ),
+            daily_realized_pnl: metrics::gauge(
+                "mm_daily_realized_pnl",
+                "Realized pnl of our fills net of fees since midnight utc, in quote.",
+                market,
+            ),
+            fees: metrics::gauge(
+                "mm_fees_paid",
+                "Fees paid on our fills, in quote, negative for rebates.",
+                market,
+            ),
+            volume: metrics::gauge(
+                "mm_fill_volume",
+                "Notional of our fills, in quote.",
+                market,
+            ),
+            fills: metrics::int_counter(
+                "mm_fills_total",
+                "Fills of our orders seen in the book since the market maker started.",
+                market,
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.realized_pnl);
+        metrics::register(&self.daily_realized_pnl);
+        metrics::register(&self.fees);
+        metrics::register(&self.volume);
+        metrics::register(&self.fills);
+    }
+
+    fn set(&self, state: &PnlState, quote_divisor: f64) {
+        self.realized_pnl.set(state.realized_pnl / quote_divisor);
+        self.daily_realized_pnl
+            .set(state.daily_realized_pnl / quote_divisor);
+        self.fees.set(state.fees / quote_divisor);
+        self.volume.set(state.volume / quote_divisor);
+    }
+}
+
+/// Tracks the pnl of the fills of our orders and writes them to the state store, if there is one,
+/// which the state is restored from at startup.
+pub struct PnlTracker {
+    symbol: String,
+    config: PnlConfig,
+    /// converts native quote amounts to ui units
+    quote_divisor: f64,
+    state: RwLock<PnlState>,
+    state_store: Option<Arc<StateStore>>,
+    metrics: PnlTrackerMetrics,
+}
+
+impl PnlTracker {
+    pub fn default() -> Self {
+        Self {
+            symbol: "".to_string(),
+            config: PnlConfig::default(),
+            quote_divisor: 1.0,
+            state: RwLock::new(PnlState::default()),
+            state_store: None,
+            metrics: PnlTrackerMetrics::new(""),
+        }
+    }
+
+    pub fn new(
+        symbol: String,
+        config: PnlConfig,
+        quote_decimals: u8,
+        state_store: Option<Arc<StateStore>>,
+    ) -> Self {
+        let quote_divisor = 10_u64.pow(quote_decimals.into()) as f64;
+        let metrics = PnlTrackerMetrics::new(&symbol);
+        metrics.register();
+
+        let state = match &state_store {
+            Some(s) => s.pnl_state(),
+            None => PnlState::default(),
+        };
+        metrics.set(&state, quote_divisor);
+        info!(
+            "[PNL-{}] Realized pnl: {} | Today: {} | Fees: {} | Position: {}.",
+            symbol,
+            state.realized_pnl / quote_divisor,
+            state.daily_realized_pnl / quote_divisor,
+            state.fees / quote_divisor,
+            state.position
+        );
+
+        Self {
+            symbol,
+            config,
+            quote_divisor,
+            state: RwLock::new(state),
+            state_store,
+            metrics,
+        }
+    }
+
+    /// records a fill of one of our orders, the price and quantity as the book reports them
+    pub async fn record_fill(
+        &self,
+        side: OrderSide,
+        order_id: u128,
+        client_order_id: u64,
+        price: u64,
+        quantity: u64,
+    ) {
+        let timestamp_ms = Utc::now().timestamp_millis();
+        let fee = price as f64 * quantity as f64 * self.config.maker_fee_bps / BPS_UNIT;
+        let mut state = self.state.write().await;
+        state.apply_fill(side, price, quantity, fee, timestamp_ms);
+        self.metrics.set(&state, self.quote_divisor);
+        self.metrics.fills.inc();
+        info!(
+            client_order_id = client_order_id;
+            "[PNL-{}] Realized pnl: {} | Today: {} | Position: {}.",
+            self.symbol,
+            state.realized_pnl / self.quote_divisor,
+            state.daily_realized_pnl / self.quote_divisor,
+            state.position
+        );
+
+        if let Some(state_store) = &self.state_store {
+            let event = StateEvent::Fill {
+                order_id,
+                client_order_id,
+                side,
+                price,
+                quantity,
+                fee,
+                position: state.position,
+                realized_pnl: state.realized_pnl,
+            };
+            if let Err(e) = state_store.record_fill(timestamp_ms, event, &state) {
+                warn!("[PNL-{}] Failed to persist the fill: {}", self.symbol, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use {
+    super::{ManagedOrder, OrderManagerConfig},
+    crate::orderbook::TrackedQueuePosition,
+    std::time::Duration,
+};
+
+const BPS_UNIT: u128 = 10_000;
+
+/// Why a resting order is considered stale and gets replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaleReason {
+    PriceOutOfTolerance,
+    SizeOutOfTolerance,
+    MaxAgeExceeded,
+    /// the order reaches its `max_ts` soon
+    Expiring,
+}
+
+/// Decides whether a resting order is close enough to the desired quote to be kept.
+///
+/// An order is kept while its price is within `price_tolerance_bps` of the desired price, its size
+/// is within `size_tolerance_lots` of the desired size and it is younger than `max_order_age_secs`.
+/// Orders with queue priority additionally get `queue_priority_size_tolerance_bps` of size drift.
+/// With an order ttl, orders are also replaced once they are within `order_ttl_refresh_secs` of
+/// their expiry.
+#[derive(Debug, Default, Clone)]
+pub struct RequotePolicy {
+    price_tolerance_bps: u16,
+    size_tolerance_lots: u64,
+    max_order_age: Option<Duration>,
+    queue_priority_size_tolerance_bps: u16,
+    queue_priority_max_size_ahead_bps: u16,
+    order_ttl: Option<Duration>,
+    order_ttl_refresh: Duration,
+}
+
+impl RequotePolicy {
+    pub fn new(config: &OrderManagerConfig) -> Self {
+        Self {
+            price_tolerance_bps: config.price_tolerance_bps,
+            size_tolerance_lots: config.size_tolerance_lots,
+            max_order_age: if config.max_order_age_secs == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(config.max_order_age_secs))
+            },
+            queue_priority_size_tolerance_bps: config.queue_priority_size_tolerance_bps,
+            queue_priority_max_size_ahead_bps: config.queue_priority_max_size_ahead_bps,
+            order_ttl: if config.order_ttl_secs == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(config.order_ttl_secs))
+            },
+            order_ttl_refresh: Duration::from_secs(config.order_ttl_refresh_secs),
+        }
+    }
+
+    pub fn has_order_ttl(&self) -> bool {
+        self.order_ttl.is_some()
+    }
+
+    /// the `max_ts` of an order placed at the unix timestamp `now`
+    pub fn max_ts(&self, now: i64) -> i64 {
+        match self.order_ttl {
+            Some(ttl) => now + ttl.as_secs() as i64,
+            None => i64::MAX,
+        }
+    }
+
+    /// whether an order with the given `max_ts` should be replaced before it expires, orders
+    /// we did not place this run are assumed to expire since we do not know their `max_ts`
+    pub fn is_expiring(&self, max_ts: Option<i64>, now: i64) -> bool {
+        if self.order_ttl.is_none() {
+            return false;
+        }
+        match max_ts {
+            Some(max_ts) => max_ts - now <= self.order_ttl_refresh.as_secs() as i64,
+            None => true,
+        }
+    }
+
+    /// evaluates a resting order against the desired price and size, both in lots
+    ///
+    /// returns `None` if the order should be kept
+    pub fn evaluate(
+        &self,
+        order: &ManagedOrder,
+        order_size_lots: u64,
+        desired_price: u64,
+        desired_size_lots: u64,
+        age: Duration,
+        queue_position: Option<&TrackedQueuePosition>,
+    ) -> Option<StaleReason> {
+        if let Some(max_order_age) = self.max_order_age {
+            if age > max_order_age {
+                return Some(StaleReason::MaxAgeExceeded);
+            }
+        }
+
+        if deviation_bps(order.price, desired_price) > self.price_tolerance_bps as u128 {
+            return Some(StaleReason::PriceOutOfTolerance);
+        }
+
+        if abs_diff(order_size_lots, desired_size_lots) <= self.size_tolerance_lots {
+            return None;
+        }
+
+        // avoid giving up a good spot in the queue because the desired size drifted slightly
+        let has_priority = match queue_position {
+            Some(p) => p.current.size_ahead_bps() <= self.queue_priority_max_size_ahead_bps as u64,
+            None => false,
+        };
+        if has_priority
+            && deviation_bps(order_size_lots, desired_size_lots)
+                <= self.queue_priority_size_tolerance_bps as u128
+        {
+            return None;
+        }
+
+        Some(StaleReason::SizeOutOfTolerance)
+    }
+}
+
+#[inline]
+fn abs_diff(a: u64, b: u64) -> u64 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// the deviation of `value` from `target`, relative to `target`
+#[inline]
+fn deviation_bps(value: u64, target: u64) -> u128 {
+    if target == 0 {
+        return if value == 0 { 0 } else { u128::MAX };
+    }
+    abs_diff(value, target) as u128 * BPS_UNIT / target as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::orderbook::QueuePosition, serum_dex::matching::Side};
+
+    const DESIRED_PRICE: u64 = 10_000;
+    const DESIRED_SIZE: u64 = 12;
+
+    fn policy() -> RequotePolicy {
+        RequotePolicy::new(&OrderManagerConfig {
+            price_tolerance_bps: 10,
+            size_tolerance_lots: 1,
+            max_order_age_secs: 60,
+            queue_priority_size_tolerance_bps: 2_000,
+            queue_priority_max_size_ahead_bps: 1_000,
+            ..OrderManagerConfig::default()
+        })
+    }
+
+    fn order(price: u64) -> ManagedOrder {
+        ManagedOrder {
+            order_id: 1,
+            client_order_id: 1,
+            price,
+            quantity: 0,
+            side: Side::Bid,
+        }
+    }
+
+    fn queue_position(size_ahead: u64, level_quantity: u64) -> TrackedQueuePosition {
+        TrackedQueuePosition {
+            current: QueuePosition {
+                order_id: 1,
+                client_order_id: 1,
+                side: Side::Bid,
+                price: DESIRED_PRICE,
+                quantity: 10,
+                size_ahead,
+                orders_ahead: if size_ahead == 0 { 0 } else { 1 },
+                level_quantity,
+            },
+            initial_size_ahead: size_ahead,
+            advances: 0,
+        }
+    }
+
+    fn evaluate(
+        policy: &RequotePolicy,
+        price: u64,
+        size: u64,
+        age_secs: u64,
+        queue_position: Option<&TrackedQueuePosition>,
+    ) -> Option<StaleReason> {
+        policy.evaluate(
+            &order(price),
+            size,
+            DESIRED_PRICE,
+            DESIRED_SIZE,
+            Duration::from_secs(age_secs),
+            queue_position,
+        )
+    }
+
+    #[test]
+    fn keeps_orders_at_the_edge_of_the_price_band() {
+        let policy = policy();
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, DESIRED_SIZE, 0, None),
+            None
+        );
+        assert_eq!(evaluate(&policy, 10_010, DESIRED_SIZE, 0, None), None);
+        assert_eq!(evaluate(&policy, 9_990, DESIRED_SIZE, 0, None), None);
+        assert_eq!(
+            evaluate(&policy, 10_011, DESIRED_SIZE, 0, None),
+            Some(StaleReason::PriceOutOfTolerance)
+        );
+        assert_eq!(
+            evaluate(&policy, 9_989, DESIRED_SIZE, 0, None),
+            Some(StaleReason::PriceOutOfTolerance)
+        );
+    }
+
+    #[test]
+    fn keeps_orders_at_the_edge_of_the_size_band() {
+        let policy = policy();
+        assert_eq!(evaluate(&policy, DESIRED_PRICE, 11, 0, None), None);
+        assert_eq!(evaluate(&policy, DESIRED_PRICE, 13, 0, None), None);
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, 10, 0, None),
+            Some(StaleReason::SizeOutOfTolerance)
+        );
+    }
+
+    #[test]
+    fn replaces_orders_older_than_the_max_age() {
+        let policy = policy();
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, DESIRED_SIZE, 60, None),
+            None
+        );
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, DESIRED_SIZE, 61, None),
+            Some(StaleReason::MaxAgeExceeded)
+        );
+    }
+
+    #[test]
+    fn max_age_of_zero_never_expires() {
+        let policy = RequotePolicy::new(&OrderManagerConfig::default());
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, DESIRED_SIZE, u32::MAX as u64, None),
+            None
+        );
+    }
+
+    #[test]
+    fn orders_with_queue_priority_get_more_size_drift() {
+        let policy = policy();
+        // 2 lots off 12 is 1666 bps, within the queue priority tolerance but not the size one
+        let front = queue_position(1, 20);
+        assert_eq!(evaluate(&policy, DESIRED_PRICE, 10, 0, Some(&front)), None);
+
+        // half of the level is ahead, so the order does not have priority
+        let back = queue_position(10, 20);
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, 10, 0, Some(&back)),
+            Some(StaleReason::SizeOutOfTolerance)
+        );
+
+        // 4 lots off 12 is 3333 bps, too far off even with priority
+        assert_eq!(
+            evaluate(&policy, DESIRED_PRICE, 8, 0, Some(&front)),
+            Some(StaleReason::SizeOutOfTolerance)
+        );
+    }
+
+    #[test]
+    fn queue_priority_does_not_widen_the_price_band() {
+        let policy = policy();
+        let front = queue_position(0, 20);
+        assert_eq!(
+            evaluate(&policy, 10_011, DESIRED_SIZE, 0, Some(&front)),
+            Some(StaleReason::PriceOutOfTolerance)
+        );
+    }
+
+    #[test]
+    fn deviation_from_a_zero_target() {
+        assert_eq!(deviation_bps(0, 0), 0);
+        assert_eq!(deviation_bps(1, 0), u128::MAX);
+        assert_eq!(deviation_bps(105, 100), 500);
+    }
+
+    fn ttl_policy(order_ttl_secs: u64, order_ttl_refresh_secs: u64) -> RequotePolicy {
+        RequotePolicy::new(&OrderManagerConfig {
+            order_ttl_secs,
+            order_ttl_refresh_secs,
+            ..OrderManagerConfig::default()
+        })
+    }
+
+    #[test]
+    fn orders_expire_the_ttl_after_they_are_placed() {
+        let policy = ttl_policy(120, 30);
+        assert!(policy.has_order_ttl());
+        assert_eq!(policy.max_ts(1_000), 1_120);
+    }
+
+    #[test]
+    fn orders_are_replaced_within_the_refresh_window_of_their_expiry() {
+        let policy = ttl_policy(120, 30);
+        assert!(!policy.is_expiring(Some(1_120), 1_000));
+        assert!(!policy.is_expiring(Some(1_120), 1_089));
+        assert!(policy.is_expiring(Some(1_120), 1_090));
+        assert!(policy.is_expiring(Some(1_120), 1_200));
+        // we do not know when orders from before a restart expire
+        assert!(policy.is_expiring(None, 1_000));
+    }
+
+    #[test]
+    fn orders_without_a_ttl_never_expire() {
+        let policy = ttl_policy(0, 30);
+        assert!(!policy.has_order_ttl());
+        assert_eq!(policy.max_ts(1_000), i64::MAX);
+        assert!(!policy.is_expiring(Some(1_000), 2_000));
+        assert!(!policy.is_expiring(None, 2_000));
+    }
+}
@@ -0,0 +1,295 @@
+use {
+    super::PnlState,
+    chrono::Utc,
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    serum_dex::matching::Side,
+    std::{
+        fs::{self, File, OpenOptions},
+        io::{self, BufRead, BufReader, Write},
+        path::{Path, PathBuf},
+        sync::Mutex,
+    },
+};
+
+const SNAPSHOT_FILE: &str = "state.json";
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StateStoreConfig {
+    /// where the snapshot and the journals are kept, one directory per market maker
+    pub directory: String,
+    /// the journal is folded into the snapshot after this many records, the old journal is kept
+    /// next to it as `journal.<seq>.jsonl` for the audit trail
+    pub snapshot_every: u64,
+}
+
+impl Default for StateStoreConfig {
+    fn default() -> Self {
+        Self {
+            directory: "state".to_string(),
+            snapshot_every: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+impl From<Side> for OrderSide {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Bid => Self::Bid,
+            Side::Ask => Self::Ask,
+        }
+    }
+}
+
+/// Something that happened to our orders, as written to the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StateEvent {
+    /// the price and quantity in lots, as the order was placed
+    #[serde(rename_all = "camelCase")]
+    OrderSubmitted {
+        client_order_id: u64,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+        max_ts: i64,
+    },
+    #[serde(rename_all = "camelCase")]
+    CancelSubmitted {
+        order_id: u128,
+        client_order_id: u64,
+    },
+    /// the price and quantity as the book reports them, the quantity in native units of base
+    #[serde(rename_all = "camelCase")]
+    Fill {
+        order_id: u128,
+        client_order_id: u64,
+        side: OrderSide,
+        price: u64,
+        quantity: u64,
+        fee: f64,
+        /// the position and realized pnl after the fill
+        position: i64,
+        realized_pnl: f64,
+    },
+}
+
+/// A line of the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateRecord {
+    pub seq: u64,
+    pub timestamp_ms: i64,
+    pub event: StateEvent,
+}
+
+/// The state as of the record with sequence number `seq`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateSnapshot {
+    seq: u64,
+    next_client_order_id: u64,
+    pnl: PnlState,
+}
+
+struct StoreInner {
+    journal: File,
+    snapshot: StateSnapshot,
+    records_since_snapshot: u64,
+}
+
+/// Keeps the order and fill history and the pnl across restarts in an append only journal of
+/// json lines, folded into a snapshot every so often. At startup the snapshot is loaded and the
+/// records written after it are replayed, so nothing is lost if the process crashes.
+pub struct StateStore {
+    directory: PathBuf,
+    snapshot_every: u64,
+    inner: Mutex<StoreInner>,
+}
+
+impl StateStore {
+    pub fn open(config: &StateStoreConfig) -> io::Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        fs::create_dir_all(&directory)?;
+
+        let snapshot_path = directory.join(SNAPSHOT_FILE);
+        let mut snapshot = if snapshot_path.exists() {
+            serde_json::from_slice::<StateSnapshot>(&fs::read(&snapshot_path)?)?
+        } else {
+            StateSnapshot::default()
+        };
+
+        let journal_path = directory.join(JOURNAL_FILE);
+        let replayed = if journal_path.exists() {
+            replay(&journal_path, &mut snapshot)?
+        } else {
+            0
+        };
+        info!(
+            "Loaded the state from {}, replayed {} records up to {}.",
+            directory.display(),
+            replayed,
+            snapshot.seq
+        );
+
+        // fold what was replayed into a fresh snapshot so the next start does not replay it again
+        if replayed > 0 {
+            write_snapshot(&directory, &snapshot)?;
+            archive_journal(&directory, snapshot.seq)?;
+        }
+
+        Ok(Self {
+            snapshot_every: config.snapshot_every,
+            inner: Mutex::new(StoreInner {
+                journal: open_journal(&directory)?,
+                snapshot,
+                records_since_snapshot: 0,
+            }),
+            directory,
+        })
+    }
+
+    pub fn pnl_state(&self) -> PnlState {
+        self.inner.lock().unwrap().snapshot.pnl.clone()
+    }
+
+    /// above every client order id submitted before, including by previous runs
+    pub fn next_client_order_id(&self) -> u64 {
+        self.inner.lock().unwrap().snapshot.next_client_order_id
+    }
+
+    pub fn record(&self, event: StateEvent) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let StateEvent::OrderSubmitted {
+            client_order_id, ..
+        } = event
+        {
+            let next = &mut inner.snapshot.next_client_order_id;
+            *next = (*next).max(client_order_id + 1);
+        }
+        self.append(&mut inner, Utc::now().timestamp_millis(), event)
+    }
+
+    /// records a fill along with the pnl after it
+    pub fn record_fill(
+        &self,
+        timestamp_ms: i64,
+        event: StateEvent,
+        pnl: &PnlState,
+    ) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.snapshot.pnl = pnl.clone();
+        self.append(&mut inner, timestamp_ms, event)
+    }
+
+    fn append(
+        &self,
+        inner: &mut StoreInner,
+        timestamp_ms: i64,
+        event: StateEvent,
+    ) -> io::Result<()> {
+        let record = StateRecord {
+            seq: inner.snapshot.seq + 1,
+            timestamp_ms,
+            event,
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        inner.journal.write_all(&line)?;
+        inner.snapshot.seq = record.seq;
+        inner.records_since_snapshot += 1;
+
+        if self.snapshot_every > 0 && inner.records_since_snapshot >= self.snapshot_every {
+            write_snapshot(&self.directory, &inner.snapshot)?;
+            archive_journal(&self.directory, inner.snapshot.seq)?;
+            inner.journal = open_journal(&self.directory)?;
+            inner.records_since_snapshot = 0;
+        }
+        Ok(())
+    }
+}
+
+/// applies the records of the journal which are newer than the snapshot, returns how many
+fn replay(journal_path: &Path, snapshot: &mut StateSnapshot) -> io::Result<u64> {
+    let mut replayed: u64 = 0;
+
+    for (i, line) in BufReader::new(File::open(journal_path)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        let record = match serde_json::from_str::<StateRecord>(&line) {
+            Ok(r) => r,
+            Err(e) => {
+                // the last line is cut short if the process died while writing it
+                warn!(
+                    "Skipping line {} of {}, it cannot be parsed: {}",
+                    i + 1,
+                    journal_path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        // a crash between writing the snapshot and archiving the journal leaves records behind
+        // which are already part of the snapshot
+        if record.seq <= snapshot.seq {
+            continue;
+        }
+
+        match record.event {
+            StateEvent::OrderSubmitted {
+                client_order_id, ..
+            } => {
+                snapshot.next_client_order_id =
+                    snapshot.next_client_order_id.max(client_order_id + 1);
+            }
+            StateEvent::Fill {
+                side,
+                price,
+                quantity,
+                fee,
+                ..
+            } => {
+                snapshot
+                    .pnl
+                    .apply_fill(side, price, quantity, fee, record.timestamp_ms);
+            }
+            StateEvent::CancelSubmitted { .. } => (),
+        }
+        snapshot.seq = record.seq;
+        replayed += 1;
+    }
+
+    Ok(replayed)
+}
+
+/// writes the snapshot through a rename so a crash never leaves a partially written one
+fn write_snapshot(directory: &Path, snapshot: &StateSnapshot) -> io::Result<()> {
+    let tmp = directory.join(format!("{}.tmp", SNAPSHOT_FILE));
+    fs::write(&tmp, serde_json::to_vec_pretty(snapshot)?)?;
+    fs::rename(&tmp, directory.join(SNAPSHOT_FILE))
+}
+
+fn archive_journal(directory: &Path, seq: u64) -> io::Result<()> {
+    fs::rename(
+        directory.join(JOURNAL_FILE),
+        directory.join(format!("journal.{}.jsonl", seq)),
+    )
+}
+
+fn open_journal(directory: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(directory.join(JOURNAL_FILE))
+}
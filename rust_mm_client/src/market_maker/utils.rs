@@ -1,6 +1,7 @@
 use {
+    crate::signer::TxSigner,
     cypher::{
-        client::{cancel_order_v2_ix, new_order_v3_ix, ToPubkey},
+        client::{cancel_order_v2_ix, new_order_v3_ix, settle_funds_ix, ToPubkey},
         utils::{derive_dex_market_authority, gen_dex_vault_signer_key},
         CypherGroup, CypherMarket, CypherToken,
     },
@@ -8,7 +9,7 @@ use {
         instruction::{CancelOrderInstructionV2, NewOrderInstructionV3},
         state::MarketStateV2,
     },
-    solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Keypair, signer::Signer},
+    solana_sdk::{instruction::Instruction, pubkey::Pubkey},
     std::convert::identity,
 };
 
@@ -20,7 +21,7 @@ pub fn get_cancel_order_ix(
     dex_market_state: &MarketStateV2,
     open_orders_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn TxSigner,
     ix_data: CancelOrderInstructionV2,
 ) -> Instruction {
     let dex_vault_signer = gen_dex_vault_signer_key(
@@ -57,7 +58,7 @@ pub fn get_new_order_ix(
     dex_market_state: &MarketStateV2,
     open_orders_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
-    signer: &Keypair,
+    signer: &dyn TxSigner,
     ix_data: NewOrderInstructionV3,
 ) -> Instruction {
     let dex_vault_signer = gen_dex_vault_signer_key(
@@ -85,3 +86,33 @@ pub fn get_new_order_ix(
         ix_data,
     )
 }
+
+#[allow(clippy::too_many_arguments)]
+pub fn get_settle_funds_ix(
+    cypher_group: &CypherGroup,
+    cypher_market: &CypherMarket,
+    cypher_token: &CypherToken,
+    dex_market_state: &MarketStateV2,
+    open_orders_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    signer: &dyn TxSigner,
+) -> Instruction {
+    let dex_vault_signer = gen_dex_vault_signer_key(
+        dex_market_state.vault_signer_nonce,
+        &cypher_market.dex_market,
+    );
+    settle_funds_ix(
+        &cypher_group.self_address,
+        &cypher_group.vault_signer,
+        cypher_user_pubkey,
+        &signer.pubkey(),
+        &cypher_token.mint,
+        &cypher_token.vault,
+        &cypher_group.quote_vault(),
+        &cypher_market.dex_market,
+        open_orders_pubkey,
+        &identity(dex_market_state.coin_vault).to_pubkey(),
+        &identity(dex_market_state.pc_vault).to_pubkey(),
+        &dex_vault_signer,
+    )
+}
@@ -1,16 +1,101 @@
 use {
-    super::{order_manager::OrderManager, InventoryManager},
-    crate::MarketMakerError,
+    super::{order_manager::OrderManager, InventoryManager, InventoryParams},
+    crate::{
+        config::MarketMakerConfig,
+        error::{ErrorClass, OrderRejection},
+        metrics,
+        orderbook::OrderBookUpdate,
+        services::{ControlCommand, ControlRequest},
+        watchdog, MarketMakerError,
+    },
+    bitflags::bitflags,
     cypher::{CypherGroup, CypherMarket, CypherUser},
     log::{info, warn},
-    solana_sdk::{pubkey::Pubkey, signature::Keypair},
-    std::{sync::Arc, time::Duration},
+    prometheus::{Gauge, IntCounter, IntCounterVec, IntGauge},
+    serde::{Deserialize, Serialize},
+    serde_json::{json, Value},
+    serum_dex::{matching::Side, state::OpenOrders},
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        path::Path,
+        sync::Arc,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
     tokio::sync::{
         broadcast::{channel, Receiver, Sender},
-        Mutex, RwLock,
+        mpsc, Mutex, Notify, RwLock,
     },
 };
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RequoteConfig {
+    /// the minimum amount of time between two consecutive requotes
+    pub min_requote_interval_ms: u64,
+    /// the worker requotes at least this often, even if no relevant event was received
+    pub heartbeat_interval_ms: u64,
+    /// oracle price moves smaller than this, relative to the price we last quoted around, are ignored
+    pub oracle_threshold_bps: u64,
+    /// how long to wait after a transient error, doubled for every consecutive one
+    pub error_backoff_ms: u64,
+    /// the upper bound of the backoff after consecutive transient errors
+    pub max_error_backoff_ms: u64,
+    /// quoting is paused after this many consecutive transient errors until it is resumed
+    /// through the control api, zero never pauses
+    pub max_consecutive_errors: u32,
+}
+
+impl Default for RequoteConfig {
+    fn default() -> Self {
+        Self {
+            min_requote_interval_ms: 500,
+            heartbeat_interval_ms: 5000,
+            oracle_threshold_bps: 5,
+            error_backoff_ms: 500,
+            max_error_backoff_ms: 30_000,
+            max_consecutive_errors: 10,
+        }
+    }
+}
+
+/// How the worker winds down once shutdown is requested: the quoting loop is stopped first,
+/// then the orders are cancelled until a freshly fetched open orders account is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ShutdownConfig {
+    /// how long to keep cancelling before giving up and leaving the remaining orders
+    pub cancel_timeout_secs: u64,
+    /// the interval between cancel attempts
+    pub cancel_retry_interval_ms: u64,
+    /// whether to settle the funds of the open orders account once the orders are cancelled
+    pub settle_funds: bool,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            cancel_timeout_secs: 60,
+            cancel_retry_interval_ms: 2000,
+            settle_funds: false,
+        }
+    }
+}
+
+bitflags! {
+    /// the provider events which caused the worker to requote
+    #[derive(Default)]
+    pub struct RequoteTriggers: u8 {
+        const ORACLE = 0b0000_0001;
+        const BOOK = 0b0000_0010;
+        const FILL = 0b0000_0100;
+        const POSITION = 0b0000_1000;
+        const HEARTBEAT = 0b0001_0000;
+        const CONTROL = 0b0010_0000;
+        const REJECTION = 0b0100_0000;
+        const STARTUP = 0b1000_0000;
+    }
+}
+
 pub struct WorkerConfig {
     pub market: Pubkey,
     pub c_asset_mint: Pubkey,
@@ -29,20 +114,86 @@ impl WorkerConfig {
     }
 }
 
+/// The quotes the worker last sent to the order manager.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteStatus {
+    pub bid_price: u64,
+    pub bid_size: i64,
+    pub ask_price: u64,
+    pub ask_size: i64,
+}
+
+struct WorkerMetrics {
+    oracle_price: IntGauge,
+    oracle_age: Gauge,
+    errors: IntCounterVec,
+    breaker_trips: IntCounter,
+}
+
+impl WorkerMetrics {
+    fn new(market: &str) -> Self {
+        Self {
+            oracle_price: metrics::int_gauge(
+                "mm_oracle_price",
+                "Most recent oracle price of the market.",
+                market,
+            ),
+            oracle_age: metrics::gauge(
+                "mm_oracle_age_seconds",
+                "Seconds since the oracle price of the market was last published.",
+                market,
+            ),
+            errors: metrics::int_counter_vec(
+                "mm_worker_errors_total",
+                "Errors while requoting, by how they were handled.",
+                market,
+                &["class"],
+            ),
+            breaker_trips: metrics::int_counter(
+                "mm_worker_breaker_trips_total",
+                "Times quoting was paused after too many consecutive errors.",
+                market,
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.oracle_price);
+        metrics::register(&self.oracle_age);
+        metrics::register(&self.errors);
+        metrics::register(&self.breaker_trips);
+    }
+}
+
 pub struct Worker {
     config: WorkerConfig,
+    mm_config: RwLock<Arc<MarketMakerConfig>>,
     inventory_manager: Arc<InventoryManager>,
     order_manager: Arc<OrderManager>,
+    requote_config: RwLock<RequoteConfig>,
     cypher_account_receiver: Mutex<Receiver<Box<CypherUser>>>,
     cypher_group_receiver: Mutex<Receiver<Box<CypherGroup>>>,
+    ob_receiver: Mutex<Receiver<Arc<OrderBookUpdate>>>,
+    oo_receiver: Mutex<Receiver<OpenOrders>>,
+    control_receiver: Mutex<mpsc::Receiver<ControlRequest>>,
     shutdown: Arc<Sender<bool>>,
+    paused: RwLock<bool>,
+    last_quotes: RwLock<Option<QuoteStatus>>,
+    triggers: Mutex<RequoteTriggers>,
+    requote_notify: Notify,
     latest_price: RwLock<u64>,
+    /// the unix timestamp at which the oracle last published the market's price
+    oracle_published_at: RwLock<u64>,
+    last_quoted_price: RwLock<u64>,
+    position: RwLock<Option<(u64, u64)>>,
+    open_orders: RwLock<Option<OpenOrders>>,
     cypher_user: RwLock<Option<CypherUser>>,
     cypher_group: RwLock<Option<CypherGroup>>,
     cypher_market: RwLock<CypherMarket>,
     cypher_user_pubkey: Pubkey,
     open_orders_pubkey: Pubkey,
-    signer: Arc<Keypair>,
+    metrics: WorkerMetrics,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -50,42 +201,67 @@ impl Worker {
     pub fn default() -> Self {
         Self {
             config: WorkerConfig::default(),
+            mm_config: RwLock::new(Arc::new(MarketMakerConfig::default())),
             inventory_manager: Arc::new(InventoryManager::default()),
             order_manager: Arc::new(OrderManager::default()),
+            requote_config: RwLock::new(RequoteConfig::default()),
             cypher_account_receiver: Mutex::new(channel::<Box<CypherUser>>(u16::MAX as usize).1),
             cypher_group_receiver: Mutex::new(channel::<Box<CypherGroup>>(u16::MAX as usize).1),
+            ob_receiver: Mutex::new(channel::<Arc<OrderBookUpdate>>(u16::MAX as usize).1),
+            oo_receiver: Mutex::new(channel::<OpenOrders>(u16::MAX as usize).1),
+            control_receiver: Mutex::new(mpsc::channel::<ControlRequest>(1).1),
             shutdown: Arc::new(channel::<bool>(1).0),
+            paused: RwLock::new(false),
+            last_quotes: RwLock::new(None),
+            triggers: Mutex::new(RequoteTriggers::empty()),
+            requote_notify: Notify::new(),
             latest_price: RwLock::new(u64::default()),
+            oracle_published_at: RwLock::new(u64::default()),
+            last_quoted_price: RwLock::new(u64::default()),
+            position: RwLock::new(None),
+            open_orders: RwLock::new(None),
             cypher_user: RwLock::new(None),
             cypher_group: RwLock::new(None),
             cypher_market: RwLock::new(CypherMarket::default()),
             cypher_user_pubkey: Pubkey::default(),
             open_orders_pubkey: Pubkey::default(),
-            signer: Arc::new(Keypair::new()),
+            metrics: WorkerMetrics::new(""),
         }
     }
 
     pub fn new(
         config: WorkerConfig,
+        mm_config: Arc<MarketMakerConfig>,
+        requote_config: RequoteConfig,
         inventory_manager: Arc<InventoryManager>,
         order_manager: Arc<OrderManager>,
         cypher_account_receiver: Receiver<Box<CypherUser>>,
         cypher_group_receiver: Receiver<Box<CypherGroup>>,
+        ob_receiver: Receiver<Arc<OrderBookUpdate>>,
+        oo_receiver: Receiver<OpenOrders>,
+        control_receiver: mpsc::Receiver<ControlRequest>,
         shutdown: Arc<Sender<bool>>,
-        signer: Arc<Keypair>,
         cypher_user_pubkey: Pubkey,
         open_orders_pubkey: Pubkey,
     ) -> Self {
+        let metrics = WorkerMetrics::new(&config.symbol);
+        metrics.register();
+
         Self {
             config,
+            mm_config: RwLock::new(mm_config),
+            requote_config: RwLock::new(requote_config),
             inventory_manager,
             order_manager,
             cypher_account_receiver: Mutex::new(cypher_account_receiver),
             cypher_group_receiver: Mutex::new(cypher_group_receiver),
+            ob_receiver: Mutex::new(ob_receiver),
+            oo_receiver: Mutex::new(oo_receiver),
+            control_receiver: Mutex::new(control_receiver),
             shutdown,
-            signer,
             cypher_user_pubkey,
             open_orders_pubkey,
+            metrics,
             ..Worker::default()
         }
     }
@@ -108,37 +284,27 @@ impl Worker {
             }
         });
 
-        let mut shutdown = aself.shutdown.subscribe();
-
-        tokio::select! {
-            res = aself.process() => {
-                match res {
-                    Ok(_) => (),
-                    Err(e) => {
-                        warn!(
-                            "[WORKER-{}] An error occurred while running the worker: {:?}",
-                            aself.config.symbol, e
-                        );
-                    }
-                }
-            },
-            _ = shutdown.recv() => {
-                info!(
-                    "[WORKER-{}] Received shutdown signal, stopping.",
-                    aself.config.symbol
+        // the quoting loop only returns between requotes, so no order is sent after this
+        match aself.process().await {
+            Ok(_) => (),
+            Err(e) => {
+                warn!(
+                    "[WORKER-{}] An error occurred while running the worker: {}",
+                    aself.config.symbol, e
                 );
-                match aself.shutdown().await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        warn!(
-                            "[WORKER-{}] An error occurred while terminating the worker: {:?}",
-                            aself.config.symbol, e
-                        );
-                    }
-                };
             }
         }
 
+        match aself.shutdown().await {
+            Ok(_) => (),
+            Err(e) => {
+                warn!(
+                    "[WORKER-{}] An error occurred while terminating the worker: {}",
+                    aself.config.symbol, e
+                );
+            }
+        };
+
         let (cp_res,) = tokio::join!(cp_update_t);
         match cp_res {
             Ok(_) => (),
@@ -152,91 +318,231 @@ impl Worker {
     }
 
     async fn process(self: &Arc<Self>) -> Result<(), MarketMakerError> {
+        let mut last_requote: Option<Instant> = None;
+        let mut consecutive_errors: u32 = 0;
+        let mut reconciled = false;
+        let mut control_receiver = self.control_receiver.lock().await;
+        let mut shutdown = self.shutdown.subscribe();
+
         loop {
-            let maybe_user = self.cypher_user.read().await;
-            if maybe_user.is_none() {
-                continue;
+            // read on every iteration as the config can be reloaded while running
+            let (heartbeat_interval, min_requote_interval) = {
+                let requote_config = self.requote_config.read().await;
+                (
+                    Duration::from_millis(requote_config.heartbeat_interval_ms),
+                    Duration::from_millis(requote_config.min_requote_interval_ms),
+                )
             };
-            let cypher_user = maybe_user.unwrap();
 
-            let maybe_group = self.cypher_group.read().await;
-            if maybe_group.is_none() {
-                continue;
+            // wait until a provider update is relevant enough to requote or the heartbeat elapses,
+            // operator commands are handled in between requotes so they never race with one
+            let notified = tokio::select! {
+                notified = tokio::time::timeout(heartbeat_interval, self.requote_notify.notified()) => notified,
+                Some(request) = control_receiver.recv() => {
+                    let res = self.process_control_command(request.command).await;
+                    if request.reply.send(res).is_err() {
+                        warn!("[WORKER-{}] Failed to reply to control request.", self.config.symbol);
+                    }
+                    continue;
+                }
+                _ = shutdown.recv() => {
+                    info!(
+                        "[WORKER-{}] Received shutdown signal, stopping the quoting loop.",
+                        self.config.symbol
+                    );
+                    return Ok(());
+                }
             };
-            let cypher_group = maybe_group.unwrap();
-            let cypher_token = cypher_group
-                .get_cypher_token(self.config.market_index)
-                .unwrap();
-
-            let quote_vols = self
-                .inventory_manager
-                .get_quote_volumes(&cypher_user, &cypher_group)
-                .await;
+            self.write_heartbeat().await;
+            if notified.is_err() {
+                self.triggers
+                    .lock()
+                    .await
+                    .insert(RequoteTriggers::HEARTBEAT);
+            }
 
-            info!(
-                "[WORKER-{}] Current delta: {} | Desired Bid Size: {} | Desired Ask Size: {}.",
-                self.config.symbol, quote_vols.delta, quote_vols.bid_size, quote_vols.ask_size
-            );
+            let oracle_published_at = *self.oracle_published_at.read().await;
+            if oracle_published_at != 0 {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                self.metrics
+                    .oracle_age
+                    .set(now.saturating_sub(oracle_published_at) as f64);
+            }
 
-            let latest_price = *self.latest_price.read().await;
-            let (best_bid, best_ask) = self.inventory_manager.get_spread(latest_price);
-            if best_ask == 0 || best_bid == 0 {
-                continue;
+            if let Some(last) = last_requote {
+                let elapsed = last.elapsed();
+                if elapsed < min_requote_interval {
+                    tokio::time::sleep(min_requote_interval - elapsed).await;
+                }
+            }
+
+            let maybe_user = *self.cypher_user.read().await;
+            let cypher_user = match maybe_user {
+                Some(u) => u,
+                None => continue,
+            };
+
+            let maybe_group = *self.cypher_group.read().await;
+            let cypher_group = match maybe_group {
+                Some(g) => g,
+                None => continue,
             };
 
+            let triggers = std::mem::take(&mut *self.triggers.lock().await);
+
+            if *self.paused.read().await {
+                continue;
+            }
+
             info!(
-                "[WORKER-{}] Desired spread: Bid: {} | Ask: {}.",
-                self.config.symbol, best_bid, best_ask
+                "[WORKER-{}] Requoting due to: {:?}.",
+                self.config.symbol, triggers
             );
 
-            let cypher_market = *self.cypher_market.read().await;
-
-            info!("[WORKER-{}] Updating orders.", self.config.symbol);
+            let res = if reconciled {
+                self.requote(&cypher_user, &cypher_group, triggers).await
+            } else {
+                // the orders left by a previous run are dealt with before quoting
+                let res = self.reconcile(&cypher_group).await;
+                if res.is_ok() {
+                    reconciled = true;
+                    self.trigger_requote(RequoteTriggers::STARTUP).await;
+                }
+                res
+            };
+            last_requote = Some(Instant::now());
 
-            match self
-                .order_manager
-                .update_orders(
-                    &cypher_group,
-                    &cypher_market,
-                    cypher_token,
-                    &quote_vols,
-                    best_bid,
-                    best_ask,
-                )
-                .await
-            {
-                Ok(_) => (),
+            match res {
+                Ok(_) => consecutive_errors = 0,
                 Err(e) => {
+                    let class = e.class();
+                    self.metrics
+                        .errors
+                        .with_label_values(&[class.as_str()])
+                        .inc();
                     warn!(
-                        "[WORKER-{}] An error occurred while updating orders: {:?}",
-                        self.config.symbol, e
+                        "[WORKER-{}] A {} error occurred while updating orders: {}",
+                        self.config.symbol,
+                        class.as_str(),
+                        e
                     );
+                    match class {
+                        // the next requote is built from fresh state anyway
+                        ErrorClass::Retryable => {
+                            // the order manager has already moved the quotes away from the book
+                            if matches!(
+                                &e,
+                                MarketMakerError::Order(o)
+                                    if o.rejection() == Some(OrderRejection::PostOnlyWouldCross)
+                            ) {
+                                self.trigger_requote(RequoteTriggers::REJECTION).await;
+                            }
+                        }
+                        ErrorClass::Transient => {
+                            consecutive_errors += 1;
+                            match self.back_off(consecutive_errors).await {
+                                Some(backoff) => tokio::select! {
+                                    _ = tokio::time::sleep(backoff) => (),
+                                    _ = shutdown.recv() => return Ok(()),
+                                },
+                                None => consecutive_errors = 0,
+                            }
+                        }
+                        ErrorClass::Fatal => {
+                            warn!(
+                                "[WORKER-{}] Cannot recover from the error, shutting down.",
+                                self.config.symbol
+                            );
+                            *self.paused.write().await = true;
+                            // the loop stops on the shutdown signal and the orders are then cancelled
+                            if self.shutdown.send(true).is_err() {
+                                warn!(
+                                    "[WORKER-{}] Failed to send the shutdown signal.",
+                                    self.config.symbol
+                                );
+                                return Err(e);
+                            }
+                        }
+                    }
                 }
             }
+        }
+    }
 
-            tokio::time::sleep(Duration::from_millis(750)).await;
+    /// lets the `watchdog` command know the quoting loop is alive
+    async fn write_heartbeat(self: &Arc<Self>) {
+        let mm_config = self.mm_config.read().await;
+        if let Some(watchdog_config) = &mm_config.watchdog_config {
+            let path = Path::new(&watchdog_config.heartbeat_file);
+            if let Err(e) = watchdog::write_heartbeat(path) {
+                warn!(
+                    "[WORKER-{}] Failed to write the heartbeat to {}: {}",
+                    self.config.symbol, watchdog_config.heartbeat_file, e
+                );
+            }
         }
     }
 
-    async fn shutdown(self: &Arc<Self>) -> Result<(), MarketMakerError> {
-        let maybe_user = self.cypher_user.read().await;
-        if maybe_user.is_none() {
-            return Ok(());
+    /// the exponentially growing interval to wait after consecutive transient errors, or `None`
+    /// when there have been too many and quoting was paused and the resting orders cancelled
+    async fn back_off(self: &Arc<Self>, consecutive_errors: u32) -> Option<Duration> {
+        let (error_backoff, max_error_backoff, max_consecutive_errors) = {
+            let requote_config = self.requote_config.read().await;
+            (
+                Duration::from_millis(requote_config.error_backoff_ms),
+                Duration::from_millis(requote_config.max_error_backoff_ms),
+                requote_config.max_consecutive_errors,
+            )
         };
-        let cypher_user = maybe_user.unwrap();
 
-        let maybe_group = self.cypher_group.read().await;
-        if maybe_group.is_none() {
-            return Ok(());
-        };
-        let cypher_group = maybe_group.unwrap();
+        if max_consecutive_errors != 0 && consecutive_errors >= max_consecutive_errors {
+            warn!(
+                "[WORKER-{}] {} consecutive errors, pausing quoting until it is resumed.",
+                self.config.symbol, consecutive_errors
+            );
+            self.metrics.breaker_trips.inc();
+            *self.paused.write().await = true;
+            match self.cancel_all_orders().await {
+                Ok(cancelled) => info!(
+                    "[WORKER-{}] Cancelled {} orders.",
+                    self.config.symbol, cancelled
+                ),
+                Err(e) => warn!(
+                    "[WORKER-{}] Failed to cancel orders after pausing: {}",
+                    self.config.symbol, e
+                ),
+            }
+            return None;
+        }
+
+        let backoff = error_backoff
+            .saturating_mul(2_u32.saturating_pow(consecutive_errors - 1))
+            .min(max_error_backoff);
+        info!(
+            "[WORKER-{}] Backing off for {} ms after {} consecutive errors.",
+            self.config.symbol,
+            backoff.as_millis(),
+            consecutive_errors
+        );
+        Some(backoff)
+    }
+
+    async fn requote(
+        self: &Arc<Self>,
+        cypher_user: &CypherUser,
+        cypher_group: &CypherGroup,
+        triggers: RequoteTriggers,
+    ) -> Result<(), MarketMakerError> {
         let cypher_token = cypher_group
             .get_cypher_token(self.config.market_index)
             .unwrap();
 
         let quote_vols = self
             .inventory_manager
-            .get_quote_volumes(&cypher_user, &cypher_group)
+            .get_quote_volumes(cypher_user, cypher_group)
             .await;
 
         info!(
@@ -259,26 +565,275 @@ impl Worker {
 
         info!("[WORKER-{}] Updating orders.", self.config.symbol);
 
-        match self
-            .order_manager
-            .cancel_orders_remain_neutral(
-                &cypher_group,
+        self.order_manager
+            .update_orders(
+                cypher_group,
                 &cypher_market,
                 cypher_token,
-                &self.signer,
                 &quote_vols,
                 best_bid,
                 best_ask,
+                &format!("requote on {:?}", triggers),
             )
+            .await?;
+
+        *self.last_quoted_price.write().await = latest_price;
+        *self.last_quotes.write().await = Some(QuoteStatus {
+            bid_price: best_bid,
+            bid_size: quote_vols.bid_size as i64,
+            ask_price: best_ask,
+            ask_size: quote_vols.ask_size as i64,
+        });
+
+        Ok(())
+    }
+
+    async fn process_control_command(
+        self: &Arc<Self>,
+        command: ControlCommand,
+    ) -> Result<Value, String> {
+        info!(
+            "[WORKER-{}] Processing control command: {:?}.",
+            self.config.symbol, command
+        );
+
+        match command {
+            ControlCommand::Status => self.get_status().await,
+            ControlCommand::Config => {
+                let mm_config = Arc::clone(&*self.mm_config.read().await);
+                serde_json::to_value(mm_config.as_ref()).map_err(|e| e.to_string())
+            }
+            ControlCommand::Pause => {
+                *self.paused.write().await = true;
+                let cancelled = self.cancel_all_orders().await?;
+                Ok(json!({ "paused": true, "cancelledOrders": cancelled }))
+            }
+            ControlCommand::Resume => {
+                *self.paused.write().await = false;
+                self.trigger_requote(RequoteTriggers::CONTROL).await;
+                Ok(json!({ "paused": false }))
+            }
+            ControlCommand::CancelAll => {
+                let cancelled = self.cancel_all_orders().await?;
+                Ok(json!({ "cancelledOrders": cancelled }))
+            }
+            ControlCommand::Flatten => {
+                // stop quoting first, otherwise the next requote builds the position back up
+                *self.paused.write().await = true;
+                let cancelled = self.cancel_all_orders().await?;
+
+                let (cypher_user, cypher_group) = self.get_accounts().await?;
+                let cypher_token = cypher_group
+                    .get_cypher_token(self.config.market_index)
+                    .unwrap();
+                let cypher_market = *self.cypher_market.read().await;
+                let native_position = self.inventory_manager.get_native_position(&cypher_user);
+
+                self.order_manager
+                    .flatten(&cypher_group, &cypher_market, cypher_token, native_position)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(json!({
+                    "paused": true,
+                    "cancelledOrders": cancelled,
+                    "flattenedPosition": native_position.to_string(),
+                }))
+            }
+            ControlCommand::UpdateParams(update) => {
+                let params = update.apply(&self.inventory_manager.params())?;
+                self.inventory_manager.set_params(params);
+                self.trigger_requote(RequoteTriggers::CONTROL).await;
+                serde_json::to_value(params).map_err(|e| e.to_string())
+            }
+            ControlCommand::ReloadConfig(mm_config) => {
+                let reloaded = self.reload_config(mm_config).await;
+                if !reloaded.is_empty() {
+                    self.trigger_requote(RequoteTriggers::CONTROL).await;
+                }
+                Ok(json!({ "reloaded": true, "sections": reloaded }))
+            }
+        }
+    }
+
+    /// applies the sections of a reloaded config which changed, returns their names
+    ///
+    /// the quoting parameters changed through the control api are kept unless the inventory
+    /// manager section of the file changed too, in which case they are replaced by the file's
+    async fn reload_config(
+        self: &Arc<Self>,
+        mm_config: Arc<MarketMakerConfig>,
+    ) -> Vec<&'static str> {
+        let old_config = Arc::clone(&*self.mm_config.read().await);
+        let mut reloaded: Vec<&'static str> = Vec::new();
+
+        if mm_config.inventory_manager_config != old_config.inventory_manager_config {
+            let params = self.inventory_manager.params();
+            if params != InventoryParams::from(&old_config.inventory_manager_config) {
+                warn!(
+                    "[WORKER-{}] Discarding the parameters set through the control api, {:?}, for those of the reloaded config.",
+                    self.config.symbol, params
+                );
+            }
+            self.inventory_manager
+                .set_params(InventoryParams::from(&mm_config.inventory_manager_config));
+            reloaded.push("inventoryManagerConfig");
+        }
+        if mm_config.order_manager_config != old_config.order_manager_config {
+            self.order_manager
+                .set_config(mm_config.order_manager_config.clone())
+                .await;
+            reloaded.push("orderManagerConfig");
+        }
+        if mm_config.requote_config != old_config.requote_config {
+            *self.requote_config.write().await = mm_config.requote_config.clone();
+            reloaded.push("requoteConfig");
+        }
+        info!(
+            "[WORKER-{}] Reloaded the config, changed sections: {:?}",
+            self.config.symbol, reloaded
+        );
+
+        self.inventory_manager.set_config(Arc::clone(&mm_config));
+        *self.mm_config.write().await = mm_config;
+        reloaded
+    }
+
+    async fn get_status(self: &Arc<Self>) -> Result<Value, String> {
+        let native_position = match *self.cypher_user.read().await {
+            Some(u) => Some(self.inventory_manager.get_native_position(&u)),
+            None => None,
+        };
+
+        let resting_orders: Vec<Value> = self
+            .order_manager
+            .get_orders()
             .await
-        {
-            Ok(_) => (),
-            Err(e) => {
+            .iter()
+            .map(|o| {
+                json!({
+                    "orderId": o.order_id.to_string(),
+                    "clientOrderId": o.client_order_id,
+                    "side": match o.side {
+                        Side::Bid => "bid",
+                        Side::Ask => "ask",
+                    },
+                    "price": o.price,
+                    "quantity": o.quantity,
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "market": self.config.symbol,
+            "paused": *self.paused.read().await,
+            "nativePosition": native_position.map(|p| p.to_string()),
+            "oraclePrice": *self.latest_price.read().await,
+            "quotes": *self.last_quotes.read().await,
+            "restingOrders": resting_orders,
+            "params": self.inventory_manager.params(),
+        }))
+    }
+
+    async fn get_accounts(self: &Arc<Self>) -> Result<(CypherUser, CypherGroup), String> {
+        let cypher_user = match *self.cypher_user.read().await {
+            Some(u) => u,
+            None => return Err("the cypher account has not been loaded yet".to_string()),
+        };
+        let cypher_group = match *self.cypher_group.read().await {
+            Some(g) => g,
+            None => return Err("the cypher group has not been loaded yet".to_string()),
+        };
+        Ok((cypher_user, cypher_group))
+    }
+
+    async fn cancel_all_orders(self: &Arc<Self>) -> Result<usize, String> {
+        let (_, cypher_group) = self.get_accounts().await?;
+        let cypher_token = cypher_group
+            .get_cypher_token(self.config.market_index)
+            .unwrap();
+        let cypher_market = *self.cypher_market.read().await;
+
+        self.order_manager
+            .cancel_all_orders(&cypher_group, &cypher_market, cypher_token)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn reconcile(
+        self: &Arc<Self>,
+        cypher_group: &CypherGroup,
+    ) -> Result<(), MarketMakerError> {
+        let cypher_token = cypher_group
+            .get_cypher_token(self.config.market_index)
+            .unwrap();
+        let cypher_market = *self.cypher_market.read().await;
+        let shutdown_config = self.mm_config.read().await.shutdown_config.clone();
+
+        self.order_manager
+            .reconcile(
+                cypher_group,
+                &cypher_market,
+                cypher_token,
+                Duration::from_secs(shutdown_config.cancel_timeout_secs),
+                Duration::from_millis(shutdown_config.cancel_retry_interval_ms),
+            )
+            .await
+    }
+
+    /// marks the given triggers as pending and wakes up the quoting loop
+    async fn trigger_requote(self: &Arc<Self>, triggers: RequoteTriggers) {
+        self.triggers.lock().await.insert(triggers);
+        self.requote_notify.notify_one();
+    }
+
+    /// cancels every order until the open orders account is confirmed to be empty, then settles
+    /// the funds if configured to
+    async fn shutdown(self: &Arc<Self>) -> Result<(), MarketMakerError> {
+        *self.paused.write().await = true;
+
+        let cypher_group = match *self.cypher_group.read().await {
+            Some(g) => g,
+            None => {
                 warn!(
-                    "[WORKER-{}] An error occurred while updating orders: {:?}",
-                    self.config.symbol, e
+                    "[WORKER-{}] The cypher group was never loaded, cannot cancel orders.",
+                    self.config.symbol
                 );
+                return Err(MarketMakerError::ShutdownError);
             }
+        };
+        let cypher_token = cypher_group
+            .get_cypher_token(self.config.market_index)
+            .unwrap();
+        let cypher_market = *self.cypher_market.read().await;
+        let shutdown_config = self.mm_config.read().await.shutdown_config.clone();
+
+        info!(
+            "[WORKER-{}] Cancelling all orders before shutting down.",
+            self.config.symbol
+        );
+        let clean = self
+            .order_manager
+            .cancel_all_until_clean(
+                &cypher_group,
+                &cypher_market,
+                cypher_token,
+                Duration::from_secs(shutdown_config.cancel_timeout_secs),
+                Duration::from_millis(shutdown_config.cancel_retry_interval_ms),
+                "shutdown",
+            )
+            .await;
+        if !clean {
+            warn!(
+                "[WORKER-{}] Could not confirm all orders were cancelled within {} seconds, check open orders account {}.",
+                self.config.symbol, shutdown_config.cancel_timeout_secs, self.open_orders_pubkey
+            );
+            return Err(MarketMakerError::ShutdownError);
+        }
+
+        if shutdown_config.settle_funds {
+            self.order_manager
+                .settle_funds(&cypher_group, &cypher_market, cypher_token)
+                .await?;
         }
 
         Ok(())
@@ -287,6 +842,8 @@ impl Worker {
     async fn process_provider_updates(self: &Arc<Self>) -> Result<(), MarketMakerError> {
         let mut group_receiver = self.cypher_group_receiver.lock().await;
         let mut account_receiver = self.cypher_account_receiver.lock().await;
+        let mut ob_receiver = self.ob_receiver.lock().await;
+        let mut oo_receiver = self.oo_receiver.lock().await;
         let mut shutdown = self.shutdown.subscribe();
         let mut shutdown_signal: bool = false;
 
@@ -297,19 +854,7 @@ impl Worker {
                         warn!("[WORKER-{}] There was an error while processing cypher group updates, restarting loop.", self.config.symbol);
                         continue;
                     } else {
-                        let cg = group.unwrap();
-                        *self.cypher_group.write().await = Some(*cg);
-
-                        let market_idx = cg.get_market_idx(self.config.c_asset_mint).unwrap();
-                        let market = cg.get_cypher_market(market_idx).unwrap();
-
-                        info!(
-                            "[WORKER-{}] Market update: Oracle price: {} - TWAP: {}",
-                            self.config.symbol, &market.oracle_price.price, &market.market_price
-                        );
-
-                        *self.cypher_market.write().await = *market;
-                        *self.latest_price.write().await = market.oracle_price.price;
+                        self.process_group_update(group.unwrap()).await;
                     }
                 }
                 account = account_receiver.recv() => {
@@ -317,7 +862,23 @@ impl Worker {
                         warn!("[WORKER-{}] There was an error while processing cypher account updates, restarting loop.", self.config.symbol);
                         continue;
                     } else {
-                        *self.cypher_user.write().await = Some(*account.unwrap());
+                        self.process_account_update(account.unwrap()).await;
+                    }
+                }
+                ob = ob_receiver.recv() => {
+                    if ob.is_err() {
+                        warn!("[WORKER-{}] There was an error while processing order book updates, restarting loop.", self.config.symbol);
+                        continue;
+                    } else {
+                        self.process_ob_update(ob.unwrap()).await;
+                    }
+                }
+                oo = oo_receiver.recv() => {
+                    if oo.is_err() {
+                        warn!("[WORKER-{}] There was an error while processing open orders updates, restarting loop.", self.config.symbol);
+                        continue;
+                    } else {
+                        self.process_oo_update(oo.unwrap()).await;
                     }
                 }
                 _ = shutdown.recv() => {
@@ -336,4 +897,189 @@ impl Worker {
 
         Ok(())
     }
+
+    async fn process_group_update(self: &Arc<Self>, cg: Box<CypherGroup>) {
+        *self.cypher_group.write().await = Some(*cg);
+
+        let market_idx = cg.get_market_idx(self.config.c_asset_mint).unwrap();
+        let market = cg.get_cypher_market(market_idx).unwrap();
+
+        info!(
+            "[WORKER-{}] Market update: Oracle price: {} - TWAP: {}",
+            self.config.symbol, &market.oracle_price.price, &market.market_price
+        );
+
+        let oracle_price = market.oracle_price.price;
+        *self.cypher_market.write().await = *market;
+        *self.latest_price.write().await = oracle_price;
+        *self.oracle_published_at.write().await = market.oracle_price.last_updated;
+        self.metrics.oracle_price.set(oracle_price as i64);
+
+        let last_quoted_price = *self.last_quoted_price.read().await;
+        if last_quoted_price == 0 {
+            self.trigger_requote(RequoteTriggers::ORACLE).await;
+            return;
+        }
+
+        let price_move = if oracle_price > last_quoted_price {
+            oracle_price - last_quoted_price
+        } else {
+            last_quoted_price - oracle_price
+        };
+        let price_move_bps = price_move as u128 * 10_000 / last_quoted_price as u128;
+
+        let oracle_threshold_bps = self.requote_config.read().await.oracle_threshold_bps;
+        if price_move_bps >= oracle_threshold_bps as u128 {
+            info!(
+                "[WORKER-{}] Oracle moved {} bps since last quote.",
+                self.config.symbol, price_move_bps
+            );
+            self.trigger_requote(RequoteTriggers::ORACLE).await;
+        }
+    }
+
+    async fn process_account_update(self: &Arc<Self>, cu: Box<CypherUser>) {
+        *self.cypher_user.write().await = Some(*cu);
+
+        let position = cu
+            .get_position(self.config.market_index)
+            .map(|p| (p.base_deposits().as_u64(0), p.base_borrows().as_u64(0)));
+
+        let mut last_position = self.position.write().await;
+        if *last_position != position {
+            *last_position = position;
+            drop(last_position);
+            self.trigger_requote(RequoteTriggers::POSITION).await;
+        }
+    }
+
+    async fn process_ob_update(self: &Arc<Self>, update: Arc<OrderBookUpdate>) {
+        if update.diff.top_changed {
+            self.trigger_requote(RequoteTriggers::BOOK).await;
+        }
+    }
+
+    async fn process_oo_update(self: &Arc<Self>, oo: OpenOrders) {
+        let cancelling_orders = self.order_manager.get_cancelling_orders().await;
+        let mut last_open_orders = self.open_orders.write().await;
+
+        let filled = match *last_open_orders {
+            Some(last) => is_fill(&last, &oo, &cancelling_orders),
+            None => false,
+        };
+        *last_open_orders = Some(oo);
+        drop(last_open_orders);
+        // the cancelled orders whose slots were freed have been told apart from fills by now
+        self.order_manager.forget_cancelled_orders(&oo).await;
+
+        if filled {
+            self.trigger_requote(RequoteTriggers::FILL).await;
+        }
+    }
+}
+
+/// whether one of our orders was hit between two states of the open orders account, our own
+/// cancels free up slots and funds as well so the slots of orders being cancelled are ignored
+fn is_fill(last: &OpenOrders, oo: &OpenOrders, cancelling_orders: &[u64]) -> bool {
+    // the account is packed, so its arrays are copied out before they are borrowed
+    let orders = oo.orders;
+    let last_orders = last.orders;
+    let last_client_order_ids = last.client_order_ids;
+
+    let mut slots_changed = false;
+    for i in 0..last_orders.len() {
+        if orders[i] == last_orders[i] {
+            continue;
+        }
+        slots_changed = true;
+        // an order left the slot, either on its own or replaced by a new one
+        if last_orders[i] != u128::default()
+            && !cancelling_orders.contains(&last_client_order_ids[i])
+        {
+            return true;
+        }
+    }
+
+    // with the same orders resting, freed up funds can only come from a partial fill
+    !slots_changed
+        && (oo.native_coin_free != last.native_coin_free
+            || oo.native_pc_free != last.native_pc_free)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, bytemuck::Zeroable};
+
+    /// an open orders account with the given orders and client order ids in its first slots
+    fn open_orders(slots: &[(u128, u64)], coin_free: u64, pc_free: u64) -> OpenOrders {
+        let mut oo = OpenOrders::zeroed();
+        let mut orders = oo.orders;
+        let mut client_order_ids = oo.client_order_ids;
+        for (i, (order_id, client_order_id)) in slots.iter().enumerate() {
+            orders[i] = *order_id;
+            client_order_ids[i] = *client_order_id;
+        }
+        oo.orders = orders;
+        oo.client_order_ids = client_order_ids;
+        oo.native_coin_free = coin_free;
+        oo.native_pc_free = pc_free;
+        oo
+    }
+
+    #[test]
+    fn nothing_changed_is_not_a_fill() {
+        let last = open_orders(&[(10, 1), (20, 2)], 0, 0);
+        let oo = open_orders(&[(10, 1), (20, 2)], 0, 0);
+
+        assert!(!is_fill(&last, &oo, &[]));
+    }
+
+    #[test]
+    fn own_cancel_is_not_a_fill() {
+        let last = open_orders(&[(10, 1), (20, 2)], 0, 0);
+        // the cancel frees the slot and the funds locked by the order
+        let oo = open_orders(&[(0, 0), (20, 2)], 0, 500);
+
+        assert!(!is_fill(&last, &oo, &[1]));
+    }
+
+    #[test]
+    fn cancelled_slot_replaced_by_a_new_order_is_not_a_fill() {
+        let last = open_orders(&[(10, 1)], 0, 0);
+        let oo = open_orders(&[(30, 3)], 0, 0);
+
+        assert!(!is_fill(&last, &oo, &[1]));
+    }
+
+    #[test]
+    fn new_order_in_a_free_slot_is_not_a_fill() {
+        let last = open_orders(&[(10, 1)], 0, 500);
+        let oo = open_orders(&[(10, 1), (30, 3)], 0, 0);
+
+        assert!(!is_fill(&last, &oo, &[]));
+    }
+
+    #[test]
+    fn partial_fill_frees_funds_without_changing_slots() {
+        let last = open_orders(&[(10, 1), (20, 2)], 0, 0);
+        let oo = open_orders(&[(10, 1), (20, 2)], 100, 0);
+
+        assert!(is_fill(&last, &oo, &[]));
+    }
+
+    #[test]
+    fn full_fill_frees_a_slot_which_is_not_being_cancelled() {
+        let last = open_orders(&[(10, 1), (20, 2)], 0, 0);
+        let oo = open_orders(&[(10, 1), (0, 0)], 100, 0);
+
+        assert!(is_fill(&last, &oo, &[1]));
+    }
+
+    #[test]
+    fn filled_slot_reused_by_a_new_order_is_a_fill() {
+        let last = open_orders(&[(10, 1)], 0, 0);
+        let oo = open_orders(&[(30, 3)], 100, 0);
+
+        assert!(is_fill(&last, &oo, &[]));
+    }
 }
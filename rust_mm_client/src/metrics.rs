@@ -0,0 +1,57 @@
+use {
+    log::warn,
+    prometheus::{
+        core::Collector, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+        IntGaugeVec, Opts,
+    },
+};
+
+// helpers for components to build their metrics, every metric created here is labeled with the
+// market the component operates on, components which are shared between markets pass an empty market
+
+fn opts(name: &str, help: &str, market: &str) -> Opts {
+    let opts = Opts::new(name, help);
+    if market.is_empty() {
+        opts
+    } else {
+        opts.const_label("market", market)
+    }
+}
+
+pub fn int_gauge(name: &str, help: &str, market: &str) -> IntGauge {
+    IntGauge::with_opts(opts(name, help, market)).unwrap()
+}
+
+pub fn gauge(name: &str, help: &str, market: &str) -> Gauge {
+    Gauge::with_opts(opts(name, help, market)).unwrap()
+}
+
+pub fn int_counter(name: &str, help: &str, market: &str) -> IntCounter {
+    IntCounter::with_opts(opts(name, help, market)).unwrap()
+}
+
+pub fn int_gauge_vec(name: &str, help: &str, market: &str, labels: &[&str]) -> IntGaugeVec {
+    IntGaugeVec::new(opts(name, help, market), labels).unwrap()
+}
+
+pub fn int_counter_vec(name: &str, help: &str, market: &str, labels: &[&str]) -> IntCounterVec {
+    IntCounterVec::new(opts(name, help, market), labels).unwrap()
+}
+
+pub fn histogram(name: &str, help: &str, market: &str, buckets: Vec<f64>) -> Histogram {
+    let mut opts = HistogramOpts::new(name, help).buckets(buckets);
+    if !market.is_empty() {
+        opts = opts.const_label("market", market);
+    }
+    Histogram::with_opts(opts).unwrap()
+}
+
+/// registers the collector with the default registry which is served by the metrics service
+pub fn register<C: Collector + Clone + 'static>(collector: &C) {
+    match prometheus::register(Box::new(collector.clone())) {
+        Ok(_) => (),
+        Err(e) => {
+            warn!("[METRICS] Failed to register collector: {}", e.to_string());
+        }
+    }
+}
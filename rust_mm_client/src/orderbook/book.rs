@@ -0,0 +1,382 @@
+use {
+    crate::serum_slab::OrderBookOrder,
+    serum_dex::matching::Side,
+    solana_sdk::pubkey::Pubkey,
+};
+
+const BPS_UNIT: u128 = 10_000;
+
+/// An aggregated price level of one side of the order book.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price: u64,
+    /// total quantity resting at this price
+    pub quantity: u64,
+    /// total quantity resting at this price and every better price
+    pub cumulative_quantity: u64,
+    /// number of orders resting at this price
+    pub orders: usize,
+    /// quantity of our own orders resting at this price
+    pub own_quantity: u64,
+    /// number of our own orders resting at this price
+    pub own_orders: usize,
+}
+
+impl PriceLevel {
+    pub fn contains_own_orders(&self) -> bool {
+        self.own_orders != 0
+    }
+}
+
+/// An immutable snapshot of a market's order book.
+///
+/// Both sides are ordered by priority, i.e. best price first and, within a price, in queue order.
+#[derive(Debug, Default, Clone)]
+pub struct OrderBook {
+    pub market: Pubkey,
+    /// the open orders account which owns our orders, used to identify them in the book
+    pub open_orders: Pubkey,
+    pub bids: Vec<OrderBookOrder>,
+    pub asks: Vec<OrderBookOrder>,
+}
+
+impl OrderBook {
+    pub fn new(market: Pubkey, open_orders: Pubkey) -> Self {
+        Self {
+            market,
+            open_orders,
+            bids: Vec::new(),
+            asks: Vec::new(),
+        }
+    }
+
+    pub fn side(&self, side: Side) -> &[OrderBookOrder] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    pub fn best_bid(&self) -> Option<&OrderBookOrder> {
+        self.bids.first()
+    }
+
+    pub fn best_ask(&self) -> Option<&OrderBookOrder> {
+        self.asks.first()
+    }
+
+    pub fn mid_price(&self) -> Option<u64> {
+        let best_bid = self.best_bid()?;
+        let best_ask = self.best_ask()?;
+        Some(((best_bid.price as u128 + best_ask.price as u128) / 2) as u64)
+    }
+
+    pub fn spread(&self) -> Option<u64> {
+        let best_bid = self.best_bid()?;
+        let best_ask = self.best_ask()?;
+        Some(best_ask.price.saturating_sub(best_bid.price))
+    }
+
+    /// the spread relative to the mid price
+    pub fn spread_bps(&self) -> Option<u64> {
+        let spread = self.spread()?;
+        let mid_price = self.mid_price()?;
+        if mid_price == 0 {
+            return None;
+        }
+        Some((spread as u128 * BPS_UNIT / mid_price as u128) as u64)
+    }
+
+    pub fn is_own_order(&self, order: &OrderBookOrder) -> bool {
+        order.owner() == self.open_orders
+    }
+
+    pub fn find_order(&self, side: Side, order_id: u128) -> Option<&OrderBookOrder> {
+        self.side(side).iter().find(|o| o.order_id == order_id)
+    }
+
+    /// aggregates the orders of the given side into price levels, best price first
+    pub fn levels(&self, side: Side) -> Vec<PriceLevel> {
+        let mut levels: Vec<PriceLevel> = Vec::new();
+        let mut cumulative_quantity: u64 = 0;
+
+        for order in self.side(side) {
+            let is_own = self.is_own_order(order);
+            cumulative_quantity += order.quantity;
+
+            match levels.last_mut() {
+                Some(level) if level.price == order.price => {
+                    level.quantity += order.quantity;
+                    level.cumulative_quantity = cumulative_quantity;
+                    level.orders += 1;
+                    if is_own {
+                        level.own_quantity += order.quantity;
+                        level.own_orders += 1;
+                    }
+                }
+                _ => {
+                    levels.push(PriceLevel {
+                        price: order.price,
+                        quantity: order.quantity,
+                        cumulative_quantity,
+                        orders: 1,
+                        own_quantity: if is_own { order.quantity } else { 0 },
+                        own_orders: if is_own { 1 } else { 0 },
+                    });
+                }
+            }
+        }
+
+        levels
+    }
+
+    /// the price levels of the given side which contain at least one of our orders
+    pub fn own_levels(&self, side: Side) -> Vec<PriceLevel> {
+        self.levels(side)
+            .into_iter()
+            .filter(|l| l.contains_own_orders())
+            .collect()
+    }
+
+    /// the total quantity resting on the given side at `price` or better
+    pub fn depth_at(&self, side: Side, price: u64) -> u64 {
+        self.side(side)
+            .iter()
+            .take_while(|o| match side {
+                Side::Bid => o.price >= price,
+                Side::Ask => o.price <= price,
+            })
+            .map(|o| o.quantity)
+            .sum()
+    }
+
+    /// the volume weighted average price of taking `size` from the given side of the book,
+    /// i.e. buying `size` consumes `Side::Ask` and selling `size` consumes `Side::Bid`
+    ///
+    /// returns `None` if the side does not have enough depth to fill `size`
+    pub fn vwap(&self, side: Side, size: u64) -> Option<u64> {
+        if size == 0 {
+            return None;
+        }
+
+        let mut remaining = size;
+        let mut notional: u128 = 0;
+
+        for order in self.side(side) {
+            let fill = std::cmp::min(remaining, order.quantity);
+            notional += fill as u128 * order.price as u128;
+            remaining -= fill;
+
+            if remaining == 0 {
+                return Some((notional / size as u128) as u64);
+            }
+        }
+
+        None
+    }
+
+    /// the worst price reached when taking `size` from the given side of the book
+    ///
+    /// returns `None` if the side does not have enough depth to fill `size`
+    pub fn price_to_fill(&self, side: Side, size: u64) -> Option<u64> {
+        self.order_to_fill(side, size).map(|o| o.price)
+    }
+
+    /// the last order taken when taking `size` from the given side of the book
+    ///
+    /// returns `None` if the side does not have enough depth to fill `size`
+    pub fn order_to_fill(&self, side: Side, size: u64) -> Option<&OrderBookOrder> {
+        if size == 0 {
+            return None;
+        }
+
+        let mut remaining = size;
+        for order in self.side(side) {
+            remaining = remaining.saturating_sub(order.quantity);
+            if remaining == 0 {
+                return Some(order);
+            }
+        }
+
+        None
+    }
+
+    /// the difference between the best price of the given side and the vwap of taking `size` from it
+    pub fn price_impact_bps(&self, side: Side, size: u64) -> Option<u64> {
+        let best_price = self.side(side).first()?.price;
+        let vwap = self.vwap(side, size)?;
+        if best_price == 0 {
+            return None;
+        }
+
+        let impact = if vwap > best_price {
+            vwap - best_price
+        } else {
+            best_price - vwap
+        };
+
+        Some((impact as u128 * BPS_UNIT / best_price as u128) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, bytemuck::cast};
+
+    fn order(price: u64, quantity: u64, owner: &Pubkey) -> OrderBookOrder {
+        OrderBookOrder {
+            price,
+            quantity,
+            order_id: (price as u128) << 64,
+            client_order_id: 0,
+            owner: cast(owner.to_bytes()),
+        }
+    }
+
+    fn book(
+        open_orders: &Pubkey,
+        bids: Vec<OrderBookOrder>,
+        asks: Vec<OrderBookOrder>,
+    ) -> OrderBook {
+        OrderBook {
+            bids,
+            asks,
+            ..OrderBook::new(Pubkey::new_unique(), *open_orders)
+        }
+    }
+
+    #[test]
+    fn aggregates_orders_at_one_price_into_a_level() {
+        let ours = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let book = book(
+            &ours,
+            vec![
+                order(100, 5, &other),
+                order(100, 3, &ours),
+                order(100, 2, &other),
+                order(99, 4, &other),
+            ],
+            Vec::new(),
+        );
+
+        let levels = book.levels(Side::Bid);
+        assert_eq!(
+            levels,
+            vec![
+                PriceLevel {
+                    price: 100,
+                    quantity: 10,
+                    cumulative_quantity: 10,
+                    orders: 3,
+                    own_quantity: 3,
+                    own_orders: 1,
+                },
+                PriceLevel {
+                    price: 99,
+                    quantity: 4,
+                    cumulative_quantity: 14,
+                    orders: 1,
+                    own_quantity: 0,
+                    own_orders: 0,
+                },
+            ]
+        );
+        assert_eq!(book.own_levels(Side::Bid), vec![levels[0]]);
+    }
+
+    #[test]
+    fn depth_includes_every_better_price() {
+        let other = Pubkey::new_unique();
+        let book = book(
+            &Pubkey::new_unique(),
+            vec![
+                order(100, 5, &other),
+                order(99, 4, &other),
+                order(98, 1, &other),
+            ],
+            vec![order(101, 2, &other), order(102, 3, &other)],
+        );
+
+        assert_eq!(book.depth_at(Side::Bid, 99), 9);
+        assert_eq!(book.depth_at(Side::Bid, 101), 0);
+        assert_eq!(book.depth_at(Side::Ask, 102), 5);
+        assert_eq!(book.mid_price(), Some(100));
+        assert_eq!(book.spread(), Some(1));
+    }
+
+    #[test]
+    fn vwap_walks_the_levels() {
+        let other = Pubkey::new_unique();
+        let book = book(
+            &Pubkey::new_unique(),
+            Vec::new(),
+            vec![
+                order(100, 5, &other),
+                order(100, 5, &other),
+                order(110, 10, &other),
+            ],
+        );
+
+        assert_eq!(book.vwap(Side::Ask, 10), Some(100));
+        assert_eq!(book.vwap(Side::Ask, 20), Some(105));
+        assert_eq!(book.vwap(Side::Ask, 21), None);
+        assert_eq!(book.vwap(Side::Ask, 0), None);
+    }
+
+    #[test]
+    fn price_impact_is_relative_to_the_best_price() {
+        let other = Pubkey::new_unique();
+        let book = book(
+            &Pubkey::new_unique(),
+            vec![order(100, 10, &other), order(90, 10, &other)],
+            Vec::new(),
+        );
+
+        assert_eq!(book.price_impact_bps(Side::Bid, 10), Some(0));
+        // the vwap of 20 is 95, 5% below the best bid
+        assert_eq!(book.price_impact_bps(Side::Bid, 20), Some(500));
+        assert_eq!(book.price_impact_bps(Side::Bid, 30), None);
+        assert_eq!(book.price_impact_bps(Side::Ask, 1), None);
+    }
+
+    #[test]
+    fn order_to_fill_is_the_last_order_taken() {
+        let other = Pubkey::new_unique();
+        let book = book(
+            &Pubkey::new_unique(),
+            Vec::new(),
+            vec![
+                order(101, 5, &other),
+                order(102, 3, &other),
+                order(103, 4, &other),
+            ],
+        );
+
+        assert_eq!(book.order_to_fill(Side::Ask, 5).map(|o| o.price), Some(101));
+        assert_eq!(book.order_to_fill(Side::Ask, 6).map(|o| o.price), Some(102));
+        assert_eq!(
+            book.order_to_fill(Side::Ask, 12).map(|o| o.order_id),
+            Some(103 << 64)
+        );
+        assert_eq!(book.price_to_fill(Side::Ask, 8), Some(102));
+    }
+
+    #[test]
+    fn order_to_fill_needs_enough_depth() {
+        let other = Pubkey::new_unique();
+        let book = book(
+            &Pubkey::new_unique(),
+            vec![order(100, 5, &other)],
+            Vec::new(),
+        );
+
+        assert!(book.order_to_fill(Side::Bid, 0).is_none());
+        assert!(book.order_to_fill(Side::Bid, 6).is_none());
+        assert!(book.price_to_fill(Side::Ask, 1).is_none());
+    }
+}
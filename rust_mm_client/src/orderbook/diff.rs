@@ -0,0 +1,368 @@
+use {
+    super::OrderBook,
+    crate::serum_slab::OrderBookOrder,
+    serum_dex::matching::Side,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::Arc,
+    },
+};
+
+/// An order whose size changed between two versions of the book.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderChange {
+    pub before: OrderBookOrder,
+    pub after: OrderBookOrder,
+}
+
+impl OrderChange {
+    pub fn is_reduced(&self) -> bool {
+        self.after.quantity < self.before.quantity
+    }
+}
+
+/// A price level whose total size or order count changed between two versions of the book.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LevelChange {
+    pub price: u64,
+    pub quantity_before: u64,
+    pub quantity_after: u64,
+    pub orders_before: usize,
+    pub orders_after: usize,
+}
+
+impl LevelChange {
+    pub fn is_new(&self) -> bool {
+        self.orders_before == 0
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.orders_after == 0
+    }
+}
+
+/// The quantity of one of our orders which left the book without us cancelling it, as far as the
+/// book can tell, i.e. the order was removed or its size was reduced.
+#[derive(Debug, Clone, Copy)]
+pub struct OwnOrderHit {
+    pub order_id: u128,
+    pub client_order_id: u64,
+    pub price: u64,
+    pub quantity: u64,
+    /// whether the order left the book entirely
+    pub removed: bool,
+}
+
+/// The changes to one side of the book between two consecutive versions of its slab.
+///
+/// Orders are matched by order id. If the provider is configured with a limited depth, orders
+/// moving across the depth cut-off show up as added or removed.
+#[derive(Debug, Clone)]
+pub struct OrderBookDiff {
+    pub side: Side,
+    pub added: Vec<OrderBookOrder>,
+    pub removed: Vec<OrderBookOrder>,
+    pub changed: Vec<OrderChange>,
+    pub levels: Vec<LevelChange>,
+    /// whether the best price or the size at the best price changed
+    pub top_changed: bool,
+}
+
+impl OrderBookDiff {
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            levels: Vec::new(),
+            top_changed: false,
+        }
+    }
+
+    pub fn compute(side: Side, before: &[OrderBookOrder], after: &[OrderBookOrder]) -> Self {
+        let mut diff = OrderBookDiff::new(side);
+
+        let before_orders: HashMap<u128, &OrderBookOrder> =
+            before.iter().map(|o| (o.order_id, o)).collect();
+        let after_orders: HashMap<u128, &OrderBookOrder> =
+            after.iter().map(|o| (o.order_id, o)).collect();
+
+        for order in after {
+            match before_orders.get(&order.order_id) {
+                Some(prev) => {
+                    if prev.quantity != order.quantity {
+                        diff.changed.push(OrderChange {
+                            before: **prev,
+                            after: *order,
+                        });
+                    }
+                }
+                None => diff.added.push(*order),
+            }
+        }
+
+        for order in before {
+            if !after_orders.contains_key(&order.order_id) {
+                diff.removed.push(*order);
+            }
+        }
+
+        let before_levels = aggregate_levels(before);
+        let after_levels = aggregate_levels(after);
+
+        let mut prices: Vec<u64> = before_levels
+            .keys()
+            .chain(after_levels.keys())
+            .copied()
+            .collect();
+        prices.sort_unstable();
+        prices.dedup();
+        if side == Side::Bid {
+            prices.reverse();
+        }
+
+        for price in prices {
+            let (quantity_before, orders_before) =
+                before_levels.get(&price).copied().unwrap_or_default();
+            let (quantity_after, orders_after) =
+                after_levels.get(&price).copied().unwrap_or_default();
+
+            if quantity_before != quantity_after || orders_before != orders_after {
+                diff.levels.push(LevelChange {
+                    price,
+                    quantity_before,
+                    quantity_after,
+                    orders_before,
+                    orders_after,
+                });
+            }
+        }
+
+        diff.top_changed = top_of_side(before, &before_levels) != top_of_side(after, &after_levels);
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn added_quantity(&self) -> u64 {
+        self.added.iter().map(|o| o.quantity).sum()
+    }
+
+    pub fn removed_quantity(&self) -> u64 {
+        self.removed.iter().map(|o| o.quantity).sum()
+    }
+
+    /// our orders, owned by the given open orders account, which were removed or reduced
+    ///
+    /// the book alone can't tell a fill from a cancel, callers should discard the orders they cancelled
+    pub fn own_orders_hit(&self, open_orders: &Pubkey) -> Vec<OwnOrderHit> {
+        let mut hits: Vec<OwnOrderHit> = Vec::new();
+
+        for order in self.removed.iter().filter(|o| o.owner() == *open_orders) {
+            hits.push(OwnOrderHit {
+                order_id: order.order_id,
+                client_order_id: order.client_order_id,
+                price: order.price,
+                quantity: order.quantity,
+                removed: true,
+            });
+        }
+
+        for change in self
+            .changed
+            .iter()
+            .filter(|c| c.is_reduced() && c.after.owner() == *open_orders)
+        {
+            hits.push(OwnOrderHit {
+                order_id: change.after.order_id,
+                client_order_id: change.after.client_order_id,
+                price: change.after.price,
+                quantity: change.before.quantity - change.after.quantity,
+                removed: false,
+            });
+        }
+
+        hits
+    }
+}
+
+/// A new version of the book along with the changes which produced it.
+#[derive(Debug, Clone)]
+pub struct OrderBookUpdate {
+    pub book: Arc<OrderBook>,
+    pub diff: OrderBookDiff,
+}
+
+impl OrderBookUpdate {
+    pub fn default() -> Self {
+        Self {
+            book: Arc::new(OrderBook::default()),
+            diff: OrderBookDiff::new(Side::Bid),
+        }
+    }
+}
+
+fn aggregate_levels(orders: &[OrderBookOrder]) -> BTreeMap<u64, (u64, usize)> {
+    let mut levels: BTreeMap<u64, (u64, usize)> = BTreeMap::new();
+
+    for order in orders {
+        let level = levels.entry(order.price).or_default();
+        level.0 += order.quantity;
+        level.1 += 1;
+    }
+
+    levels
+}
+
+fn top_of_side(
+    orders: &[OrderBookOrder],
+    levels: &BTreeMap<u64, (u64, usize)>,
+) -> Option<(u64, u64)> {
+    let best_price = orders.first()?.price;
+    levels.get(&best_price).map(|l| (best_price, l.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, bytemuck::cast};
+
+    fn order(order_id: u128, price: u64, quantity: u64, owner: &Pubkey) -> OrderBookOrder {
+        OrderBookOrder {
+            price,
+            quantity,
+            order_id,
+            client_order_id: order_id as u64,
+            owner: cast(owner.to_bytes()),
+        }
+    }
+
+    #[test]
+    fn identical_sides_have_an_empty_diff() {
+        let other = Pubkey::new_unique();
+        let orders = vec![order(1, 100, 5, &other), order(2, 99, 5, &other)];
+
+        let diff = OrderBookDiff::compute(Side::Bid, &orders, &orders);
+        assert!(diff.is_empty());
+        assert!(diff.levels.is_empty());
+        assert!(!diff.top_changed);
+    }
+
+    #[test]
+    fn inserted_order_below_the_top() {
+        let other = Pubkey::new_unique();
+        let before = vec![order(1, 100, 5, &other)];
+        let after = vec![order(1, 100, 5, &other), order(2, 99, 3, &other)];
+
+        let diff = OrderBookDiff::compute(Side::Bid, &before, &after);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].order_id, 2);
+        assert_eq!(diff.added_quantity(), 3);
+        assert!(diff.removed.is_empty());
+        assert!(diff.changed.is_empty());
+        assert_eq!(
+            diff.levels,
+            vec![LevelChange {
+                price: 99,
+                quantity_before: 0,
+                quantity_after: 3,
+                orders_before: 0,
+                orders_after: 1,
+            }]
+        );
+        assert!(diff.levels[0].is_new());
+        assert!(!diff.top_changed);
+    }
+
+    #[test]
+    fn removed_best_order_changes_the_top() {
+        let other = Pubkey::new_unique();
+        let before = vec![order(1, 100, 5, &other), order(2, 101, 3, &other)];
+        let after = vec![order(2, 101, 3, &other)];
+
+        let diff = OrderBookDiff::compute(Side::Ask, &before, &after);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed_quantity(), 5);
+        assert_eq!(diff.levels.len(), 1);
+        assert!(diff.levels[0].is_removed());
+        assert!(diff.top_changed);
+    }
+
+    #[test]
+    fn size_change_at_the_top_level() {
+        let other = Pubkey::new_unique();
+        let before = vec![order(1, 100, 5, &other), order(2, 100, 5, &other)];
+        let after = vec![order(1, 100, 2, &other), order(2, 100, 5, &other)];
+
+        let diff = OrderBookDiff::compute(Side::Bid, &before, &after);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.changed[0].is_reduced());
+        assert_eq!(
+            diff.levels,
+            vec![LevelChange {
+                price: 100,
+                quantity_before: 10,
+                quantity_after: 7,
+                orders_before: 2,
+                orders_after: 2,
+            }]
+        );
+        assert!(diff.top_changed);
+    }
+
+    #[test]
+    fn levels_are_ordered_best_price_first() {
+        let other = Pubkey::new_unique();
+        let before = vec![order(1, 100, 5, &other)];
+        let after = vec![order(2, 101, 1, &other), order(3, 99, 1, &other)];
+
+        let bids = OrderBookDiff::compute(Side::Bid, &before, &after);
+        let prices: Vec<u64> = bids.levels.iter().map(|l| l.price).collect();
+        assert_eq!(prices, vec![101, 100, 99]);
+
+        let asks = OrderBookDiff::compute(Side::Ask, &before, &after);
+        let prices: Vec<u64> = asks.levels.iter().map(|l| l.price).collect();
+        assert_eq!(prices, vec![99, 100, 101]);
+    }
+
+    #[test]
+    fn own_orders_hit_by_removal_or_reduction() {
+        let ours = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let before = vec![
+            order(1, 100, 5, &ours),
+            order(2, 100, 5, &other),
+            order(3, 99, 4, &ours),
+        ];
+        let after = vec![order(2, 100, 1, &other), order(3, 99, 1, &ours)];
+
+        let diff = OrderBookDiff::compute(Side::Bid, &before, &after);
+        let hits = diff.own_orders_hit(&ours);
+        assert_eq!(hits.len(), 2);
+
+        assert_eq!(hits[0].order_id, 1);
+        assert_eq!(hits[0].client_order_id, 1);
+        assert_eq!(hits[0].quantity, 5);
+        assert!(hits[0].removed);
+
+        assert_eq!(hits[1].order_id, 3);
+        assert_eq!(hits[1].quantity, 3);
+        assert!(!hits[1].removed);
+    }
+
+    #[test]
+    fn growing_own_order_is_not_a_hit() {
+        let ours = Pubkey::new_unique();
+        let before = vec![order(1, 100, 5, &ours)];
+        let after = vec![order(1, 100, 6, &ours)];
+
+        let diff = OrderBookDiff::compute(Side::Bid, &before, &after);
+        assert!(diff.own_orders_hit(&ours).is_empty());
+    }
+}
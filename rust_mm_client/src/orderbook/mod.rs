@@ -0,0 +1,7 @@
+pub mod book;
+pub mod diff;
+pub mod queue;
+
+pub use book::*;
+pub use diff::*;
+pub use queue::*;
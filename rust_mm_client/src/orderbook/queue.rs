@@ -0,0 +1,306 @@
+use {
+    super::{OrderBook, OrderBookUpdate},
+    serum_dex::matching::Side,
+    solana_sdk::pubkey::Pubkey,
+    std::collections::HashMap,
+};
+
+const BPS_UNIT: u128 = 10_000;
+
+/// Where one of our orders sits in the queue of its price level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub order_id: u128,
+    pub client_order_id: u64,
+    pub side: Side,
+    pub price: u64,
+    pub quantity: u64,
+    /// the quantity resting ahead of the order at its price level
+    pub size_ahead: u64,
+    /// the number of orders resting ahead of the order at its price level
+    pub orders_ahead: usize,
+    /// the total quantity resting at the order's price level, including the order itself
+    pub level_quantity: u64,
+}
+
+impl QueuePosition {
+    /// the share of the price level which is ahead of the order
+    pub fn size_ahead_bps(&self) -> u64 {
+        if self.level_quantity == 0 {
+            return 0;
+        }
+        (self.size_ahead as u128 * BPS_UNIT / self.level_quantity as u128) as u64
+    }
+
+    pub fn is_first(&self) -> bool {
+        self.orders_ahead == 0
+    }
+}
+
+/// Serum encodes the order's sequence number in the lower half of the order id, negated for bids
+/// so that the crit-bit tree walks every price level in time priority.
+#[inline]
+pub fn order_sequence_number(order_id: u128, side: Side) -> u64 {
+    match side {
+        Side::Bid => !(order_id as u64),
+        Side::Ask => order_id as u64,
+    }
+}
+
+impl OrderBook {
+    /// estimates the queue position of the order with the given id
+    pub fn queue_position(&self, side: Side, order_id: u128) -> Option<QueuePosition> {
+        let order = self.find_order(side, order_id)?;
+        let sequence_number = order_sequence_number(order_id, side);
+
+        let mut position = QueuePosition {
+            order_id,
+            client_order_id: order.client_order_id,
+            side,
+            price: order.price,
+            quantity: order.quantity,
+            size_ahead: 0,
+            orders_ahead: 0,
+            level_quantity: 0,
+        };
+
+        for other in self.side(side).iter().filter(|o| o.price == order.price) {
+            position.level_quantity += other.quantity;
+            if order_sequence_number(other.order_id, side) < sequence_number {
+                position.size_ahead += other.quantity;
+                position.orders_ahead += 1;
+            }
+        }
+
+        Some(position)
+    }
+}
+
+/// The queue position of one of our orders and how it evolved since it was first seen.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedQueuePosition {
+    pub current: QueuePosition,
+    /// the size ahead of the order when it was first seen in the book
+    pub initial_size_ahead: u64,
+    /// the number of book updates which moved the order up the queue
+    pub advances: u64,
+}
+
+/// Tracks the queue positions of the orders owned by our open orders account as the book changes.
+#[derive(Debug, Default)]
+pub struct QueueTracker {
+    open_orders: Pubkey,
+    positions: HashMap<u128, TrackedQueuePosition>,
+}
+
+impl QueueTracker {
+    pub fn new(open_orders: Pubkey) -> Self {
+        Self {
+            open_orders,
+            positions: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, order_id: u128) -> Option<&TrackedQueuePosition> {
+        self.positions.get(&order_id)
+    }
+
+    pub fn positions(&self) -> Vec<TrackedQueuePosition> {
+        self.positions.values().copied().collect()
+    }
+
+    /// refreshes the positions of our orders on the side of the book which changed
+    pub fn update(&mut self, update: &OrderBookUpdate) {
+        let side = update.diff.side;
+        let book = &update.book;
+
+        // orders which left the book no longer have a queue position
+        for order in update.diff.removed.iter() {
+            self.positions.remove(&order.order_id);
+        }
+
+        let changed_prices: Vec<u64> = update.diff.levels.iter().map(|l| l.price).collect();
+
+        for order in book
+            .side(side)
+            .iter()
+            .filter(|o| o.owner() == self.open_orders)
+        {
+            let tracked = self.positions.get(&order.order_id).copied();
+
+            // the position of an order can only move if its level changed
+            if tracked.is_some() && !changed_prices.contains(&order.price) {
+                continue;
+            }
+
+            let position = match book.queue_position(side, order.order_id) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let tracked = match tracked {
+                Some(t) => TrackedQueuePosition {
+                    current: position,
+                    initial_size_ahead: t.initial_size_ahead,
+                    advances: if position.size_ahead < t.current.size_ahead {
+                        t.advances + 1
+                    } else {
+                        t.advances
+                    },
+                },
+                None => TrackedQueuePosition {
+                    current: position,
+                    initial_size_ahead: position.size_ahead,
+                    advances: 0,
+                },
+            };
+            self.positions.insert(order.order_id, tracked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{orderbook::OrderBookDiff, serum_slab::OrderBookOrder},
+        bytemuck::cast,
+        std::sync::Arc,
+    };
+
+    /// an order id as serum builds it, the price in the upper half and the sequence number in the
+    /// lower half, negated for bids
+    fn order_id(side: Side, price: u64, sequence_number: u64) -> u128 {
+        let lower = match side {
+            Side::Bid => !sequence_number,
+            Side::Ask => sequence_number,
+        };
+        (price as u128) << 64 | lower as u128
+    }
+
+    fn order(
+        side: Side,
+        price: u64,
+        sequence_number: u64,
+        quantity: u64,
+        owner: &Pubkey,
+    ) -> OrderBookOrder {
+        OrderBookOrder {
+            price,
+            quantity,
+            order_id: order_id(side, price, sequence_number),
+            client_order_id: sequence_number,
+            owner: cast(owner.to_bytes()),
+        }
+    }
+
+    fn book(open_orders: &Pubkey, side: Side, orders: Vec<OrderBookOrder>) -> OrderBook {
+        let mut book = OrderBook::new(Pubkey::new_unique(), *open_orders);
+        match side {
+            Side::Bid => book.bids = orders,
+            Side::Ask => book.asks = orders,
+        }
+        book
+    }
+
+    #[test]
+    fn sequence_numbers_of_both_sides() {
+        assert_eq!(
+            order_sequence_number(order_id(Side::Bid, 100, 7), Side::Bid),
+            7
+        );
+        assert_eq!(
+            order_sequence_number(order_id(Side::Ask, 100, 7), Side::Ask),
+            7
+        );
+    }
+
+    #[test]
+    fn counts_only_older_orders_at_the_same_price() {
+        for side in [Side::Bid, Side::Ask] {
+            let ours = Pubkey::new_unique();
+            let other = Pubkey::new_unique();
+            let book = book(
+                &ours,
+                side,
+                vec![
+                    order(side, 100, 1, 5, &other),
+                    order(side, 100, 2, 3, &other),
+                    order(side, 100, 3, 4, &ours),
+                    order(side, 100, 4, 6, &other),
+                    order(side, 101, 0, 9, &other),
+                ],
+            );
+
+            let position = book.queue_position(side, order_id(side, 100, 3)).unwrap();
+            assert_eq!(position.size_ahead, 8);
+            assert_eq!(position.orders_ahead, 2);
+            assert_eq!(position.level_quantity, 18);
+            assert_eq!(position.quantity, 4);
+            // 8 out of 18 is ahead
+            assert_eq!(position.size_ahead_bps(), 4444);
+            assert!(!position.is_first());
+        }
+    }
+
+    #[test]
+    fn first_in_the_queue() {
+        let ours = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let book = book(
+            &ours,
+            Side::Ask,
+            vec![
+                order(Side::Ask, 100, 1, 5, &ours),
+                order(Side::Ask, 100, 2, 5, &other),
+            ],
+        );
+
+        let position = book
+            .queue_position(Side::Ask, order_id(Side::Ask, 100, 1))
+            .unwrap();
+        assert!(position.is_first());
+        assert_eq!(position.size_ahead_bps(), 0);
+        assert!(book
+            .queue_position(Side::Ask, order_id(Side::Ask, 100, 3))
+            .is_none());
+    }
+
+    #[test]
+    fn tracker_counts_advances_and_forgets_removed_orders() {
+        let ours = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let ahead = order(Side::Bid, 100, 1, 5, &other);
+        let own = order(Side::Bid, 100, 2, 4, &ours);
+
+        let mut tracker = QueueTracker::new(ours);
+        let first = book(&ours, Side::Bid, vec![ahead, own]);
+        tracker.update(&OrderBookUpdate {
+            diff: OrderBookDiff::compute(Side::Bid, &[], &first.bids),
+            book: Arc::new(first.clone()),
+        });
+        let tracked = *tracker.get(own.order_id).unwrap();
+        assert_eq!(tracked.initial_size_ahead, 5);
+        assert_eq!(tracked.advances, 0);
+
+        // the order ahead is filled
+        let second = book(&ours, Side::Bid, vec![own]);
+        tracker.update(&OrderBookUpdate {
+            diff: OrderBookDiff::compute(Side::Bid, &first.bids, &second.bids),
+            book: Arc::new(second.clone()),
+        });
+        let tracked = *tracker.get(own.order_id).unwrap();
+        assert_eq!(tracked.current.size_ahead, 0);
+        assert_eq!(tracked.initial_size_ahead, 5);
+        assert_eq!(tracked.advances, 1);
+
+        // and then ours
+        let third = book(&ours, Side::Bid, Vec::new());
+        tracker.update(&OrderBookUpdate {
+            diff: OrderBookDiff::compute(Side::Bid, &second.bids, &third.bids),
+            book: Arc::new(third),
+        });
+        assert!(tracker.get(own.order_id).is_none());
+        assert!(tracker.positions().is_empty());
+    }
+}
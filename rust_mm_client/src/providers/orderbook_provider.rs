@@ -1,11 +1,13 @@
 use {
     crate::{
         accounts_cache::AccountsCache,
+        orderbook::{OrderBook, OrderBookDiff, OrderBookUpdate},
         serum_slab::{OrderBookOrder, Slab},
         MarketMakerError,
     },
     arrayref::array_refs,
-    log::{info, warn},
+    log::{debug, info, warn},
+    serum_dex::matching::Side,
     solana_sdk::pubkey::Pubkey,
     std::sync::Arc,
     tokio::sync::{
@@ -14,79 +16,68 @@ use {
     },
 };
 
-#[derive(Default)]
-pub struct OrderBook {
-    pub market: Pubkey,
-    pub bids: RwLock<Vec<OrderBookOrder>>,
-    pub asks: RwLock<Vec<OrderBookOrder>>,
-}
-
-impl OrderBook {
-    pub fn new(market: Pubkey) -> Self {
-        Self {
-            market,
-            bids: RwLock::new(Vec::new()),
-            asks: RwLock::new(Vec::new()),
-        }
-    }
-}
-
 pub struct OrderBookProvider {
     cache: Arc<AccountsCache>,
-    sender: Arc<Sender<Arc<OrderBook>>>,
+    sender: Arc<Sender<Arc<OrderBookUpdate>>>,
     receiver: Mutex<Receiver<Pubkey>>,
     shutdown_receiver: Mutex<Receiver<bool>>,
-    book: Arc<OrderBook>,
+    book: RwLock<Arc<OrderBook>>,
     market: Pubkey,
     bids: Pubkey,
     asks: Pubkey,
     coin_lot_size: u64,
     pc_lot_size: u64,
     coin_decimals: u64,
+    /// the number of orders to read from each side of the book, `None` reads the full book
+    depth: Option<u64>,
 }
 
 impl OrderBookProvider {
     pub fn default() -> Self {
         Self {
             cache: Arc::new(AccountsCache::default()),
-            sender: Arc::new(channel::<Arc<OrderBook>>(u16::MAX as usize).0),
+            sender: Arc::new(channel::<Arc<OrderBookUpdate>>(u16::MAX as usize).0),
             receiver: Mutex::new(channel::<Pubkey>(u16::MAX as usize).1),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
-            book: Arc::new(OrderBook::default()),
+            book: RwLock::new(Arc::new(OrderBook::default())),
             market: Pubkey::default(),
             bids: Pubkey::default(),
             asks: Pubkey::default(),
             coin_lot_size: u64::default(),
             pc_lot_size: u64::default(),
             coin_decimals: u64::default(),
+            depth: None,
         }
     }
 
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         cache: Arc<AccountsCache>,
-        sender: Arc<Sender<Arc<OrderBook>>>,
+        sender: Arc<Sender<Arc<OrderBookUpdate>>>,
         receiver: Receiver<Pubkey>,
         shutdown_receiver: Receiver<bool>,
         market: Pubkey,
+        open_orders: Pubkey,
         bids: Pubkey,
         asks: Pubkey,
         coin_lot_size: u64,
         pc_lot_size: u64,
         coin_decimals: u64,
+        depth: Option<u64>,
     ) -> Self {
         Self {
             cache,
             sender,
             receiver: Mutex::new(receiver),
             shutdown_receiver: Mutex::new(shutdown_receiver),
-            book: Arc::new(OrderBook::new(market)),
+            book: RwLock::new(Arc::new(OrderBook::new(market, open_orders))),
             market,
             bids,
             asks,
             coin_lot_size,
             pc_lot_size,
             coin_decimals,
+            depth,
         }
     }
 
@@ -127,43 +118,57 @@ impl OrderBookProvider {
     }
 
     #[allow(clippy::ptr_offset_with_cast)]
-    async fn process_updates(self: &Arc<Self>, key: Pubkey) -> Result<(), MarketMakerError> {
-        let mut updated: bool = false;
+    fn read_side(&self, key: &Pubkey, is_asks: bool) -> Vec<OrderBookOrder> {
+        let ai = self.cache.get(key).unwrap();
+        let (_head, data, _tail) = array_refs![&ai.account, 5; ..; 7];
+        let data = &mut data[8..].to_vec().clone();
+        let slab = Slab::new(data);
+
+        slab.get_depth(
+            self.depth.unwrap_or(u64::MAX),
+            self.pc_lot_size,
+            self.coin_lot_size,
+            is_asks,
+        )
+    }
 
-        if key == self.bids {
-            let bid_ai = self.cache.get(&key).unwrap();
-            let (_bid_head, bid_data, _bid_tail) = array_refs![&bid_ai.account, 5; ..; 7];
-            let bid_data = &mut bid_data[8..].to_vec().clone();
-            let bids = Slab::new(bid_data);
+    async fn process_updates(self: &Arc<Self>, key: Pubkey) -> Result<(), MarketMakerError> {
+        if key != self.bids && key != self.asks {
+            return Ok(());
+        }
 
-            let obl = bids.get_depth(25, self.pc_lot_size, self.coin_lot_size, false);
+        let mut book = self.book.write().await;
+        let mut new_book = OrderBook::clone(&book);
 
-            *self.book.bids.write().await = obl;
-            updated = true;
-        } else if key == self.asks {
-            let ask_ai = self.cache.get(&key).unwrap();
-            let (_ask_head, ask_data, _ask_tail) = array_refs![&ask_ai.account, 5; ..; 7];
-            let ask_data = &mut ask_data[8..].to_vec().clone();
-            let asks = Slab::new(ask_data);
+        let diff = if key == self.bids {
+            new_book.bids = self.read_side(&key, false);
+            OrderBookDiff::compute(Side::Bid, &book.bids, &new_book.bids)
+        } else {
+            new_book.asks = self.read_side(&key, true);
+            OrderBookDiff::compute(Side::Ask, &book.asks, &new_book.asks)
+        };
 
-            let obl = asks.get_depth(25, self.pc_lot_size, self.coin_lot_size, true);
+        let new_book = Arc::new(new_book);
+        *book = Arc::clone(&new_book);
+        drop(book);
 
-            *self.book.asks.write().await = obl;
-            updated = true;
+        if diff.is_empty() {
+            return Ok(());
         }
 
-        if updated {
-            let res = self.sender.send(Arc::clone(&self.book));
+        let update = Arc::new(OrderBookUpdate {
+            book: new_book,
+            diff,
+        });
 
-            match res {
-                Ok(_) => {
-                    info!("[OBP] Updated orderbook for market: {}.", self.market);
-                }
-                Err(_) => {
-                    return Err(MarketMakerError::ChannelSendError);
-                }
-            };
-        }
+        match self.sender.send(update) {
+            Ok(_) => {
+                debug!("[OBP] Updated orderbook for market: {}.", self.market);
+            }
+            Err(_) => {
+                return Err(MarketMakerError::ChannelSendError);
+            }
+        };
 
         Ok(())
     }
@@ -1,8 +1,9 @@
 #![allow(dead_code, clippy::upper_case_acronyms)]
 use {
     arrayref::array_refs,
-    bytemuck::{cast_mut, cast_ref, cast_slice, Pod, Zeroable},
+    bytemuck::{cast, cast_mut, cast_ref, cast_slice, Pod, Zeroable},
     num_enum::{IntoPrimitive, TryFromPrimitive},
+    solana_sdk::pubkey::Pubkey,
     static_assertions::const_assert_eq,
     std::{
         convert::TryFrom,
@@ -295,6 +296,15 @@ pub struct OrderBookOrder {
     pub quantity: u64,
     pub order_id: u128,
     pub client_order_id: u64,
+    pub owner: [u64; 4],
+}
+
+impl OrderBookOrder {
+    /// the open orders account which owns this order
+    #[inline]
+    pub fn owner(&self) -> Pubkey {
+        Pubkey::new_from_array(cast(self.owner))
+    }
 }
 
 #[repr(transparent)]
@@ -321,6 +331,7 @@ impl Slab {
     }
 
     //Each one of these does a preorder traversal
+    //Pass `u64::MAX` as the depth to walk the whole book
     pub fn get_depth(
         &self,
         depth: u64,
@@ -349,6 +360,7 @@ impl Slab {
                 quantity: token_quantity,
                 order_id: leaf.order_id(),
                 client_order_id: leaf.client_order_id,
+                owner: leaf.owner(),
             };
             res.push(line);
         }
@@ -1,12 +1,51 @@
 use std::sync::Arc;
 use futures::StreamExt;
-use log::{warn, info};
+use log::{debug, warn, info};
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::{nonblocking::{pubsub_client::{PubsubClient, PubsubClientError}, rpc_client::RpcClient}, rpc_config::RpcAccountInfoConfig, client_error::ClientError};
 use solana_sdk::{pubkey::Pubkey, commitment_config::CommitmentConfig};
+use prometheus::{IntCounterVec, IntGaugeVec};
 use tokio::{sync::broadcast::{Sender, channel}, task::JoinHandle};
 
-use crate::{accounts_cache::{AccountsCache, AccountState}, providers::get_account_info};
+use crate::{accounts_cache::{AccountsCache, AccountState}, metrics, providers::get_account_info, services::ChainMetaService};
+
+#[derive(Clone)]
+struct AccountInfoServiceMetrics {
+    updates: IntCounterVec,
+    update_slot: IntGaugeVec,
+    slot_lag: IntGaugeVec,
+}
+
+impl AccountInfoServiceMetrics {
+    fn new() -> Self {
+        Self {
+            updates: metrics::int_counter_vec(
+                "mm_account_updates_total",
+                "Number of updates received for each subscribed account.",
+                "",
+                &["account"],
+            ),
+            update_slot: metrics::int_gauge_vec(
+                "mm_account_update_slot",
+                "Slot of the most recent update received for each subscribed account.",
+                "",
+                &["account"],
+            ),
+            slot_lag: metrics::int_gauge_vec(
+                "mm_account_slot_lag",
+                "Number of slots between the most recent update for each subscribed account and the chain slot.",
+                "",
+                &["account"],
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.updates);
+        metrics::register(&self.update_slot);
+        metrics::register(&self.slot_lag);
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct AccountSubscription {
@@ -18,9 +57,11 @@ pub struct AccountInfoService {
     cache: Arc<AccountsCache>,
     pubsub_client: Arc<PubsubClient>,
     rpc_client: Arc<RpcClient>,
+    chain_meta_service: Arc<ChainMetaService>,
     subs: Vec<Pubkey>,
     tasks: Vec<JoinHandle<()>>,
     shutdown: Arc<Sender<bool>>,
+    metrics: AccountInfoServiceMetrics,
 }
 
 impl AccountInfoService {
@@ -29,9 +70,11 @@ impl AccountInfoService {
             cache: Arc::new(AccountsCache::default()),
             pubsub_client: Arc::new(PubsubClient::new("wss://devnet.genesysgo.net").await.unwrap()),
             rpc_client: Arc::new(RpcClient::new("http://localhost:8899".to_string())),
+            chain_meta_service: Arc::new(ChainMetaService::default()),
             subs: Vec::new(),
             tasks: Vec::new(),
-            shutdown: Arc::new(channel::<bool>(1).0)
+            shutdown: Arc::new(channel::<bool>(1).0),
+            metrics: AccountInfoServiceMetrics::new(),
         }
     }
 
@@ -39,16 +82,22 @@ impl AccountInfoService {
         cache: Arc<AccountsCache>,
         pubsub_client: Arc<PubsubClient>,
         rpc_client: Arc<RpcClient>,
+        chain_meta_service: Arc<ChainMetaService>,
         subs: &[Pubkey],
         shutdown: Arc<Sender<bool>>,
     ) -> Self {
+        let metrics = AccountInfoServiceMetrics::new();
+        metrics.register();
+
         Self {
             cache,
             pubsub_client,
             rpc_client,
+            chain_meta_service,
             shutdown,
             subs: Vec::from(subs),
-            tasks: Vec::new()
+            tasks: Vec::new(),
+            metrics,
         }
     }
 
@@ -66,8 +115,10 @@ impl AccountInfoService {
             let handler = SubscriptionHandler::new(
                 Arc::clone(&self.cache),
                 Arc::clone(&self.pubsub_client),
+                Arc::clone(&self.chain_meta_service),
                 Arc::clone(&self.shutdown),
                 account_sub,
+                self.metrics.clone(),
             );
 
             let t = tokio::spawn(
@@ -155,22 +206,28 @@ impl AccountInfoService {
 struct SubscriptionHandler {
     cache: Arc<AccountsCache>,
     pubsub_client: Arc<PubsubClient>,
+    chain_meta_service: Arc<ChainMetaService>,
     shutdown: Arc<Sender<bool>>,
     sub: Pubkey,
+    metrics: AccountInfoServiceMetrics,
 }
 
 impl SubscriptionHandler {
     pub fn new(
         cache: Arc<AccountsCache>,
         pubsub_client: Arc<PubsubClient>,
+        chain_meta_service: Arc<ChainMetaService>,
         shutdown: Arc<Sender<bool>>,
         sub: Pubkey,
+        metrics: AccountInfoServiceMetrics,
     ) -> Self {
         Self {
             cache,
             pubsub_client,
+            chain_meta_service,
             shutdown,
-            sub
+            sub,
+            metrics,
         }
     }
 
@@ -191,13 +248,19 @@ impl SubscriptionHandler {
             .unwrap();
 
         let mut stream = sub.0;
+        let account = self.sub.to_string();
         loop {
             tokio::select! {
                 update = stream.next() => {
                     if update.is_some() {
                         let account_res = update.unwrap();
                         let account_data = get_account_info(&account_res.value).unwrap();
-                        info!("[AIS] Received account update for {}, updating cache.", self.sub);
+                        let slot = account_res.context.slot;
+                        debug!(slot = slot; "[AIS] Received account update for {}, updating cache.", self.sub);
+                        let chain_slot = self.chain_meta_service.get_slot().await;
+                        self.metrics.updates.with_label_values(&[&account]).inc();
+                        self.metrics.update_slot.with_label_values(&[&account]).set(slot as i64);
+                        self.metrics.slot_lag.with_label_values(&[&account]).set(chain_slot.saturating_sub(slot) as i64);
                         let res = self.cache.insert(self.sub, AccountState{
                             account: account_data,
                             slot: account_res.context.slot,
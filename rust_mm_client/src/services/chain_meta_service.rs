@@ -1,5 +1,7 @@
 use {
-    log::{info, warn},
+    crate::metrics,
+    log::{debug, info, warn},
+    prometheus::{Histogram, IntCounter, IntGauge},
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
     solana_sdk::commitment_config::CommitmentConfig,
     solana_sdk::hash::Hash,
@@ -8,15 +10,47 @@ use {
     tokio::sync::Mutex,
     tokio::{
         sync::RwLock,
-        time::{sleep, Duration},
+        time::{sleep, Duration, Instant},
     },
 };
 
+struct ChainMetaServiceMetrics {
+    slot: IntGauge,
+    update_failures: IntCounter,
+    rpc_latency: Histogram,
+}
+
+impl ChainMetaServiceMetrics {
+    fn new() -> Self {
+        Self {
+            slot: metrics::int_gauge("mm_chain_slot", "Most recent slot fetched from the RPC.", ""),
+            update_failures: metrics::int_counter(
+                "mm_chain_meta_update_failures_total",
+                "Number of failed attempts at fetching the recent blockhash and slot.",
+                "",
+            ),
+            rpc_latency: metrics::histogram(
+                "mm_chain_meta_rpc_latency_seconds",
+                "Time taken to fetch the recent blockhash and slot.",
+                "",
+                vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0],
+            ),
+        }
+    }
+
+    fn register(&self) {
+        metrics::register(&self.slot);
+        metrics::register(&self.update_failures);
+        metrics::register(&self.rpc_latency);
+    }
+}
+
 pub struct ChainMetaService {
     client: Arc<RpcClient>,
     recent_blockhash: RwLock<Hash>,
     slot: RwLock<u64>,
     shutdown_receiver: Mutex<Receiver<bool>>,
+    metrics: ChainMetaServiceMetrics,
 }
 
 impl ChainMetaService {
@@ -26,19 +60,25 @@ impl ChainMetaService {
             recent_blockhash: RwLock::new(Hash::default()),
             slot: RwLock::new(u64::default()),
             shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+            metrics: ChainMetaServiceMetrics::new(),
         }
     }
 
     pub fn new(client: Arc<RpcClient>, shutdown_receiver: Receiver<bool>) -> ChainMetaService {
+        let metrics = ChainMetaServiceMetrics::new();
+        metrics.register();
+
         ChainMetaService {
             client,
             shutdown_receiver: Mutex::new(shutdown_receiver),
+            metrics,
             ..ChainMetaService::default()
         }
     }
 
     #[inline(always)]
     async fn update_chain_meta(self: &Arc<Self>) -> Result<(), ClientError> {
+        let start = Instant::now();
         let hash_res = self
             .client
             .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
@@ -50,7 +90,7 @@ impl ChainMetaService {
                 return Err(e);
             }
         };
-        info!("[CMS] Fetched recent block hash: {}", hash.0.to_string());
+        debug!("[CMS] Fetched recent block hash: {}", hash.0.to_string());
         *self.recent_blockhash.write().await = hash.0;
 
         let slot_res = self.client.get_slot().await;
@@ -61,9 +101,14 @@ impl ChainMetaService {
                 return Err(e);
             }
         };
-        info!("[CMS] Fetched recent slot: {}", slot);
+        debug!(slot = slot; "[CMS] Fetched recent slot: {}", slot);
         *self.slot.write().await = slot;
 
+        self.metrics
+            .rpc_latency
+            .observe(start.elapsed().as_secs_f64());
+        self.metrics.slot.set(slot as i64);
+
         Ok(())
     }
 
@@ -73,6 +118,7 @@ impl ChainMetaService {
             let res = self.update_chain_meta().await;
 
             if res.is_err() {
+                self.metrics.update_failures.inc();
                 warn!(
                     "[CMS] Couldn't get new chain meta! Error: {}",
                     res.err().unwrap().to_string()
@@ -102,4 +148,9 @@ impl ChainMetaService {
         //Copy and return hash
         *self.recent_blockhash.read().await
     }
+
+    #[inline(always)]
+    pub async fn get_slot(self: &Arc<Self>) -> u64 {
+        *self.slot.read().await
+    }
 }
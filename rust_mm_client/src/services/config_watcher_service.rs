@@ -0,0 +1,191 @@
+use {
+    super::{ControlCommand, ControlRequest},
+    crate::config::{load_mm_config, ConfigOverrides, MarketMakerConfig},
+    log::{info, warn},
+    std::{fs, sync::Arc, time::SystemTime},
+    tokio::{
+        signal::unix::{signal, Signal, SignalKind},
+        sync::{
+            broadcast::{channel, Receiver},
+            mpsc, oneshot, Mutex, RwLock,
+        },
+        time::{sleep, Duration},
+    },
+};
+
+/// Reloads the market maker config when the file changes or on SIGHUP and hands the
+/// parts which can be applied live to the worker.
+pub struct ConfigWatcherService {
+    path: String,
+    overrides: ConfigOverrides,
+    config: RwLock<Arc<MarketMakerConfig>>,
+    last_modified: RwLock<Option<SystemTime>>,
+    sender: mpsc::Sender<ControlRequest>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl ConfigWatcherService {
+    pub fn default() -> Self {
+        Self {
+            path: String::default(),
+            overrides: ConfigOverrides::default(),
+            config: RwLock::new(Arc::new(MarketMakerConfig::default())),
+            last_modified: RwLock::new(None),
+            sender: mpsc::channel::<ControlRequest>(1).0,
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+        }
+    }
+
+    pub fn new(
+        path: &str,
+        overrides: ConfigOverrides,
+        config: Arc<MarketMakerConfig>,
+        sender: mpsc::Sender<ControlRequest>,
+        shutdown_receiver: Receiver<bool>,
+    ) -> Self {
+        Self {
+            path: path.to_string(),
+            overrides,
+            config: RwLock::new(config),
+            last_modified: RwLock::new(modified_at(path)),
+            sender,
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!(
+                    "[CFGW] Failed to listen for SIGHUP, the config will only be reloaded when the file changes: {}",
+                    e.to_string()
+                );
+                None
+            }
+        };
+
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        info!("[CFGW] Watching config file {} for changes.", self.path);
+
+        loop {
+            let poll_interval = {
+                let config = self.config.read().await;
+                if config.hot_reload_config.watch {
+                    Some(Duration::from_millis(config.hot_reload_config.poll_interval_ms))
+                } else {
+                    None
+                }
+            };
+
+            tokio::select! {
+                _ = sleep(poll_interval.unwrap_or(Duration::from_secs(60))), if poll_interval.is_some() => {
+                    let modified = modified_at(&self.path);
+                    if modified != *self.last_modified.read().await {
+                        *self.last_modified.write().await = modified;
+                        info!("[CFGW] Config file {} changed, reloading.", self.path);
+                        self.reload().await;
+                    }
+                },
+                _ = recv_hangup(&mut hangup) => {
+                    info!("[CFGW] Received SIGHUP, reloading config from {}.", self.path);
+                    *self.last_modified.write().await = modified_at(&self.path);
+                    self.reload().await;
+                },
+                _ = shutdown.recv() => {
+                    info!("[CFGW] Received shutdown signal, stopping.");
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn reload(self: &Arc<Self>) {
+        let mut new_config = match load_mm_config(&self.path) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(
+                    "[CFGW] Failed to load config from {}, keeping the current config: {}",
+                    self.path,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        // the overrides still apply, otherwise they would show up as changes to the config
+        self.overrides.apply_mm_config(&mut new_config);
+
+        if let Err(errors) = new_config.validate(&self.path) {
+            for error in errors {
+                warn!("[CFGW] Invalid config: {}", error);
+            }
+            warn!("[CFGW] Rejecting config reload, keeping the current config.");
+            return;
+        }
+
+        let current_config = Arc::clone(&*self.config.read().await);
+        let diff = match current_config.diff(&new_config) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("[CFGW] Failed to compare configs: {}", e.to_string());
+                return;
+            }
+        };
+
+        if diff.is_empty() {
+            info!("[CFGW] Config did not change.");
+            return;
+        }
+
+        if !diff.rejected.is_empty() {
+            for field in diff.rejected.iter() {
+                warn!(
+                    "[CFGW] `{}` cannot be changed while the market maker is running, restart it to apply this change.",
+                    field
+                );
+            }
+            warn!("[CFGW] Rejecting config reload, keeping the current config.");
+            return;
+        }
+
+        for field in diff.changed.iter() {
+            info!("[CFGW] Applying change to `{}`.", field);
+        }
+
+        let new_config = Arc::new(new_config);
+        let (reply, reply_receiver) = oneshot::channel();
+        let request = ControlRequest {
+            command: ControlCommand::ReloadConfig(Arc::clone(&new_config)),
+            reply,
+        };
+        if self.sender.send(request).await.is_err() {
+            warn!("[CFGW] Failed to send the new config, the worker is not running.");
+            return;
+        }
+
+        match reply_receiver.await {
+            Ok(Ok(_)) => {
+                *self.config.write().await = new_config;
+                info!("[CFGW] Successfully applied the new config.");
+            }
+            Ok(Err(e)) => {
+                warn!("[CFGW] The worker rejected the new config: {}", e);
+            }
+            Err(_) => {
+                warn!("[CFGW] The worker stopped before applying the new config.");
+            }
+        }
+    }
+}
+
+async fn recv_hangup(hangup: &mut Option<Signal>) -> Option<()> {
+    match hangup {
+        Some(s) => s.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
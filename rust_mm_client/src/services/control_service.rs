@@ -0,0 +1,338 @@
+use {
+    crate::{
+        config::MarketMakerConfig,
+        market_maker::{CapitalManager, InventoryParams, Rebalance},
+    },
+    hyper::{
+        body,
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        service::{make_service_fn, service_fn},
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    serde_json::{json, Value},
+    std::{convert::Infallible, env, net::SocketAddr, sync::Arc},
+    tokio::sync::{
+        broadcast::{channel, Receiver},
+        mpsc, oneshot, Mutex,
+    },
+};
+
+/// the environment variable which takes precedence over the auth token in the config
+pub const CONTROL_TOKEN_ENV: &str = "MM_CONTROL_TOKEN";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlConfig {
+    /// the address the control api is served on, this should not be reachable from outside the host
+    pub bind_address: String,
+    /// the bearer token requests need to provide, can be omitted if it is set in `MM_CONTROL_TOKEN`
+    #[serde(default, skip_serializing)]
+    pub auth_token: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RebalanceRequest {
+    /// only log the transfer which would be made
+    pub dry_run: bool,
+}
+
+/// Changes to the quoting parameters, omitted fields are left unchanged.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParamsUpdate {
+    pub spread: Option<u8>,
+    pub max_quote: Option<i64>,
+    pub shape_num: Option<u32>,
+    pub shape_denom: Option<u32>,
+}
+
+impl ParamsUpdate {
+    pub fn apply(&self, params: &InventoryParams) -> Result<InventoryParams, String> {
+        let updated = InventoryParams {
+            spread: self.spread.unwrap_or(params.spread),
+            max_quote: self.max_quote.unwrap_or(params.max_quote),
+            shape_num: self.shape_num.unwrap_or(params.shape_num),
+            shape_denom: self.shape_denom.unwrap_or(params.shape_denom),
+        };
+
+        if updated.max_quote < 0 {
+            return Err("maxQuote must not be negative".to_string());
+        }
+        if updated.shape_denom == 0 {
+            return Err("shapeDenom must be greater than zero".to_string());
+        }
+        Ok(updated)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ControlCommand {
+    Status,
+    Config,
+    Pause,
+    Resume,
+    CancelAll,
+    Flatten,
+    UpdateParams(ParamsUpdate),
+    /// sent by the config watcher once a changed config has been validated
+    ReloadConfig(Arc<MarketMakerConfig>),
+}
+
+/// A command sent to the worker, which replies once the command has been applied.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub reply: oneshot::Sender<Result<Value, String>>,
+}
+
+/// Serves the operator api and forwards commands to the worker.
+pub struct ControlService {
+    bind_address: String,
+    auth_token: Option<String>,
+    sender: mpsc::Sender<ControlRequest>,
+    /// rebalances do not touch the quoting state, so they bypass the worker
+    capital_manager: Option<Arc<CapitalManager>>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl ControlService {
+    pub fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:9091".to_string(),
+            auth_token: None,
+            sender: mpsc::channel::<ControlRequest>(1).0,
+            capital_manager: None,
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+        }
+    }
+
+    pub fn new(
+        config: &ControlConfig,
+        sender: mpsc::Sender<ControlRequest>,
+        capital_manager: Option<Arc<CapitalManager>>,
+        shutdown_receiver: Receiver<bool>,
+    ) -> Self {
+        let auth_token = match env::var(CONTROL_TOKEN_ENV) {
+            Ok(t) if !t.is_empty() => Some(t),
+            _ => config.auth_token.clone(),
+        };
+
+        Self {
+            bind_address: config.bind_address.to_string(),
+            auth_token,
+            sender,
+            capital_manager,
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        if self.auth_token.is_none() {
+            warn!(
+                "[CONTROL] No auth token configured, set one in the config or in {}. Not starting the control api.",
+                CONTROL_TOKEN_ENV
+            );
+            return;
+        }
+
+        let addr: SocketAddr = match self.bind_address.parse() {
+            Ok(a) => a,
+            Err(e) => {
+                warn!(
+                    "[CONTROL] Invalid bind address {}: {}",
+                    self.bind_address,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        let aself = Arc::clone(self);
+        let make_svc = make_service_fn(move |_conn| {
+            let service = Arc::clone(&aself);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let service = Arc::clone(&service);
+                    async move { service.handle(req).await }
+                }))
+            }
+        });
+
+        let server = match Server::try_bind(&addr) {
+            Ok(b) => b.serve(make_svc),
+            Err(e) => {
+                warn!(
+                    "[CONTROL] Failed to bind control api to {}: {}",
+                    addr,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        info!("[CONTROL] Serving control api on http://{}", addr);
+
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let graceful = server.with_graceful_shutdown(async {
+            let _ = shutdown.recv().await;
+        });
+
+        match graceful.await {
+            Ok(_) => {
+                info!("[CONTROL] Received shutdown signal, stopping.");
+            }
+            Err(e) => {
+                warn!(
+                    "[CONTROL] There was an error serving the control api: {}",
+                    e.to_string()
+                );
+            }
+        }
+    }
+
+    async fn handle(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        // without a token configured every request is refused
+        let authorized = match &self.auth_token {
+            Some(t) => is_authorized(&req, t),
+            None => false,
+        };
+        if !authorized {
+            return Ok(json_response(
+                StatusCode::UNAUTHORIZED,
+                json!({ "error": "unauthorized" }),
+            ));
+        }
+
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        info!("[CONTROL] Received request: {} {}", method, path);
+
+        let command = match (&method, path.as_str()) {
+            (&Method::GET, "/status") => ControlCommand::Status,
+            (&Method::GET, "/config") => ControlCommand::Config,
+            (&Method::POST, "/pause") => ControlCommand::Pause,
+            (&Method::POST, "/resume") => ControlCommand::Resume,
+            (&Method::POST, "/cancel-all") => ControlCommand::CancelAll,
+            (&Method::POST, "/flatten") => ControlCommand::Flatten,
+            (&Method::POST, "/params") => match read_json::<ParamsUpdate>(req).await {
+                Ok(update) => ControlCommand::UpdateParams(update),
+                Err(response) => return Ok(response),
+            },
+            (&Method::POST, "/rebalance") => {
+                return Ok(self.rebalance(req).await);
+            }
+            _ => {
+                return Ok(json_response(
+                    StatusCode::NOT_FOUND,
+                    json!({ "error": "not found" }),
+                ));
+            }
+        };
+
+        let (reply, reply_receiver) = oneshot::channel();
+        if self
+            .sender
+            .send(ControlRequest { command, reply })
+            .await
+            .is_err()
+        {
+            return Ok(json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "error": "the worker is not running" }),
+            ));
+        }
+
+        let response = match reply_receiver.await {
+            Ok(Ok(value)) => json_response(StatusCode::OK, value),
+            Ok(Err(e)) => json_response(StatusCode::BAD_REQUEST, json!({ "error": e })),
+            Err(_) => json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "error": "the worker stopped before replying" }),
+            ),
+        };
+        Ok(response)
+    }
+
+    async fn rebalance(&self, req: Request<Body>) -> Response<Body> {
+        let capital_manager = match &self.capital_manager {
+            Some(c) => c,
+            None => {
+                return json_response(
+                    StatusCode::BAD_REQUEST,
+                    json!({ "error": "no capitalManagerConfig configured" }),
+                );
+            }
+        };
+
+        // an empty body rebalances with the configured dry run setting
+        let request = match read_json::<RebalanceRequest>(req).await {
+            Ok(r) => r,
+            Err(response) => return response,
+        };
+
+        match capital_manager.rebalance(request.dry_run).await {
+            Ok(rebalance) => {
+                let (action, amount) = match rebalance {
+                    Rebalance::Sweep(a) => ("sweep", a),
+                    Rebalance::TopUp(a) => ("topUp", a),
+                    Rebalance::Hold => ("hold", 0),
+                };
+                json_response(
+                    StatusCode::OK,
+                    json!({ "action": action, "amount": amount }),
+                )
+            }
+            Err(e) => json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "error": format!("{:?}", e) }),
+            ),
+        }
+    }
+}
+
+/// whether the request carries `auth_token` as its bearer token
+pub fn is_authorized(req: &Request<Body>, auth_token: &str) -> bool {
+    let provided = match req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+    {
+        Some(t) => t,
+        None => return false,
+    };
+
+    constant_time_eq(provided.as_bytes(), auth_token.as_bytes())
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// reads the request body as json, an empty body yields the default value
+async fn read_json<T: serde::de::DeserializeOwned + Default>(
+    req: Request<Body>,
+) -> Result<T, Response<Body>> {
+    let bytes = body::to_bytes(req.into_body()).await.map_err(|e| {
+        json_response(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() }))
+    })?;
+    if bytes.is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_slice::<T>(&bytes)
+        .map_err(|e| json_response(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() })))
+}
+
+pub fn json_response(status: StatusCode, value: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(value.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
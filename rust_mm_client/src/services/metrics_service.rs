@@ -0,0 +1,119 @@
+use {
+    hyper::{
+        header::CONTENT_TYPE,
+        service::{make_service_fn, service_fn},
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    log::{info, warn},
+    prometheus::{Encoder, TextEncoder},
+    serde::{Deserialize, Serialize},
+    std::{convert::Infallible, net::SocketAddr, sync::Arc},
+    tokio::sync::{
+        broadcast::{channel, Receiver},
+        Mutex,
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsConfig {
+    /// the address the `/metrics` endpoint is served on, e.g. `127.0.0.1:9090`
+    pub bind_address: String,
+}
+
+/// Serves the metrics registered by every component in the prometheus text format.
+pub struct MetricsService {
+    bind_address: String,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl MetricsService {
+    pub fn default() -> Self {
+        Self {
+            bind_address: "127.0.0.1:9090".to_string(),
+            shutdown_receiver: Mutex::new(channel::<bool>(1).1),
+        }
+    }
+
+    pub fn new(config: &MetricsConfig, shutdown_receiver: Receiver<bool>) -> Self {
+        Self {
+            bind_address: config.bind_address.to_string(),
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        let addr: SocketAddr = match self.bind_address.parse() {
+            Ok(a) => a,
+            Err(e) => {
+                warn!(
+                    "[METRICS] Invalid bind address {}: {}",
+                    self.bind_address,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        let make_svc =
+            make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(serve_metrics)) });
+
+        let server = match Server::try_bind(&addr) {
+            Ok(b) => b.serve(make_svc),
+            Err(e) => {
+                warn!(
+                    "[METRICS] Failed to bind metrics endpoint to {}: {}",
+                    addr,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        info!("[METRICS] Serving metrics on http://{}/metrics", addr);
+
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let graceful = server.with_graceful_shutdown(async {
+            let _ = shutdown.recv().await;
+        });
+
+        match graceful.await {
+            Ok(_) => {
+                info!("[METRICS] Received shutdown signal, stopping.");
+            }
+            Err(e) => {
+                warn!(
+                    "[METRICS] There was an error serving metrics: {}",
+                    e.to_string()
+                );
+            }
+        }
+    }
+}
+
+async fn serve_metrics(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut not_found = Response::new(Body::empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(not_found);
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(_) => (),
+        Err(e) => {
+            warn!("[METRICS] Failed to encode metrics: {}", e.to_string());
+            let mut error = Response::new(Body::empty());
+            *error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return Ok(error);
+        }
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        encoder.format_type().parse().unwrap(),
+    );
+    Ok(response)
+}
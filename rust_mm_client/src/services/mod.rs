@@ -1,5 +1,13 @@
 pub mod account_info_service;
 pub mod chain_meta_service;
+pub mod config_watcher_service;
+pub mod control_service;
+pub mod metrics_service;
+pub mod signer_service;
 
 pub use account_info_service::*;
 pub use chain_meta_service::*;
+pub use config_watcher_service::*;
+pub use control_service::*;
+pub use metrics_service::*;
+pub use signer_service::*;
@@ -0,0 +1,168 @@
+use {
+    super::{is_authorized, json_response},
+    crate::signer::{PubkeyResponse, SignRequest, SignResponse, TxSigner},
+    hyper::{
+        body,
+        service::{make_service_fn, service_fn},
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    log::{info, warn},
+    serde_json::json,
+    solana_sdk::{message::Message, program_utils::limited_deserialize},
+    std::{convert::Infallible, net::SocketAddr, sync::Arc},
+    tokio::sync::{broadcast::Receiver, Mutex},
+};
+
+/// Serves the remote signer protocol for a key held by this process, so the key can be kept
+/// on a separate host from the market maker.
+pub struct SignerService {
+    bind_address: String,
+    auth_token: String,
+    signer: Arc<dyn TxSigner>,
+    shutdown_receiver: Mutex<Receiver<bool>>,
+}
+
+impl SignerService {
+    pub fn new(
+        bind_address: &str,
+        auth_token: String,
+        signer: Arc<dyn TxSigner>,
+        shutdown_receiver: Receiver<bool>,
+    ) -> Self {
+        Self {
+            bind_address: bind_address.to_string(),
+            auth_token,
+            signer,
+            shutdown_receiver: Mutex::new(shutdown_receiver),
+        }
+    }
+
+    pub async fn start_service(self: &Arc<Self>) {
+        let addr: SocketAddr = match self.bind_address.parse() {
+            Ok(a) => a,
+            Err(e) => {
+                warn!(
+                    "[SIGNER] Invalid bind address {}: {}",
+                    self.bind_address,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        let aself = Arc::clone(self);
+        let make_svc = make_service_fn(move |_conn| {
+            let service = Arc::clone(&aself);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let service = Arc::clone(&service);
+                    async move { service.handle(req).await }
+                }))
+            }
+        });
+
+        let server = match Server::try_bind(&addr) {
+            Ok(b) => b.serve(make_svc),
+            Err(e) => {
+                warn!(
+                    "[SIGNER] Failed to bind signer to {}: {}",
+                    addr,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+
+        info!(
+            "[SIGNER] Signing for {} on http://{}",
+            self.signer.pubkey(),
+            addr
+        );
+
+        let mut shutdown = self.shutdown_receiver.lock().await;
+        let graceful = server.with_graceful_shutdown(async {
+            let _ = shutdown.recv().await;
+        });
+
+        match graceful.await {
+            Ok(_) => {
+                info!("[SIGNER] Received shutdown signal, stopping.");
+            }
+            Err(e) => {
+                warn!(
+                    "[SIGNER] There was an error serving the signer: {}",
+                    e.to_string()
+                );
+            }
+        }
+    }
+
+    async fn handle(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if !is_authorized(&req, &self.auth_token) {
+            return Ok(json_response(
+                StatusCode::UNAUTHORIZED,
+                json!({ "error": "unauthorized" }),
+            ));
+        }
+
+        let response = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/pubkey") => json_response(
+                StatusCode::OK,
+                json!(PubkeyResponse {
+                    pubkey: self.signer.pubkey().to_string()
+                }),
+            ),
+            (&Method::POST, "/sign") => self.sign(req).await,
+            _ => json_response(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+        };
+        Ok(response)
+    }
+
+    async fn sign(&self, req: Request<Body>) -> Response<Body> {
+        let bad_request = |e: String| json_response(StatusCode::BAD_REQUEST, json!({ "error": e }));
+
+        let bytes = match body::to_bytes(req.into_body()).await {
+            Ok(b) => b,
+            Err(e) => return bad_request(e.to_string()),
+        };
+        let request = match serde_json::from_slice::<SignRequest>(&bytes) {
+            Ok(r) => r,
+            Err(e) => return bad_request(e.to_string()),
+        };
+        if request.pubkey != self.signer.pubkey().to_string() {
+            return bad_request(format!("not signing for {}", request.pubkey));
+        }
+        let message_data = match base64::decode(&request.message) {
+            Ok(m) => m,
+            Err(e) => return bad_request(e.to_string()),
+        };
+
+        // only sign transactions this key pays for, never arbitrary bytes
+        let message = match limited_deserialize::<Message>(&message_data) {
+            Ok(m) => m,
+            Err(_) => return bad_request("not a transaction message".to_string()),
+        };
+        if message.account_keys.first() != Some(&self.signer.pubkey()) {
+            return bad_request("the signer must be the fee payer".to_string());
+        }
+
+        match self.signer.sign_message(&message_data).await {
+            Ok(signature) => {
+                info!(
+                    "[SIGNER] Signed transaction with {} instructions.",
+                    message.instructions.len()
+                );
+                json_response(
+                    StatusCode::OK,
+                    json!(SignResponse {
+                        signature: signature.to_string()
+                    }),
+                )
+            }
+            Err(e) => json_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({ "error": e.to_string() }),
+            ),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use {
+    super::SEED_PASSPHRASE_ENV,
+    crate::error::KeyError,
+    log::warn,
+    solana_sdk::{
+        derivation_path::DerivationPath,
+        signature::Keypair,
+        signer::keypair::{
+            generate_seed_from_seed_phrase_and_passphrase, keypair_from_seed_and_derivation_path,
+            keypair_from_seed_phrase_and_passphrase,
+        },
+    },
+    std::{env, fs},
+};
+
+/// wallets prefixed with this are read from the named environment variable instead of a file
+pub const ENV_WALLET_PREFIX: &str = "env:";
+
+/// Loads a keypair from a file, or from an environment variable if the wallet is `env:<NAME>`.
+///
+/// The key can be a Solana CLI json byte array, a base58 encoded secret key or a seed phrase,
+/// which is derived with `derivation_path` if one is given, like `solana-keygen recover` does.
+pub fn load_keypair(wallet: &str, derivation_path: Option<&str>) -> Result<Keypair, KeyError> {
+    let contents = match wallet.strip_prefix(ENV_WALLET_PREFIX) {
+        Some(name) => env::var(name).map_err(|e| {
+            warn!(
+                "Failed to read keypair from environment variable {}: {}",
+                name,
+                e.to_string()
+            );
+            KeyError::EnvVar
+        })?,
+        None => fs::read_to_string(wallet).map_err(|e| {
+            warn!("Failed to read keypair file {}: {}", wallet, e.to_string());
+            match e.kind() {
+                std::io::ErrorKind::InvalidData => KeyError::FileRead,
+                _ => KeyError::FileOpen,
+            }
+        })?,
+    };
+
+    parse_keypair(&contents, derivation_path)
+}
+
+pub fn parse_keypair(contents: &str, derivation_path: Option<&str>) -> Result<Keypair, KeyError> {
+    let contents = contents.trim();
+    let words = contents.split_whitespace().count();
+
+    if words == 0 {
+        warn!("The keypair is empty.");
+        return Err(KeyError::InvalidFormat);
+    }
+
+    if derivation_path.is_some() && (contents.starts_with('[') || words == 1) {
+        warn!("A derivation path can only be used with a seed phrase.");
+        return Err(KeyError::InvalidDerivationPath);
+    }
+
+    let bytes = if contents.starts_with('[') {
+        serde_json::from_str::<Vec<u8>>(contents).map_err(|e| {
+            warn!("Failed to parse keypair json: {}", e.to_string());
+            KeyError::InvalidFormat
+        })?
+    } else if words == 1 {
+        bs58::decode(contents).into_vec().map_err(|e| {
+            warn!("Failed to decode base58 keypair: {}", e.to_string());
+            KeyError::InvalidFormat
+        })?
+    } else {
+        return keypair_from_seed_phrase(contents, derivation_path);
+    };
+
+    Keypair::from_bytes(&bytes).map_err(|e| {
+        warn!("Failed to load keypair from bytes: {}", e.to_string());
+        KeyError::InvalidBytes
+    })
+}
+
+fn keypair_from_seed_phrase(
+    seed_phrase: &str,
+    derivation_path: Option<&str>,
+) -> Result<Keypair, KeyError> {
+    let seed_phrase = seed_phrase
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    // any phrase yields a seed, so a typo would silently load a different key
+    if let Err(e) = bip39::Mnemonic::validate(&seed_phrase, bip39::Language::English) {
+        warn!("Invalid seed phrase: {}", e.to_string());
+        return Err(KeyError::InvalidSeedPhrase);
+    }
+    let passphrase = env::var(SEED_PASSPHRASE_ENV).unwrap_or_default();
+
+    let keypair = match derivation_path {
+        Some(path) => {
+            let derivation_path = DerivationPath::from_absolute_path_str(path).map_err(|e| {
+                warn!("Invalid derivation path {}: {}", path, e.to_string());
+                KeyError::InvalidDerivationPath
+            })?;
+            let seed = generate_seed_from_seed_phrase_and_passphrase(&seed_phrase, &passphrase);
+            keypair_from_seed_and_derivation_path(&seed, Some(derivation_path))
+        }
+        None => keypair_from_seed_phrase_and_passphrase(&seed_phrase, &passphrase),
+    };
+
+    keypair.map_err(|e| {
+        warn!(
+            "Failed to derive keypair from seed phrase: {}",
+            e.to_string()
+        );
+        KeyError::InvalidBytes
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::signer::Signer};
+
+    const SEED_PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn pretty_json(keypair: &Keypair) -> String {
+        serde_json::to_string_pretty(&keypair.to_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn parses_pretty_printed_json() {
+        let keypair = Keypair::new();
+        let contents = pretty_json(&keypair);
+        assert!(contents.contains('\n'));
+
+        let parsed = parse_keypair(&contents, None).unwrap();
+        assert_eq!(parsed.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn parses_json_with_surrounding_whitespace() {
+        let keypair = Keypair::new();
+        let contents = format!(
+            "\n  {}  \r\n\n",
+            serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap()
+        );
+
+        let parsed = parse_keypair(&contents, None).unwrap();
+        assert_eq!(parsed.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn parses_base58() {
+        let keypair = Keypair::new();
+        let contents = format!("{}\n", keypair.to_base58_string());
+
+        let parsed = parse_keypair(&contents, None).unwrap();
+        assert_eq!(parsed.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn parses_seed_phrase_across_lines() {
+        let expected = keypair_from_seed_phrase_and_passphrase(SEED_PHRASE, "").unwrap();
+        let contents = SEED_PHRASE.replacen(' ', "\n  ", 3) + "\n";
+
+        let parsed = parse_keypair(&contents, None).unwrap();
+        assert_eq!(parsed.pubkey(), expected.pubkey());
+    }
+
+    #[test]
+    fn derives_seed_phrase_with_derivation_path() {
+        let path = "m/44'/501'/0'/0'";
+        let seed = generate_seed_from_seed_phrase_and_passphrase(SEED_PHRASE, "");
+        let expected = keypair_from_seed_and_derivation_path(
+            &seed,
+            Some(DerivationPath::from_absolute_path_str(path).unwrap()),
+        )
+        .unwrap();
+
+        let parsed = parse_keypair(SEED_PHRASE, Some(path)).unwrap();
+        assert_eq!(parsed.pubkey(), expected.pubkey());
+    }
+
+    #[test]
+    fn loads_from_environment() {
+        let keypair = Keypair::new();
+        let name = "MM_KEYPAIR_LOADER_TEST_KEYPAIR";
+        env::set_var(name, pretty_json(&keypair));
+
+        let loaded = load_keypair(&format!("{}{}", ENV_WALLET_PREFIX, name), None).unwrap();
+        assert_eq!(loaded.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn rejects_missing_environment_variable() {
+        let wallet = format!("{}MM_KEYPAIR_LOADER_TEST_UNSET", ENV_WALLET_PREFIX);
+        assert!(matches!(load_keypair(&wallet, None), Err(KeyError::EnvVar)));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(matches!(
+            parse_keypair("[1, 2, 3", None),
+            Err(KeyError::InvalidFormat)
+        ));
+        assert!(matches!(
+            parse_keypair("not-base58-0OIl", None),
+            Err(KeyError::InvalidFormat)
+        ));
+        assert!(matches!(
+            parse_keypair("[1, 2, 3]", None),
+            Err(KeyError::InvalidBytes)
+        ));
+        assert!(matches!(
+            parse_keypair("abandon abandon abandon", None),
+            Err(KeyError::InvalidSeedPhrase)
+        ));
+        assert!(matches!(
+            parse_keypair(" \n\t", None),
+            Err(KeyError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn rejects_derivation_path_without_seed_phrase() {
+        let contents = pretty_json(&Keypair::new());
+        assert!(matches!(
+            parse_keypair(&contents, Some("m/44'/501'/0'/0'")),
+            Err(KeyError::InvalidDerivationPath)
+        ));
+    }
+}
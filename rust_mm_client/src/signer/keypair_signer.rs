@@ -0,0 +1,31 @@
+use {
+    super::TxSigner,
+    async_trait::async_trait,
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Keypair, Signature},
+        signer::{Signer, SignerError},
+    },
+};
+
+/// Signs with a keypair held in memory, loaded from a plaintext file or an encrypted keystore.
+pub struct KeypairSigner {
+    keypair: Keypair,
+}
+
+impl KeypairSigner {
+    pub fn new(keypair: Keypair) -> Self {
+        Self { keypair }
+    }
+}
+
+#[async_trait]
+impl TxSigner for KeypairSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.keypair.try_sign_message(message)
+    }
+}
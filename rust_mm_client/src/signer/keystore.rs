@@ -0,0 +1,139 @@
+use {
+    crate::error::KeyError,
+    aes_gcm_siv::{
+        aead::{Aead, NewAead},
+        Aes256GcmSiv, Key, Nonce,
+    },
+    hmac::Hmac,
+    log::warn,
+    rand::{rngs::OsRng, RngCore},
+    serde::{Deserialize, Serialize},
+    sha2::Sha256,
+    solana_sdk::{signature::Keypair, signer::Signer},
+    std::fs,
+};
+
+const KEYSTORE_VERSION: u8 = 1;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+// keystores derived with fewer iterations than this are too cheap to brute force and refused
+const MIN_PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// A keypair encrypted with a key derived from a passphrase, so it never sits in plaintext on disk.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keystore {
+    pub version: u8,
+    /// the public key of the encrypted keypair, so it can be identified without the passphrase
+    pub pubkey: String,
+    /// pbkdf2-hmac-sha256 iterations used to derive the encryption key
+    pub iterations: u32,
+    /// base64 encoded
+    pub salt: String,
+    /// base64 encoded
+    pub nonce: String,
+    /// the aes-256-gcm-siv encrypted keypair bytes, base64 encoded
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self, KeyError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let cipher = cipher(passphrase, &salt, PBKDF2_ITERATIONS);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), keypair.to_bytes().as_ref())
+            .map_err(|_| KeyError::Keystore)?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            pubkey: keypair.pubkey().to_string(),
+            iterations: PBKDF2_ITERATIONS,
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keypair, KeyError> {
+        if self.version != KEYSTORE_VERSION {
+            warn!("Unsupported keystore version: {}", self.version);
+            return Err(KeyError::Keystore);
+        }
+        if self.iterations < MIN_PBKDF2_ITERATIONS {
+            warn!(
+                "The keystore uses {} key derivation iterations, at least {} are required.",
+                self.iterations, MIN_PBKDF2_ITERATIONS
+            );
+            return Err(KeyError::Keystore);
+        }
+
+        let decode = |field: &str, value: &str| {
+            base64::decode(value).map_err(|e| {
+                warn!("Invalid keystore {}: {}", field, e.to_string());
+                KeyError::Keystore
+            })
+        };
+        let salt = decode("salt", &self.salt)?;
+        let nonce = decode("nonce", &self.nonce)?;
+        let ciphertext = decode("ciphertext", &self.ciphertext)?;
+        if nonce.len() != NONCE_LEN {
+            warn!("Invalid keystore nonce length: {}", nonce.len());
+            return Err(KeyError::Keystore);
+        }
+
+        let cipher = cipher(passphrase, &salt, self.iterations);
+        let bytes = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                warn!(
+                    "Failed to decrypt keystore, the passphrase is wrong or the file is corrupted."
+                );
+                KeyError::Keystore
+            })?;
+
+        let keypair = Keypair::from_bytes(&bytes).map_err(|e| {
+            warn!("Failed to load keypair from keystore: {}", e.to_string());
+            KeyError::Keystore
+        })?;
+
+        if keypair.pubkey().to_string() != self.pubkey {
+            warn!(
+                "The keystore decrypted to {} but claims to hold {}.",
+                keypair.pubkey(),
+                self.pubkey
+            );
+            return Err(KeyError::Keystore);
+        }
+        Ok(keypair)
+    }
+
+    pub fn load(path: &str) -> Result<Self, KeyError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            warn!("Failed to read keystore {}: {}", path, e.to_string());
+            KeyError::Keystore
+        })?;
+        serde_json::from_str(&contents).map_err(|e| {
+            warn!("Failed to parse keystore {}: {}", path, e.to_string());
+            KeyError::Keystore
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), KeyError> {
+        let contents = serde_json::to_string_pretty(self).unwrap();
+        fs::write(path, contents).map_err(|e| {
+            warn!("Failed to write keystore {}: {}", path, e.to_string());
+            KeyError::Keystore
+        })
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8], iterations: u32) -> Aes256GcmSiv {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, iterations, &mut key);
+    Aes256GcmSiv::new(Key::from_slice(&key))
+}
@@ -0,0 +1,146 @@
+pub mod keypair_loader;
+pub mod keypair_signer;
+pub mod keystore;
+pub mod remote_signer;
+
+pub use keypair_loader::*;
+pub use keypair_signer::*;
+pub use keystore::*;
+pub use remote_signer::*;
+
+use {
+    crate::{error::KeyError, MarketMakerError},
+    async_trait::async_trait,
+    log::{info, warn},
+    serde::{Deserialize, Serialize},
+    solana_sdk::{
+        hash::Hash, pubkey::Pubkey, signature::Signature, signer::SignerError,
+        transaction::Transaction,
+    },
+    std::{env, str::FromStr, sync::Arc},
+};
+
+/// the environment variable the keystore passphrase is read from, prompted for if unset
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "MM_KEYSTORE_PASSPHRASE";
+/// the environment variable which takes precedence over the auth token of the remote signer
+pub const SIGNER_TOKEN_ENV: &str = "MM_SIGNER_TOKEN";
+/// the environment variable the optional bip39 passphrase of a seed phrase wallet is read from
+pub const SEED_PASSPHRASE_ENV: &str = "MM_SEED_PASSPHRASE";
+
+/// Signs transactions on behalf of a single account, which may live outside of this process.
+#[async_trait]
+pub trait TxSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError>;
+
+    /// sets the blockhash and adds this signer's signature to the transaction
+    async fn sign_transaction(
+        &self,
+        tx: &mut Transaction,
+        recent_blockhash: Hash,
+    ) -> Result<(), SignerError> {
+        if tx.message.recent_blockhash != recent_blockhash {
+            tx.message.recent_blockhash = recent_blockhash;
+            tx.signatures
+                .iter_mut()
+                .for_each(|s| *s = Signature::default());
+        }
+
+        let position = tx
+            .get_signing_keypair_positions(&[self.pubkey()])
+            .map_err(|e| SignerError::Custom(e.to_string()))?
+            .first()
+            .copied()
+            .flatten()
+            .ok_or(SignerError::KeypairPubkeyMismatch)?;
+
+        let signature = self.sign_message(&tx.message_data()).await?;
+        tx.signatures[position] = signature;
+        Ok(())
+    }
+}
+
+/// Where the key which signs the market maker's transactions is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum SignerConfig {
+    /// the plaintext key at `wallet`, a file or `env:<NAME>` to read it from the environment
+    #[serde(rename_all = "camelCase")]
+    File {
+        /// derives the key from a seed phrase wallet with this path, e.g. `m/44'/501'/0'/0'`
+        #[serde(default)]
+        derivation_path: Option<String>,
+    },
+    /// a keypair file encrypted with `encrypt-keypair`, unlocked with a passphrase
+    #[serde(rename_all = "camelCase")]
+    Keystore { path: String },
+    /// a signing service which holds the key, see `signer-server` for the protocol
+    #[serde(rename_all = "camelCase")]
+    Remote {
+        url: String,
+        /// the account the service signs for
+        pubkey: String,
+        /// the bearer token sent to the service, can be omitted if it is set in `MM_SIGNER_TOKEN`
+        #[serde(default, skip_serializing)]
+        auth_token: Option<String>,
+    },
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        Self::File {
+            derivation_path: None,
+        }
+    }
+}
+
+/// reads the passphrase from the environment, or prompts for it if there is a terminal
+pub fn read_passphrase(prompt: &str) -> Result<String, KeyError> {
+    if let Ok(passphrase) = env::var(KEYSTORE_PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    rpassword::prompt_password(prompt).map_err(|e| {
+        warn!(
+            "Failed to read the keystore passphrase, set {} when running without a terminal: {}",
+            KEYSTORE_PASSPHRASE_ENV,
+            e.to_string()
+        );
+        KeyError::Keystore
+    })
+}
+
+/// loads the signer described by the config, the file signer reads the key at `wallet`
+pub async fn load_signer(
+    config: &SignerConfig,
+    wallet: &str,
+) -> Result<Arc<dyn TxSigner>, MarketMakerError> {
+    match config {
+        SignerConfig::File { derivation_path } => {
+            let keypair = load_keypair(wallet, derivation_path.as_deref())?;
+            Ok(Arc::new(KeypairSigner::new(keypair)))
+        }
+        SignerConfig::Keystore { path } => {
+            let keystore = Keystore::load(path)?;
+            let passphrase = read_passphrase(&format!("Passphrase for keystore {}: ", path))?;
+            let keypair = keystore.decrypt(&passphrase)?;
+            info!("Unlocked keystore {}.", path);
+            Ok(Arc::new(KeypairSigner::new(keypair)))
+        }
+        SignerConfig::Remote {
+            url,
+            pubkey,
+            auth_token,
+        } => {
+            let pubkey = Pubkey::from_str(pubkey).map_err(|_| KeyError::Signer)?;
+            let auth_token = match env::var(SIGNER_TOKEN_ENV) {
+                Ok(t) if !t.is_empty() => Some(t),
+                _ => auth_token.clone(),
+            };
+            let signer = RemoteSigner::new(url, pubkey, auth_token);
+            signer.verify().await?;
+            info!("Using remote signer at {} for {}.", url, pubkey);
+            Ok(Arc::new(signer))
+        }
+    }
+}
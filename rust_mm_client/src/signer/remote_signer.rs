@@ -0,0 +1,129 @@
+use {
+    super::TxSigner,
+    crate::error::KeyError,
+    async_trait::async_trait,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    solana_sdk::{pubkey::Pubkey, signature::Signature, signer::SignerError},
+    std::{str::FromStr, time::Duration},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PubkeyResponse {
+    pub pubkey: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignRequest {
+    /// the account the message should be signed by
+    pub pubkey: String,
+    /// the serialized transaction message, base64 encoded
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignResponse {
+    /// base58 encoded
+    pub signature: String,
+}
+
+/// Asks a signing service over http to sign, so the key never has to be on the trading host.
+///
+/// The service answers `GET /pubkey` with the account it signs for and `POST /sign` with the
+/// signature of the given message, both authenticated with a bearer token.
+pub struct RemoteSigner {
+    url: String,
+    pubkey: Pubkey,
+    auth_token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, pubkey: Pubkey, auth_token: Option<String>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            pubkey,
+            auth_token,
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap(),
+        }
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.auth_token {
+            Some(t) => builder.bearer_auth(t),
+            None => builder,
+        }
+    }
+
+    /// checks the service is reachable and signs for the configured account
+    pub async fn verify(&self) -> Result<(), KeyError> {
+        let response = self
+            .request(self.client.get(format!("{}/pubkey", self.url)))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                warn!(
+                    "Failed to reach the remote signer at {}: {}",
+                    self.url,
+                    e.to_string()
+                );
+                KeyError::Signer
+            })?;
+
+        let pubkey = response.json::<PubkeyResponse>().await.map_err(|e| {
+            warn!("Invalid response from the remote signer: {}", e.to_string());
+            KeyError::Signer
+        })?;
+
+        if pubkey.pubkey != self.pubkey.to_string() {
+            warn!(
+                "The remote signer signs for {} but {} is configured.",
+                pubkey.pubkey, self.pubkey
+            );
+            return Err(KeyError::Signer);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TxSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let request = SignRequest {
+            pubkey: self.pubkey.to_string(),
+            message: base64::encode(message),
+        };
+
+        let response = self
+            .request(self.client.post(format!("{}/sign", self.url)))
+            .json(&request)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| SignerError::Connection(e.to_string()))?
+            .json::<SignResponse>()
+            .await
+            .map_err(|e| SignerError::Protocol(e.to_string()))?;
+
+        let signature = Signature::from_str(&response.signature)
+            .map_err(|e| SignerError::Protocol(e.to_string()))?;
+
+        // never put a signature we cannot vouch for into a transaction
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Protocol(
+                "the remote signer returned an invalid signature".to_string(),
+            ));
+        }
+        Ok(signature)
+    }
+}
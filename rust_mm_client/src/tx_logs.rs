@@ -0,0 +1,189 @@
+use {
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        rpc_request::{RpcError, RpcResponseErrorData},
+    },
+    solana_sdk::pubkey::Pubkey,
+    std::str::FromStr,
+};
+
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// The logs of a failed transaction, from its preflight simulation or once it was confirmed,
+/// with the error reported by the failing program picked out of them.
+#[derive(Debug, Clone, Default)]
+pub struct TxLogs {
+    pub logs: Vec<String>,
+    /// the program which returned the error, when a cpi fails the program called last fails first
+    pub failed_program: Option<Pubkey>,
+    /// the name of the error, for programs which log it like anchor does
+    pub error_name: Option<String>,
+    pub error_message: Option<String>,
+}
+
+impl TxLogs {
+    pub fn parse(logs: Vec<String>) -> Self {
+        let mut tx_logs = Self::default();
+
+        for log in logs.iter() {
+            if tx_logs.failed_program.is_none() {
+                tx_logs.failed_program = parse_failed_program(log);
+            }
+
+            let message = match log.strip_prefix(PROGRAM_LOG_PREFIX) {
+                Some(m) => m,
+                None => continue,
+            };
+            // e.g. `AnchorError occurred. Error Code: NotEnoughMargin. Error Number: 6012. Error Message: ...`
+            if message.starts_with("AnchorError") {
+                tx_logs.error_name = field(message, "Error Code: ");
+                tx_logs.error_message = field(message, "Error Message: ");
+            } else if let Some(m) = message.strip_prefix("Error: ") {
+                tx_logs.error_message = Some(m.trim_end_matches('.').to_string());
+            }
+        }
+
+        tx_logs.logs = logs;
+        tx_logs
+    }
+
+    /// the logs of the preflight simulation which rejected the transaction, if that is what failed
+    pub fn from_client_error(e: &ClientError) -> Self {
+        match e.kind() {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError {
+                data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+                ..
+            }) => Self::parse(result.logs.clone().unwrap_or_default()),
+            _ => Self::default(),
+        }
+    }
+
+    /// the error the failing program reported, as `<name>: <message>` when both are known
+    pub fn error(&self) -> Option<String> {
+        match (&self.error_name, &self.error_message) {
+            (Some(name), Some(message)) => Some(format!("{}: {}", name, message)),
+            (Some(name), None) => Some(name.clone()),
+            (None, Some(message)) => Some(message.clone()),
+            (None, None) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty()
+    }
+
+    /// whether the reported error or any program log mentions the given words, ignoring case,
+    /// spaces and punctuation so `PostOnly`, `post only` and `post-only` all match `postonly`
+    pub fn mentions(&self, words: &str) -> bool {
+        let words = normalize(words);
+        self.error_name
+            .iter()
+            .chain(self.error_message.iter())
+            .map(|s| s.as_str())
+            .chain(
+                self.logs
+                    .iter()
+                    .filter_map(|l| l.strip_prefix(PROGRAM_LOG_PREFIX)),
+            )
+            .any(|s| normalize(s).contains(&words))
+    }
+}
+
+/// parses lines like `Program <id> failed: custom program error: 0x22`
+fn parse_failed_program(log: &str) -> Option<Pubkey> {
+    let (program, rest) = log.strip_prefix("Program ")?.split_once(' ')?;
+    if !rest.starts_with("failed") {
+        return None;
+    }
+    Pubkey::from_str(program).ok()
+}
+
+fn field(message: &str, key: &str) -> Option<String> {
+    let start = message.find(key)? + key.len();
+    let value = message[start..].split(". ").next()?;
+    Some(value.trim_end_matches('.').to_string())
+}
+
+fn normalize(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_anchor_error_out_of_the_logs() {
+        let cypher = cypher::ID.to_string();
+        let logs = TxLogs::parse(vec![
+            format!("Program {} invoke [1]", cypher),
+            "Program log: Instruction: NewOrder".to_string(),
+            "Program log: AnchorError occurred. Error Code: NotEnoughMargin. Error Number: 6012. Error Message: Not enough margin.".to_string(),
+            format!("Program {} consumed 24512 of 200000 compute units", cypher),
+            format!("Program {} failed: custom program error: 0x177c", cypher),
+        ]);
+
+        assert_eq!(logs.failed_program, Some(cypher::ID));
+        assert_eq!(logs.error_name.as_deref(), Some("NotEnoughMargin"));
+        assert_eq!(logs.error_message.as_deref(), Some("Not enough margin"));
+        assert_eq!(
+            logs.error().as_deref(),
+            Some("NotEnoughMargin: Not enough margin")
+        );
+        assert!(logs.mentions("margin"));
+        assert!(!logs.mentions("post only"));
+    }
+
+    #[test]
+    fn the_program_called_last_fails_first() {
+        let cypher = cypher::ID.to_string();
+        let dex = anchor_spl::dex::ID.to_string();
+        let logs = TxLogs::parse(vec![
+            format!("Program {} invoke [1]", cypher),
+            "Program log: Instruction: NewOrder".to_string(),
+            format!("Program {} invoke [2]", dex),
+            format!("Program {} consumed 9870 of 174062 compute units", dex),
+            format!("Program {} failed: custom program error: 0x22", dex),
+            format!("Program {} consumed 35808 of 200000 compute units", cypher),
+            format!("Program {} failed: custom program error: 0x22", cypher),
+        ]);
+
+        assert_eq!(logs.failed_program, Some(anchor_spl::dex::ID));
+        assert_eq!(logs.error(), None);
+    }
+
+    #[test]
+    fn parses_the_failed_program_from_a_log_line() {
+        let program = Pubkey::new_unique();
+        assert_eq!(
+            parse_failed_program(&format!(
+                "Program {} failed: custom program error: 0x22",
+                program
+            )),
+            Some(program)
+        );
+        assert_eq!(
+            parse_failed_program(&format!("Program {} invoke [1]", program)),
+            None
+        );
+        assert_eq!(
+            parse_failed_program(&format!(
+                "Program {} consumed 5000 of 200000 compute units",
+                program
+            )),
+            None
+        );
+        assert_eq!(parse_failed_program("Program log: Error: failed"), None);
+    }
+
+    #[test]
+    fn logs_without_a_failure_are_empty() {
+        let logs = TxLogs::parse(Vec::new());
+        assert!(logs.is_empty());
+        assert_eq!(logs.failed_program, None);
+        assert_eq!(logs.error(), None);
+    }
+}
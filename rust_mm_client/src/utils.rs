@@ -1,8 +1,11 @@
 use {
-    crate::fast_tx_builder::FastTxnBuilder,
+    crate::{fast_tx_builder::FastTxnBuilder, signer::TxSigner},
     anchor_spl::{associated_token, token::spl_token},
     cypher::{
-        client::{deposit_collateral_ix, init_cypher_user_ix, init_open_orders_ix},
+        client::{
+            close_open_orders_ix, deposit_collateral_ix, init_cypher_user_ix,
+            init_open_orders_ix, set_delegate_ix, withdraw_collateral_ix,
+        },
         quote_mint,
         utils::{derive_cypher_user_address, derive_dex_market_authority},
     },
@@ -10,9 +13,11 @@ use {
     solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient},
     solana_sdk::{
         commitment_config::CommitmentConfig, instruction::Instruction, pubkey::Pubkey,
-        signature::Keypair, signer::Signer,
     },
-    std::sync::Arc,
+    std::{
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
 };
 
 pub fn derive_quote_token_address(wallet_address: Pubkey) -> Pubkey {
@@ -27,6 +32,14 @@ pub fn derive_quote_token_address(wallet_address: Pubkey) -> Pubkey {
     .0
 }
 
+/// the current unix timestamp in seconds, as compared against the `max_ts` of orders
+pub fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
 pub async fn get_token_account(
     client: Arc<RpcClient>,
     token_account: &Pubkey,
@@ -47,7 +60,7 @@ pub async fn get_token_account(
 
 pub async fn init_cypher_user(
     group_address: &Pubkey,
-    owner: &Keypair,
+    owner: &dyn TxSigner,
     rpc: &Arc<RpcClient>,
 ) -> Result<(), ClientError> {
     let (address, bump) = derive_cypher_user_address(group_address, &owner.pubkey());
@@ -55,10 +68,8 @@ pub async fn init_cypher_user(
     let mut builder = FastTxnBuilder::new();
     builder.add(ix);
     let hash = rpc.get_latest_blockhash().await?;
-    let tx = builder.build(hash, owner, None);
-    rpc.send_and_confirm_transaction_with_spinner(&tx)
-        .await
-        .unwrap();
+    let tx = builder.build(hash, owner, None).await?;
+    rpc.send_and_confirm_transaction_with_spinner(&tx).await?;
     Ok(())
 }
 
@@ -80,6 +91,26 @@ pub fn get_deposit_collateral_ix(
     )
 }
 
+pub fn get_withdraw_collateral_ix(
+    cypher_group_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    cypher_pc_vault: &Pubkey,
+    vault_signer: &Pubkey,
+    destination_token_account: &Pubkey,
+    signer: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    withdraw_collateral_ix(
+        cypher_group_pubkey,
+        cypher_user_pubkey,
+        signer,
+        cypher_pc_vault,
+        vault_signer,
+        destination_token_account,
+        amount,
+    )
+}
+
 pub fn get_init_open_orders_ix(
     cypher_group_pubkey: &Pubkey,
     cypher_user_pubkey: &Pubkey,
@@ -98,3 +129,32 @@ pub fn get_init_open_orders_ix(
         &market_authority,
     )
 }
+
+pub fn get_close_open_orders_ix(
+    cypher_group_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    cypher_market: &Pubkey,
+    open_orders: &Pubkey,
+    signer: &Pubkey,
+) -> Instruction {
+    let market_authority = derive_dex_market_authority(cypher_market);
+    close_open_orders_ix(
+        cypher_group_pubkey,
+        cypher_user_pubkey,
+        signer,
+        cypher_market,
+        open_orders,
+        &market_authority,
+    )
+}
+
+/// lets `delegate` place and cancel orders for the cypher account without being able to withdraw,
+/// a default pubkey removes the delegate
+pub fn get_set_delegate_ix(
+    cypher_group_pubkey: &Pubkey,
+    cypher_user_pubkey: &Pubkey,
+    owner: &Pubkey,
+    delegate: &Pubkey,
+) -> Instruction {
+    set_delegate_ix(cypher_group_pubkey, cypher_user_pubkey, owner, delegate)
+}
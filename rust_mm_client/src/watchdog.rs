@@ -0,0 +1,42 @@
+use {
+    crate::utils::unix_timestamp,
+    serde::{Deserialize, Serialize},
+    std::{fs, io, path::Path},
+};
+
+/// The dead man's switch: the market maker writes a heartbeat to a file on every iteration of
+/// its quoting loop and the `watchdog` command, run as a separate process, cancels every order
+/// once the heartbeat is older than `timeout_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WatchdogConfig {
+    /// where the heartbeat is written, both processes must see the same file
+    pub heartbeat_file: String,
+    /// how old the heartbeat may get before the orders are cancelled
+    pub timeout_secs: u64,
+    /// how often the watchdog checks the heartbeat, and retries cancelling
+    pub poll_interval_ms: u64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_file: "mm-heartbeat".to_string(),
+            timeout_secs: 60,
+            poll_interval_ms: 1000,
+        }
+    }
+}
+
+/// writes the current unix timestamp to the heartbeat file, through a rename so the watchdog
+/// never reads a partially written file
+pub fn write_heartbeat(path: &Path) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, unix_timestamp().to_string())?;
+    fs::rename(&tmp, path)
+}
+
+/// the unix timestamp of the last heartbeat, `None` if it was never written
+pub fn read_heartbeat(path: &Path) -> Option<i64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}